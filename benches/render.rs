@@ -0,0 +1,46 @@
+use criterion::{black_box, criterion_group, criterion_main, Criterion};
+use custom_status_bar::modules::*;
+
+/// A module with fixed output, so only the rendering itself is measured.
+struct StaticModule {
+    rate: usize,
+}
+
+impl Module for StaticModule {
+    fn get_output(&mut self) -> ModuleRes {
+        Ok(
+            ModuleOutput::new("W: ( 72% at home, 2 Mb/s) 192.168.1.10".to_string())
+                .with_color_fg("#50fa7b".to_string())
+                .with_color_bg("#44475a".to_string())
+                .with_border("#000000".to_string())
+                .with_separator_block_width(12),
+        )
+    }
+
+    fn rate(&self) -> usize {
+        self.rate
+    }
+}
+
+fn bar(rate: usize) -> Modules {
+    Modules::new(
+        (0..12)
+            .map(|_| Box::new(StaticModule { rate }) as Box<dyn Module>)
+            .collect(),
+    )
+}
+
+fn render(c: &mut Criterion) {
+    let mut modules = bar(1);
+    c.bench_function("combine_modules", |b| {
+        b.iter(|| black_box(modules.combine_modules().map(|l| l.len())))
+    });
+
+    let mut cached = bar(usize::MAX);
+    c.bench_function("combine_modules_cached", |b| {
+        b.iter(|| black_box(cached.combine_modules().map(|l| l.len())))
+    });
+}
+
+criterion_group!(benches, render);
+criterion_main!(benches);
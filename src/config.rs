@@ -0,0 +1,1189 @@
+use serde::{de::DeserializeOwned, Deserialize};
+use std::{env, fs::read_to_string, path::PathBuf, sync::Arc, time::Duration};
+
+use crate::modules::*;
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Config {
+    #[serde(default)]
+    pub output: OutputConfig,
+    #[serde(default)]
+    pub theme: Theme,
+    /// Glyph overrides by name, see [`DEFAULT_GLYPHS`].
+    #[serde(default)]
+    pub glyphs: Glyphs,
+    /// Tick length in milliseconds; module rates are multiples of it.
+    pub interval_ms: Option<u64>,
+    /// Seconds after startup during which failing modules are hidden
+    /// instead of showing their error.
+    pub startup_grace_secs: Option<u64>,
+    /// Seconds without a refresh after which a block is shown in the
+    /// theme's `stale` color.
+    pub stale_after_secs: Option<u64>,
+    #[serde(rename = "module", default)]
+    pub modules: Vec<ModuleConfig>,
+}
+
+#[derive(Deserialize, Default)]
+#[serde(deny_unknown_fields)]
+pub struct OutputConfig {
+    pub format: Option<String>,
+    pub separator: Option<String>,
+    #[serde(default)]
+    pub skip_empty: bool,
+    pub empty_placeholder: Option<String>,
+    /// i3bar separator settings for blocks that don't set their own.
+    pub block_separator: Option<bool>,
+    /// Also advertised in the i3bar header; a module's own width wins.
+    pub separator_block_width: Option<usize>,
+    /// Bar width in characters, filled out by flexible spacers.
+    pub bar_width: Option<usize>,
+    /// Length in characters any block is truncated to.
+    pub max_len: Option<usize>,
+    /// Whether to print the protocol header; off to continue the stream of
+    /// a previous instance.
+    pub header: Option<bool>,
+}
+
+#[derive(Deserialize)]
+pub struct ModuleConfig {
+    #[serde(rename = "type")]
+    pub kind: String,
+    /// Key in the JSON output format, by default the type, numbered from
+    /// `_2` on when several modules share it.
+    pub id: Option<String>,
+    pub rate: Option<usize>,
+    /// Refreshes only on startup and when triggered by a signal or click.
+    #[serde(default)]
+    pub on_demand: bool,
+    pub separator: Option<bool>,
+    pub separator_block_width: Option<usize>,
+    pub min_width: Option<MinWidth>,
+    pub align: Option<Align>,
+    pub group: Option<String>,
+    #[serde(default)]
+    pub auto_short: bool,
+    /// Spaces around the block's text, as `[left, right]`.
+    pub padding: Option<(usize, usize)>,
+    /// Fixed text around the module's content, e.g. a label or unit.
+    pub prefix: Option<String>,
+    pub suffix: Option<String>,
+    /// Shows the mean of the last this many readings of the first number in
+    /// the content, smoothing out noisy values.
+    pub average: Option<usize>,
+    /// Runs a command when the module's value crosses a bound.
+    pub notify: Option<NotifyConfig>,
+    /// Colors forced over whatever the module picks.
+    pub color: Option<Color>,
+    pub background: Option<Color>,
+    #[serde(flatten)]
+    pub options: toml::Table,
+}
+
+/// A `notify = { below = 5, command = "..." }` entry, see [`NotifyOnThreshold`].
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct NotifyConfig {
+    pub above: Option<f64>,
+    pub below: Option<f64>,
+    /// Metric to watch instead of the first number in the content.
+    pub metric: Option<String>,
+    /// Run with `sh -c`, getting the value as `$VALUE`.
+    pub command: String,
+}
+
+#[cfg(target_os = "linux")]
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct BatteryOptions {
+    paths: Vec<PathBuf>,
+    display: Option<BatteryDisplay>,
+    low_threshold: Option<u64>,
+    #[serde(default)]
+    blink: bool,
+    icons: Option<Vec<char>>,
+    #[serde(default)]
+    hide_when_full_on_ac: bool,
+    /// Number of current readings averaged for the time-remaining estimate.
+    smoothing: Option<usize>,
+    /// Width of a progress bar replacing the charge glyph.
+    progress_bar: Option<usize>,
+    /// Shell command run when charging starts or stops and when the battery
+    /// runs low, see [`battery_event_command`].
+    on_state_change: Option<String>,
+}
+
+#[cfg(all(target_os = "linux", feature = "network"))]
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct NetworkOptions {
+    /// Omitted to follow the primary connection.
+    device: Option<String>,
+    name: Option<String>,
+    expanded: Option<bool>,
+    #[serde(default)]
+    signal_icon: bool,
+    #[serde(default)]
+    raw_frequency: bool,
+    /// `first`, `all`, or a subnet like `10.0.0.0/8` to prefer.
+    address: Option<String>,
+    /// Hides the block, or shows `down_text`, instead of `name: down`.
+    #[serde(default)]
+    hide_when_down: bool,
+    down_text: Option<String>,
+    /// Refreshes to highlight the block for after switching WiFi networks.
+    ssid_flash: Option<usize>,
+}
+
+#[cfg(feature = "sysinfo-modules")]
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct DiskSpaceOptions {
+    device: Option<String>,
+    /// Several devices or mount points whose free space is summed.
+    #[serde(default)]
+    devices: Vec<String>,
+    display: Option<DiskDisplay>,
+    grouping: Option<String>,
+    /// Warns about the disk being mounted read-only, off by default.
+    read_only_warning: Option<bool>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct DateTimeOptions {
+    format: Option<String>,
+    preset: Option<String>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct SpacerOptions {
+    #[serde(default)]
+    width: usize,
+    /// Fill the room left on a bar of `output.bar_width` instead.
+    #[serde(default)]
+    flexible: bool,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct PublicIpOptions {
+    endpoint: Option<String>,
+}
+
+#[cfg(all(target_os = "linux", feature = "dbus-modules"))]
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct MediaOptions {
+    max_len: Option<usize>,
+}
+
+#[cfg(target_os = "linux")]
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct UpdatesOptions {
+    backend: UpdatesBackend,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct GitOptions {
+    path: String,
+}
+
+#[cfg(feature = "sysinfo-modules")]
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ProcessCountOptions {
+    threshold: Option<usize>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct WeatherOptions {
+    latitude: f32,
+    longitude: f32,
+    endpoint: Option<String>,
+}
+
+#[cfg(target_os = "linux")]
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct WindowTitleOptions {
+    backend: Option<String>,
+    max_len: Option<usize>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct TimerOptions {
+    seconds: u64,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct PomodoroOptions {
+    work_minutes: Option<u64>,
+    break_minutes: Option<u64>,
+}
+
+#[cfg(feature = "sysinfo-modules")]
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct OsOptions {
+    field: Option<OsField>,
+}
+
+#[cfg(target_os = "linux")]
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct GpuOptions {
+    backend: GpuBackend,
+}
+
+#[cfg(target_os = "linux")]
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct MemoryPressureOptions {
+    threshold: Option<f32>,
+}
+
+#[cfg(feature = "sysinfo-modules")]
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct GradientOptions {
+    #[serde(default)]
+    gradient: bool,
+}
+
+#[cfg(feature = "sysinfo-modules")]
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct CpuOptions {
+    display: Option<CpuDisplay>,
+    #[serde(default)]
+    gradient: bool,
+    warn: Option<f32>,
+    crit: Option<f32>,
+    /// Also colors by CPU temperature; either option turns this on.
+    temp_warn: Option<f32>,
+    temp_crit: Option<f32>,
+}
+
+#[cfg(feature = "sysinfo-modules")]
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct TemperatureOptions {
+    aggregate: Option<TempAggregate>,
+    warn: Option<f32>,
+    crit: Option<f32>,
+    /// Fade between colors across the warn to crit range.
+    #[serde(default)]
+    smooth_color: bool,
+}
+
+/// Thresholds from optional `warn`/`crit` options, filling in whichever is
+/// missing from `default`; `None` if neither is set. Fails unless the
+/// result warns below its critical level.
+#[cfg(feature = "sysinfo-modules")]
+fn thresholds(
+    warn: Option<f32>,
+    crit: Option<f32>,
+    default: Thresholds,
+) -> Result<Option<Thresholds>, String> {
+    if warn.is_none() && crit.is_none() {
+        return Ok(None);
+    }
+    let (warn, crit) = (warn.unwrap_or(default.warn), crit.unwrap_or(default.crit));
+    if warn >= crit {
+        return Err(format!(
+            "warn level {} must be below the critical level {}",
+            warn, crit
+        ));
+    }
+    Ok(Some(Thresholds::new(warn, crit)))
+}
+
+/// Thousands separator from a `grouping` option, where an empty string turns
+/// grouping off.
+fn grouping(grouping: Option<String>, default: Option<char>) -> Result<Option<char>, String> {
+    let Some(grouping) = grouping else {
+        return Ok(default);
+    };
+    let mut chars = grouping.chars();
+    match (chars.next(), chars.next()) {
+        (None, _) => Ok(None),
+        (Some(c), None) => Ok(Some(c)),
+        _ => Err(format!(
+            "`grouping` must be a single character, got `{}`",
+            grouping
+        )),
+    }
+}
+
+#[cfg(feature = "sysinfo-modules")]
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RamOptions {
+    #[serde(default)]
+    gradient: bool,
+    history: Option<usize>,
+    display: Option<RamDisplay>,
+    /// Content template over `used`, `available`, `total` and `pct`.
+    format: Option<String>,
+    grouping: Option<String>,
+    warn: Option<f32>,
+    crit: Option<f32>,
+}
+
+#[cfg(target_os = "linux")]
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct VolumeOptions {
+    step: Option<u32>,
+}
+
+#[cfg(target_os = "linux")]
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct BrightnessOptions {
+    /// Backlight device name, the first one found if omitted.
+    device: Option<String>,
+    step: Option<u32>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct SunOptions {
+    latitude: f64,
+    longitude: f64,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct TickerOptions {
+    coin: String,
+    currency: Option<String>,
+    label: Option<String>,
+    endpoint: Option<String>,
+    grouping: Option<String>,
+}
+
+#[cfg(target_os = "linux")]
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ThroughputOptions {
+    device: String,
+}
+
+#[cfg(feature = "sysinfo-modules")]
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct HostnameOptions {
+    #[serde(default)]
+    ssh_only: bool,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ContainersOptions {
+    socket: Option<PathBuf>,
+}
+
+#[cfg(all(target_os = "linux", feature = "dbus-modules"))]
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct SystemdOptions {
+    #[serde(default)]
+    user: bool,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct MailOptions {
+    maildir: PathBuf,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct CalendarOptions {
+    path: PathBuf,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct TodoOptions {
+    path: Option<PathBuf>,
+    #[serde(default)]
+    taskwarrior: bool,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct DnsOptions {
+    /// Host name to look up.
+    host: Option<String>,
+    timeout_ms: Option<u64>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct FileOptions {
+    path: PathBuf,
+    /// Template where `{}` stands for the value.
+    format: Option<String>,
+    /// Parses the value as a number and divides it, e.g. by 1000.
+    divide: Option<f64>,
+    decimals: Option<usize>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct CommandOptions {
+    /// Run with `sh -c`.
+    command: String,
+    timeout_ms: Option<u64>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct CombinatorOptions {
+    /// Inner modules, configured like top-level ones.
+    #[serde(rename = "module")]
+    modules: Vec<ModuleConfig>,
+    /// Ticks each module of a `rotating` block is shown for.
+    period: Option<usize>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct NoOptions {}
+
+/// Returns `$XDG_CONFIG_HOME/custom_status_bar/config.toml`, falling back to
+/// `~/.config` when `XDG_CONFIG_HOME` is unset.
+pub fn default_path() -> Option<PathBuf> {
+    let base = env::var_os("XDG_CONFIG_HOME")
+        .map(PathBuf::from)
+        .or_else(|| env::var_os("HOME").map(|home| PathBuf::from(home).join(".config")))?;
+    Some(base.join("custom_status_bar").join("config.toml"))
+}
+
+pub fn load(path: &PathBuf) -> Result<Config, String> {
+    let raw = read_to_string(path).map_err(|e| format!("{}: {}", path.display(), e))?;
+    parse(&raw).map_err(|e| format!("{}: {}", path.display(), e))
+}
+
+pub fn parse(raw: &str) -> Result<Config, String> {
+    toml::from_str(raw).map_err(|e| e.to_string())
+}
+
+impl Config {
+    pub fn build(&self) -> Result<Modules, String> {
+        let groups: Vec<Option<String>> = self.modules.iter().map(|m| m.group.clone()).collect();
+        let names: Vec<String> = self
+            .modules
+            .iter()
+            .enumerate()
+            .map(|(i, m)| format!("#{} {}", i + 1, m.kind))
+            .collect();
+        Ok(Modules::new(self.build_modules()?)
+            .with_interval(self.interval()?)
+            .with_groups(&groups)
+            .with_names(&names)
+            .with_keys(&self.keys()?)
+            .with_output_format(self.output_format()?)
+            .with_default_separator(
+                self.output.block_separator,
+                self.output.separator_block_width,
+            )
+            .with_bar_width(self.output.bar_width)
+            .with_max_len(self.output.max_len)
+            .with_header(self.output.header.unwrap_or(true))
+            .with_error_color(self.theme.error)
+            .with_stale_after(
+                self.stale_after_secs.map(Duration::from_secs),
+                self.theme.stale,
+            )
+            .with_highlight_color(self.theme.accent)
+            .with_startup_grace(Duration::from_secs(self.startup_grace_secs.unwrap_or(0)))
+            .with_empty_line(self.empty_line()))
+    }
+
+    /// Stable module keys for the JSON output format.
+    pub fn keys(&self) -> Result<Vec<String>, String> {
+        let mut keys: Vec<String> = Vec::new();
+        for m in &self.modules {
+            let key = match &m.id {
+                Some(id) if keys.contains(id) => {
+                    return Err(format!("duplicate module id `{}`", id))
+                }
+                Some(id) => id.clone(),
+                None => (1..)
+                    .map(|n| match n {
+                        1 => m.kind.clone(),
+                        n => format!("{}_{}", m.kind, n),
+                    })
+                    .find(|key| !keys.contains(key))
+                    .unwrap(),
+            };
+            keys.push(key);
+        }
+        Ok(keys)
+    }
+
+    pub fn interval(&self) -> Result<Duration, String> {
+        match self.interval_ms {
+            None => Ok(DEFAULT_INTERVAL),
+            Some(0) => Err("`interval_ms` must be positive".to_string()),
+            Some(ms) => Ok(Duration::from_millis(ms)),
+        }
+    }
+
+    pub fn empty_line(&self) -> EmptyLine {
+        match (&self.output.empty_placeholder, self.output.skip_empty) {
+            (Some(placeholder), _) => EmptyLine::Placeholder(placeholder.clone()),
+            (None, true) => EmptyLine::Skip,
+            (None, false) => EmptyLine::Emit,
+        }
+    }
+
+    pub fn output_format(&self) -> Result<OutputFormat, String> {
+        output_format(self.output.format.as_deref(), self.output.separator.clone())
+    }
+
+    pub fn build_modules(&self) -> Result<Vec<Box<dyn Module>>, String> {
+        let theme = Arc::new(Theme {
+            glyphs: self.glyphs.clone(),
+            ..self.theme.clone()
+        });
+        self.modules
+            .iter()
+            .enumerate()
+            .map(|(i, conf)| {
+                build_module(conf, &theme).map_err(|e| format!("module #{}: {}", i + 1, e))
+            })
+            .collect()
+    }
+}
+
+/// Resolves an output format by name, defaulting to i3bar, with an optional
+/// separator overriding the format's default.
+pub fn output_format(
+    name: Option<&str>,
+    separator: Option<String>,
+) -> Result<OutputFormat, String> {
+    let separator = |default: &str| separator.unwrap_or_else(|| default.to_string());
+    match name {
+        None | Some("i3bar") => Ok(OutputFormat::I3bar),
+        Some("waybar") => Ok(OutputFormat::Waybar(separator(" | "))),
+        Some("plain") => Ok(OutputFormat::Plain(separator(DEFAULT_PLAIN_SEPARATOR))),
+        Some("diff") => Ok(OutputFormat::Diff),
+        Some("json") => Ok(OutputFormat::Json),
+        Some(other) => Err(format!("unknown output format `{}`", other)),
+    }
+}
+
+fn options<T: DeserializeOwned>(conf: &ModuleConfig) -> Result<T, String> {
+    conf.options
+        .clone()
+        .try_into()
+        .map_err(|e| format!("invalid options for `{}`: {}", conf.kind, e))
+}
+
+/// Builds a module from its config entry, keyed by the `type` field.
+pub fn build_module(conf: &ModuleConfig, theme: &Arc<Theme>) -> Result<Box<dyn Module>, String> {
+    let module: Box<dyn Module> = match conf.kind.as_str() {
+        #[cfg(target_os = "linux")]
+        "battery" => {
+            let opts: BatteryOptions = options(conf)?;
+            let mut module = BatteryModule::new(opts.paths).with_theme(theme.clone());
+            if let Some(display) = opts.display {
+                module = module.with_display(display);
+            }
+            if let Some(threshold) = opts.low_threshold {
+                module = module.with_low_threshold(threshold);
+            }
+            if let Some(icons) = opts.icons {
+                module = module.with_icons(icons);
+            }
+            if let Some(samples) = opts.smoothing {
+                module = module.with_smoothing(samples);
+            }
+            if let Some(width) = opts.progress_bar {
+                module = module.with_progress_bar(width);
+            }
+            if let Some(command) = opts.on_state_change {
+                module = module.with_on_state_change(battery_event_command(command));
+            }
+            module = module
+                .with_blink(opts.blink)
+                .with_hide_when_full(opts.hide_when_full_on_ac);
+            Box::new(module)
+        }
+        #[cfg(all(target_os = "linux", feature = "network"))]
+        "network" => {
+            let opts: NetworkOptions = options(conf)?;
+            let module = match opts.device {
+                Some(device) => NetworkModule::new(&device),
+                None => NetworkModule::auto(),
+            };
+            let mut module = module
+                .with_expanded(opts.expanded.unwrap_or(true))
+                .with_signal_icon(opts.signal_icon)
+                .with_raw_frequency(opts.raw_frequency)
+                .with_theme(theme.clone());
+            if let Some(name) = opts.name {
+                module = module.with_name(&name);
+            }
+            if let Some(address) = opts.address {
+                let selection = AddressSelection::parse(&address)
+                    .ok_or_else(|| format!("invalid address selection `{}`", address))?;
+                module = module.with_addresses(selection);
+            }
+            if let Some(refreshes) = opts.ssid_flash {
+                module = module.with_ssid_flash(refreshes);
+            }
+            let down = match (opts.hide_when_down, opts.down_text) {
+                (false, None) => DownBehavior::Default,
+                (true, None) => DownBehavior::Hide,
+                (false, Some(text)) => DownBehavior::Text(text),
+                (true, Some(_)) => {
+                    return Err("`hide_when_down` and `down_text` can't be combined".to_string())
+                }
+            };
+            Box::new(module.with_down_behavior(down))
+        }
+        #[cfg(feature = "sysinfo-modules")]
+        "disk" => {
+            let opts: DiskSpaceOptions = options(conf)?;
+            let mut module = match (opts.device, opts.devices.is_empty()) {
+                (Some(device), true) => DiskSpaceModule::new(&device),
+                (None, false) => DiskSpaceModule::multi(opts.devices),
+                _ => return Err("set either `device` or `devices`".to_string()),
+            };
+            if let Some(display) = opts.display {
+                module = module.with_display(display);
+            }
+            if let Some(warn) = opts.read_only_warning {
+                module = module.with_read_only_warning(warn);
+            }
+            Box::new(
+                module
+                    .with_grouping(grouping(opts.grouping, None)?)
+                    .with_theme(theme.clone()),
+            )
+        }
+        "datetime" => {
+            let opts: DateTimeOptions = options(conf)?;
+            let format = match (opts.format, opts.preset.as_deref()) {
+                (Some(_), Some(_)) => return Err("set either `format` or `preset`".to_string()),
+                (Some(format), None) => Some(DateFormat::Custom(format)),
+                (None, Some("iso8601")) => Some(DateFormat::Iso8601),
+                (None, Some("unix")) => Some(DateFormat::Unix),
+                (None, Some("since_boot")) => Some(DateFormat::SinceBoot),
+                (None, Some(other)) => return Err(format!("unknown date preset `{}`", other)),
+                (None, None) => None,
+            };
+            let mut module = DateTimeModule::new();
+            if let Some(format) = format {
+                module = module.with_date_format(format);
+            }
+            Box::new(module)
+        }
+        "spacer" => {
+            let opts: SpacerOptions = options(conf)?;
+            match (opts.flexible, opts.width) {
+                (false, width) => Box::new(SpacerModule::new(width)),
+                (true, 0) => Box::new(SpacerModule::flexible()),
+                (true, _) => return Err("a flexible spacer can't have a `width`".to_string()),
+            }
+        }
+        #[cfg(feature = "sysinfo-modules")]
+        "temperature" => {
+            let opts: TemperatureOptions = options(conf)?;
+            let mut module = TemperatureModule::new().with_theme(theme.clone());
+            if let Some(aggregate) = opts.aggregate {
+                module = module.with_aggregate(aggregate);
+            }
+            let thresholds = thresholds(opts.warn, opts.crit, DEFAULT_TEMP_THRESHOLDS)?;
+            match thresholds {
+                Some(t) => module = module.with_thresholds(t),
+                None if opts.smooth_color => {
+                    module = module.with_thresholds(DEFAULT_TEMP_THRESHOLDS)
+                }
+                None => (),
+            }
+            Box::new(module.with_smooth_color(opts.smooth_color))
+        }
+        #[cfg(feature = "sysinfo-modules")]
+        "ram" => {
+            let opts: RamOptions = options(conf)?;
+            let mut module = RamModule::new()
+                .with_gradient(opts.gradient)
+                .with_grouping(grouping(opts.grouping, None)?)
+                .with_theme(theme.clone());
+            if let Some(history) = opts.history {
+                module = module.with_history(history);
+            }
+            if let Some(display) = opts.display {
+                module = module.with_display(display);
+            }
+            if let Some(template) = &opts.format {
+                module = module.with_template(template);
+            }
+            if let Some(t) = thresholds(opts.warn, opts.crit, DEFAULT_CPU_THRESHOLDS)? {
+                module = module.with_thresholds(t);
+            }
+            Box::new(module)
+        }
+        #[cfg(feature = "sysinfo-modules")]
+        "cpu" => {
+            let opts: CpuOptions = options(conf)?;
+            let mut module = CpuModule::new()
+                .with_gradient(opts.gradient)
+                .with_theme(theme.clone());
+            if let Some(warn) = opts.warn {
+                module = module.with_warn(warn);
+            }
+            if let Some(crit) = opts.crit {
+                module = module.with_crit(crit);
+            }
+            if let Some(display) = opts.display {
+                module = module.with_display(display);
+            }
+            if let Some(t) = thresholds(opts.temp_warn, opts.temp_crit, DEFAULT_TEMP_THRESHOLDS)? {
+                module = module.with_temp_thresholds(t);
+            }
+            Box::new(module)
+        }
+        "public_ip" => {
+            let opts: PublicIpOptions = options(conf)?;
+            let mut module = PublicIpModule::new();
+            if let Some(endpoint) = opts.endpoint {
+                module = module.with_endpoint(&endpoint);
+            }
+            Box::new(module)
+        }
+        #[cfg(all(target_os = "linux", feature = "network"))]
+        "vpn" => {
+            options::<NoOptions>(conf)?;
+            Box::new(VpnModule::new().with_theme(theme.clone()))
+        }
+        #[cfg(all(target_os = "linux", feature = "dbus-modules"))]
+        "media" => {
+            let opts: MediaOptions = options(conf)?;
+            let mut module = MediaModule::new();
+            if let Some(max_len) = opts.max_len {
+                module = module.with_max_len(max_len);
+            }
+            Box::new(module)
+        }
+        #[cfg(target_os = "linux")]
+        "updates" => {
+            let opts: UpdatesOptions = options(conf)?;
+            Box::new(UpdatesModule::new(opts.backend))
+        }
+        "git" => {
+            let opts: GitOptions = options(conf)?;
+            Box::new(GitModule::new(&opts.path).with_theme(theme.clone()))
+        }
+        #[cfg(feature = "sysinfo-modules")]
+        "processes" => {
+            let opts: ProcessCountOptions = options(conf)?;
+            let mut module = ProcessCountModule::new().with_theme(theme.clone());
+            if let Some(threshold) = opts.threshold {
+                module = module.with_threshold(threshold);
+            }
+            Box::new(module)
+        }
+        #[cfg(feature = "sysinfo-modules")]
+        "uptime" => {
+            options::<NoOptions>(conf)?;
+            Box::new(UptimeModule::new())
+        }
+        "weather" => {
+            let opts: WeatherOptions = options(conf)?;
+            let mut module = WeatherModule::new(opts.latitude, opts.longitude);
+            if let Some(endpoint) = opts.endpoint {
+                module = module.with_endpoint(&endpoint);
+            }
+            Box::new(module)
+        }
+        #[cfg(target_os = "linux")]
+        "mic" => {
+            options::<NoOptions>(conf)?;
+            Box::new(MicModule::new().with_theme(theme.clone()))
+        }
+        #[cfg(target_os = "linux")]
+        "lock_keys" => {
+            options::<NoOptions>(conf)?;
+            Box::new(LockKeysModule)
+        }
+        #[cfg(target_os = "linux")]
+        "window_title" => {
+            let opts: WindowTitleOptions = options(conf)?;
+            let mut module = match opts.backend.as_deref() {
+                None => WindowTitleModule::detect(),
+                Some("x11") => WindowTitleModule::new(X11WindowTitle),
+                Some("sway") => WindowTitleModule::new(SwayWindowTitle),
+                Some(other) => return Err(format!("unknown window title backend `{}`", other)),
+            };
+            if let Some(max_len) = opts.max_len {
+                module = module.with_max_len(max_len);
+            }
+            Box::new(module)
+        }
+        #[cfg(target_os = "linux")]
+        "workspace" => {
+            options::<NoOptions>(conf)?;
+            Box::new(WorkspaceModule)
+        }
+        "timer" => {
+            let opts: TimerOptions = options(conf)?;
+            Box::new(TimerModule::new(Duration::from_secs(opts.seconds)).with_theme(theme.clone()))
+        }
+        "pomodoro" => {
+            let opts: PomodoroOptions = options(conf)?;
+            Box::new(PomodoroModule::new().with_durations(
+                Duration::from_secs(opts.work_minutes.unwrap_or(25) * 60),
+                Duration::from_secs(opts.break_minutes.unwrap_or(5) * 60),
+            ))
+        }
+        "users" => {
+            options::<NoOptions>(conf)?;
+            Box::new(UsersModule)
+        }
+        #[cfg(feature = "sysinfo-modules")]
+        "os" => {
+            let opts: OsOptions = options(conf)?;
+            Box::new(OsModule::new(opts.field.unwrap_or(OsField::Kernel)))
+        }
+        #[cfg(all(target_os = "linux", feature = "dbus-modules"))]
+        "power_profile" => {
+            options::<NoOptions>(conf)?;
+            Box::new(PowerProfileModule::new().with_theme(theme.clone()))
+        }
+        #[cfg(target_os = "linux")]
+        "gpu" => {
+            let opts: GpuOptions = options(conf)?;
+            Box::new(GpuModule::new(opts.backend))
+        }
+        #[cfg(target_os = "linux")]
+        "memory_pressure" => {
+            let opts: MemoryPressureOptions = options(conf)?;
+            let mut module = MemoryPressureModule::new().with_theme(theme.clone());
+            if let Some(threshold) = opts.threshold {
+                module = module.with_threshold(threshold);
+            }
+            Box::new(module)
+        }
+        #[cfg(target_os = "linux")]
+        "volume" => {
+            let opts: VolumeOptions = options(conf)?;
+            let mut module = VolumeModule::new().with_theme(theme.clone());
+            if let Some(step) = opts.step {
+                module = module.with_step(step);
+            }
+            Box::new(module)
+        }
+        "stopwatch" => {
+            options::<NoOptions>(conf)?;
+            Box::new(StopwatchModule::new())
+        }
+        "moon" => {
+            options::<NoOptions>(conf)?;
+            Box::new(MoonPhaseModule)
+        }
+        "sun" => {
+            let opts: SunOptions = options(conf)?;
+            Box::new(SunModule::new(opts.latitude, opts.longitude))
+        }
+        "ticker" => {
+            let opts: TickerOptions = options(conf)?;
+            let mut module =
+                TickerModule::new(&opts.coin, opts.currency.as_deref().unwrap_or("usd"))
+                    .with_grouping(grouping(opts.grouping, Some(','))?)
+                    .with_theme(theme.clone());
+            if let Some(label) = opts.label {
+                module = module.with_label(&label);
+            }
+            if let Some(endpoint) = opts.endpoint {
+                module = module.with_endpoint(&endpoint);
+            }
+            Box::new(module)
+        }
+        #[cfg(feature = "sysinfo-modules")]
+        "hostname" => {
+            let opts: HostnameOptions = options(conf)?;
+            Box::new(HostnameModule::new().with_ssh_only(opts.ssh_only))
+        }
+        #[cfg(all(target_os = "linux", feature = "dbus-modules"))]
+        "dnd" => {
+            options::<NoOptions>(conf)?;
+            Box::new(DndModule::new().with_theme(theme.clone()))
+        }
+        "containers" => {
+            let opts: ContainersOptions = options(conf)?;
+            let mut module = ContainersModule::new().with_theme(theme.clone());
+            if let Some(socket) = opts.socket {
+                module = module.with_socket(socket);
+            }
+            Box::new(module)
+        }
+        #[cfg(all(target_os = "linux", feature = "dbus-modules"))]
+        "systemd" => {
+            let opts: SystemdOptions = options(conf)?;
+            Box::new(
+                SystemdModule::new()
+                    .with_user(opts.user)
+                    .with_theme(theme.clone()),
+            )
+        }
+        "mail" => {
+            let opts: MailOptions = options(conf)?;
+            Box::new(MailModule::new(opts.maildir))
+        }
+        "calendar" => {
+            let opts: CalendarOptions = options(conf)?;
+            Box::new(CalendarModule::new(opts.path))
+        }
+        "todo" => {
+            let opts: TodoOptions = options(conf)?;
+            let source = match (opts.path, opts.taskwarrior) {
+                (Some(path), false) => TodoSource::TodoTxt(path),
+                (None, true) => TodoSource::Taskwarrior,
+                _ => return Err("set either `path` or `taskwarrior = true`".to_string()),
+            };
+            Box::new(TodoModule::new(source))
+        }
+        #[cfg(target_os = "linux")]
+        "brightness" => {
+            let opts: BrightnessOptions = options(conf)?;
+            let mut module = BrightnessModule::new().with_theme(theme.clone());
+            if let Some(device) = opts.device {
+                module = module.with_device(&device);
+            }
+            if let Some(step) = opts.step {
+                module = module.with_step(step);
+            }
+            Box::new(module)
+        }
+        #[cfg(target_os = "linux")]
+        "throughput" => {
+            let opts: ThroughputOptions = options(conf)?;
+            Box::new(ThroughputModule::new(&opts.device).with_theme(theme.clone()))
+        }
+        #[cfg(target_os = "linux")]
+        "throttle" => {
+            options::<NoOptions>(conf)?;
+            Box::new(ThrottleModule::new().with_theme(theme.clone()))
+        }
+        #[cfg(feature = "sysinfo-modules")]
+        "cpu_temp" => {
+            let opts: GradientOptions = options(conf)?;
+            Box::new(Joined::new(
+                CpuModule::new()
+                    .with_gradient(opts.gradient)
+                    .with_theme(theme.clone()),
+                TemperatureModule::new(),
+            ))
+        }
+        #[cfg(feature = "sysinfo-modules")]
+        "top_mem" => {
+            options::<NoOptions>(conf)?;
+            Box::new(TopMemModule::new())
+        }
+        #[cfg(feature = "sysinfo-modules")]
+        "top_cpu" => {
+            options::<NoOptions>(conf)?;
+            Box::new(TopCpuModule::new())
+        }
+        #[cfg(target_os = "linux")]
+        "gateway" => {
+            options::<NoOptions>(conf)?;
+            Box::new(GatewayModule)
+        }
+        #[cfg(target_os = "linux")]
+        "connections" => {
+            options::<NoOptions>(conf)?;
+            Box::new(ConnectionsModule)
+        }
+        "dns" => {
+            let opts: DnsOptions = options(conf)?;
+            let mut module = DnsModule::new().with_theme(theme.clone());
+            if let Some(host) = opts.host {
+                module = module.with_host(&host);
+            }
+            if let Some(ms) = opts.timeout_ms {
+                module = module.with_timeout(Duration::from_millis(ms));
+            }
+            Box::new(module)
+        }
+        "first_of" => {
+            let opts: CombinatorOptions = options(conf)?;
+            if opts.period.is_some() {
+                return Err("`period` only applies to `rotating`".to_string());
+            }
+            let modules = opts
+                .modules
+                .iter()
+                .map(|inner| build_module(inner, theme))
+                .collect::<Result<_, _>>()
+                .map_err(|e| format!("in first_of: {}", e))?;
+            Box::new(FirstOf::new(modules))
+        }
+        "rotating" => {
+            let opts: CombinatorOptions = options(conf)?;
+            let modules = opts
+                .modules
+                .iter()
+                .map(|inner| build_module(inner, theme))
+                .collect::<Result<_, _>>()
+                .map_err(|e| format!("in rotating: {}", e))?;
+            let mut module = Rotating::new(modules);
+            if let Some(period) = opts.period {
+                module = module.with_period(period);
+            }
+            Box::new(module)
+        }
+        "file" => {
+            let opts: FileOptions = options(conf)?;
+            let format = opts.format.as_deref().unwrap_or("{}");
+            let module = match (opts.divide, opts.decimals) {
+                (None, None) => FileModule::new(&opts.path, format, |raw| Some(raw.to_string())),
+                (divide, decimals) => FileModule::new(
+                    &opts.path,
+                    format,
+                    scaled(divide.unwrap_or(1.), decimals.unwrap_or(0)),
+                ),
+            };
+            Box::new(module)
+        }
+        "command" => {
+            let opts: CommandOptions = options(conf)?;
+            let mut module = CommandModule::new(&opts.command).with_theme(theme.clone());
+            if let Some(ms) = opts.timeout_ms {
+                module = module.with_timeout(Duration::from_millis(ms));
+            }
+            Box::new(module)
+        }
+        other => return Err(format!("unknown module type `{}`", other)),
+    };
+
+    if conf.rate == Some(0) {
+        return Err("rate must be at least 1".to_string());
+    }
+    let rate = match (conf.rate, conf.on_demand) {
+        (Some(_), true) => return Err("`rate` and `on_demand` can't be combined".to_string()),
+        (rate, false) => rate,
+        (None, true) => Some(ON_DEMAND),
+    };
+
+    let module = match conf.average {
+        Some(0) => return Err("average must be at least 1".to_string()),
+        Some(samples) => Box::new(Averaged::new(module, samples)),
+        None => module,
+    };
+
+    let module = match &conf.notify {
+        Some(notify) => {
+            let bound = match (notify.above, notify.below) {
+                (Some(above), None) => Bound::Above(above),
+                (None, Some(below)) => Bound::Below(below),
+                _ => return Err("`notify` needs exactly one of `above` and `below`".to_string()),
+            };
+            let mut module =
+                NotifyOnThreshold::new(module, bound, threshold_command(notify.command.clone()));
+            if let Some(metric) = &notify.metric {
+                module = module.with_metric(metric);
+            }
+            Box::new(module)
+        }
+        None => module,
+    };
+
+    let mut decorated = Decorated::new(module);
+    if let Some(prefix) = &conf.prefix {
+        decorated = decorated.with_prefix(prefix);
+    }
+    if let Some(suffix) = &conf.suffix {
+        decorated = decorated.with_suffix(suffix);
+    }
+    if let Some(color) = &conf.color {
+        decorated = decorated.with_color_fg(color.to_string());
+    }
+    if let Some(color) = &conf.background {
+        decorated = decorated.with_color_bg(color.to_string());
+    }
+
+    Ok(Box::new(Configured {
+        inner: Box::new(decorated),
+        rate,
+        separator: conf.separator,
+        separator_block_width: conf.separator_block_width,
+        min_width: conf.min_width.clone(),
+        align: conf.align,
+        auto_short: conf.auto_short,
+        padding: conf.padding,
+    }))
+}
+
+/// Applies the generic per-module config options on top of any module.
+struct Configured {
+    inner: Box<dyn Module>,
+    rate: Option<usize>,
+    separator: Option<bool>,
+    separator_block_width: Option<usize>,
+    min_width: Option<MinWidth>,
+    align: Option<Align>,
+    auto_short: bool,
+    padding: Option<(usize, usize)>,
+}
+
+impl Module for Configured {
+    fn get_output(&mut self) -> ModuleRes {
+        let mut out = self.inner.get_output()?;
+        if let Some(separator) = self.separator {
+            out = out.with_separator(separator);
+        }
+        if let Some(width) = self.separator_block_width {
+            out = out.with_separator_block_width(width);
+        }
+        if let Some(min_width) = &self.min_width {
+            out = out.with_min_width(min_width.clone());
+        }
+        if let Some(align) = self.align {
+            out = out.with_align(align);
+        }
+        if self.auto_short {
+            out = out.auto_short();
+        }
+        if let Some((left, right)) = self.padding {
+            out = out.with_padding(left, right);
+        }
+        Ok(out)
+    }
+
+    fn rate(&self) -> usize {
+        self.rate.unwrap_or_else(|| self.inner.rate())
+    }
+
+    fn hidden_limit(&self) -> Option<usize> {
+        self.inner.hidden_limit()
+    }
+
+    fn set_expanded(&mut self, expanded: bool) {
+        self.inner.set_expanded(expanded);
+    }
+
+    fn on_click(&mut self, event: &ClickEvent) {
+        self.inner.on_click(event);
+    }
+
+    /// A configured `min_width` is set on the output, so it wins over this.
+    fn min_width(&self) -> Option<MinWidth> {
+        self.inner.min_width()
+    }
+}
@@ -1,32 +1,667 @@
-#[macro_use]
-mod modules;
-
-use std::{thread::sleep, time::Duration};
+use custom_status_bar::{config, modules, sink::UnixSocketSink};
+use signal_hook::{
+    consts::{SIGHUP, SIGINT, SIGTERM, SIGUSR1},
+    iterator::Signals,
+};
+use std::{
+    fs::File,
+    io::{stdin, stdout, BufRead, BufReader, Write},
+    os::unix::net::UnixListener,
+    process::exit,
+    sync::mpsc::{channel, Receiver, RecvTimeoutError, Sender},
+    thread,
+    time::{Duration, Instant},
+};
 
 use modules::*;
 
+/// The built-in bar, limited to the modules this platform and the enabled
+/// features provide.
+fn default_modules() -> Modules {
+    let mut modules: Vec<Box<dyn Module>> = Vec::new();
+    #[cfg(target_os = "linux")]
+    modules.push(Box::new(BatteryModule::new([
+        "/sys/class/power_supply/BAT0",
+        "/sys/class/power_supply/BAT1",
+    ])));
+    #[cfg(all(target_os = "linux", feature = "network"))]
+    modules.extend([
+        Box::new(NetworkModule::new("enp0s31f6").with_name("E")) as Box<dyn Module>,
+        Box::new(NetworkModule::new("enp60s0u1u1").with_name("ED")),
+        Box::new(NetworkModule::new("wlp3s0").with_name("W")),
+    ]);
+    #[cfg(feature = "sysinfo-modules")]
+    modules.extend([
+        Box::new(DiskSpaceModule::new(if cfg!(target_os = "linux") {
+            "/dev/sda3"
+        } else {
+            "/"
+        })) as Box<dyn Module>,
+        Box::new(TemperatureModule::new()),
+        Box::new(RamModule::new()),
+        Box::new(CpuModule::new()),
+    ]);
+    modules.extend([
+        Box::new(DateTimeModule::new()) as Box<dyn Module>,
+        Box::new(SpacerModule::new(0)),
+    ]);
+    Modules::new(modules)
+}
+
+/// Sets up logging, which is off unless `CUSTOM_STATUS_BAR_LOG` holds an
+/// `env_logger` filter such as `warn` or `debug`. Stdout belongs to the bar,
+/// so logs go to stderr, or to `CUSTOM_STATUS_BAR_LOG_FILE` if set.
+fn init_logging() {
+    let mut builder = env_logger::Builder::from_env(
+        env_logger::Env::new().filter_or("CUSTOM_STATUS_BAR_LOG", "off"),
+    );
+    if let Some(path) = std::env::var_os("CUSTOM_STATUS_BAR_LOG_FILE") {
+        match File::options().create(true).append(true).open(&path) {
+            Ok(file) => {
+                builder.target(env_logger::Target::Pipe(Box::new(file)));
+            }
+            Err(e) => eprintln!("custom_status_bar: can't open log file: {}", e),
+        }
+    }
+    builder.init();
+}
+
+/// Command-line overrides, applied on top of the config file.
+#[derive(Default)]
+struct Args {
+    interval: Option<Duration>,
+    format: Option<OutputFormat>,
+    click_events: bool,
+    /// Debug dump target: `Some(None)` for stderr, or a file path.
+    dump: Option<Option<String>>,
+    check: bool,
+    /// Leave out the protocol header, continuing a previous instance's stream.
+    no_header: bool,
+    /// Unix socket to write lines to instead of stdout.
+    socket: Option<String>,
+    /// Unix socket to accept [`Control`] commands on.
+    control: Option<String>,
+}
+
+const USAGE: &str = "usage: custom_status_bar [--interval-ms=N] [--output-format=i3bar|waybar|plain|diff|json] [--no-click-events] [--no-header] [--debug-dump[=FILE]] [--check] [--socket=PATH] [--control=PATH]";
+
+/// Parses flags. Those taking a value accept either `--flag=value` or
+/// `--flag value`, except `--debug-dump`, whose file is optional and so only
+/// given as `--debug-dump=FILE`. Switches take no value.
+fn parse_args(args: impl IntoIterator<Item = String>) -> Result<Args, String> {
+    let mut parsed = Args {
+        click_events: true,
+        ..Args::default()
+    };
+    let mut args = args.into_iter();
+    while let Some(arg) = args.next() {
+        let (flag, inline) = match arg.split_once('=') {
+            Some((flag, value)) => (flag.to_string(), Some(value.to_string())),
+            None => (arg, None),
+        };
+        let mut value = || {
+            inline
+                .clone()
+                .or_else(|| args.next())
+                .ok_or_else(|| format!("`{}` needs a value", flag))
+        };
+        match flag.as_str() {
+            "--no-click-events" | "--check" | "--no-header" if inline.is_some() => {
+                return Err(format!("`{}` takes no value", flag));
+            }
+            "--interval-ms" => {
+                let ms = value()?;
+                match ms.parse::<u64>() {
+                    Ok(ms) if ms > 0 => parsed.interval = Some(Duration::from_millis(ms)),
+                    _ => return Err(format!("invalid interval `{}`", ms)),
+                }
+            }
+            "--output-format" => {
+                parsed.format = Some(config::output_format(Some(&value()?), None)?);
+            }
+            "--no-click-events" => parsed.click_events = false,
+            "--debug-dump" => parsed.dump = Some(inline),
+            "--check" => parsed.check = true,
+            "--no-header" => parsed.no_header = true,
+            "--socket" => parsed.socket = Some(value()?),
+            "--control" => parsed.control = Some(value()?),
+            other => return Err(format!("unknown argument `{}`\n{}", other, USAGE)),
+        }
+    }
+    Ok(parsed)
+}
+
+/// Polls each module once and reports it, returning whether none failed fatally.
+fn check(modules: &mut Modules) -> bool {
+    let mut ok = true;
+    for (name, res) in modules.check() {
+        match res {
+            Ok(out) => println!("OK      {}: {}", name, out.content()),
+            Err(ModuleError::Hidden) => println!("HIDDEN  {}", name),
+            Err(ModuleError::Message(mes)) => println!("FAIL    {}: {}", name, mes),
+            Err(ModuleError::Fatal(mes)) => {
+                ok = false;
+                println!("FATAL   {}: {}", name, mes);
+            }
+        }
+    }
+    ok
+}
+
 fn main() {
-    let mut modules = modules![
-        BatteryModule::new([
-            "/sys/class/power_supply/BAT0",
-            "/sys/class/power_supply/BAT1"
-        ]),
-        NetworkModule::new("enp0s31f6").with_name("E"),
-        NetworkModule::new("enp60s0u1u1").with_name("ED"),
-        NetworkModule::new("wlp3s0").with_name("W"),
-        DiskSpaceModule::new("/dev/sda3"),
-        TemperatureModule::new(),
-        RamModule::new(),
-        CpuModule::new(),
-        DateTimeModule,
-        SpacerModule::<0>::new()
-    ];
-    println!("{{\"version\": 1}}\n[");
-
-    let t_sleep = Duration::from_millis(1000);
-    loop {
-        let res = modules.combine_modules();
-        println!("{},", res);
-        sleep(t_sleep);
+    init_logging();
+    let args = match parse_args(std::env::args().skip(1)) {
+        Ok(args) => args,
+        Err(e) => {
+            eprintln!("custom_status_bar: {}", e);
+            exit(2);
+        }
+    };
+    let mut modules = match config::default_path().filter(|p| p.exists()) {
+        Some(path) => match config::load(&path).and_then(|c| c.build()) {
+            Ok(modules) => modules,
+            Err(e) => {
+                eprintln!("custom_status_bar: {}", e);
+                exit(1);
+            }
+        },
+        None => default_modules(),
+    };
+    if let Some(interval) = args.interval {
+        modules = modules.with_interval(interval);
+    }
+    if let Some(format) = args.format {
+        modules = modules.with_output_format(format);
+    }
+    if args.no_header {
+        modules = modules.with_header(false);
+    }
+    match args.dump {
+        Some(Some(path)) => match File::options().create(true).append(true).open(&path) {
+            Ok(file) => modules = modules.with_dump(Box::new(file)),
+            Err(e) => {
+                eprintln!("custom_status_bar: can't open dump file: {}", e);
+                exit(1);
+            }
+        },
+        Some(None) => modules = modules.with_dump(Box::new(std::io::stderr())),
+        None => (),
+    }
+    if args.check {
+        exit(if check(&mut modules) { 0 } else { 1 });
+    }
+    let mut out: Box<dyn Write> = match &args.socket {
+        Some(path) => {
+            let header = modules.header(args.click_events);
+            Box::new(UnixSocketSink::new(path.as_ref()).with_header(header))
+        }
+        None => {
+            let mut out = stdout();
+            if modules.write_header(&mut out, args.click_events).is_err() {
+                exit(1);
+            }
+            Box::new(out)
+        }
+    };
+
+    let (tx, rx) = channel();
+    if args.click_events && matches!(modules.output_format(), OutputFormat::I3bar) {
+        spawn_click_thread(tx.clone());
+    }
+    if let Some(path) = &args.control {
+        if let Err(e) = spawn_control_thread(tx.clone(), path) {
+            eprintln!("custom_status_bar: can't listen on {}: {}", path, e);
+            exit(1);
+        }
+    }
+    spawn_signal_thread(tx, modules.len());
+
+    run(&mut modules, &mut out, &rx, args.interval);
+}
+
+/// Where the main loop waits for events and reads the time, so tests can
+/// stand in for the channel and the passing of time.
+trait EventSource {
+    fn now(&self) -> Instant;
+    fn recv_timeout(&self, timeout: Duration) -> Result<Event, RecvTimeoutError>;
+}
+
+impl EventSource for Receiver<Event> {
+    fn now(&self) -> Instant {
+        Instant::now()
+    }
+
+    fn recv_timeout(&self, timeout: Duration) -> Result<Event, RecvTimeoutError> {
+        Receiver::recv_timeout(self, timeout)
+    }
+}
+
+/// Writes a line every tick and handles events in between, until told to
+/// stop or the output goes away. Closes the stream with the format's footer,
+/// so the bar is left with well-formed output.
+fn run<W: Write + ?Sized, E: EventSource>(
+    modules: &mut Modules,
+    out: &mut W,
+    events: &E,
+    interval: Option<Duration>,
+) {
+    let mut paused = false;
+    'main: loop {
+        if !paused && modules.write_line(out).is_err() {
+            break 'main;
+        }
+
+        let next_tick = events.now() + modules.interval();
+        loop {
+            let event = match events.recv_timeout(next_tick.saturating_duration_since(events.now()))
+            {
+                Ok(event) => event,
+                Err(RecvTimeoutError::Timeout) => break,
+                Err(RecvTimeoutError::Disconnected) => break 'main,
+            };
+            match event {
+                Event::Terminate => break 'main,
+                Event::Stop => paused = true,
+                Event::Cont => paused = false,
+                Event::Refresh(target) if !paused => {
+                    match target {
+                        Some(i) => modules.trigger(i),
+                        None => modules.trigger_all(),
+                    }
+                    if modules.write_refresh(out).is_err() {
+                        break 'main;
+                    }
+                }
+                Event::Refresh(_) => (),
+                Event::Toggle(i) => {
+                    modules.toggle(i);
+                    if !paused && modules.write_refresh(out).is_err() {
+                        break 'main;
+                    }
+                }
+                Event::Click(click) => {
+                    modules.click(&click);
+                    if !paused && modules.write_refresh(out).is_err() {
+                        break 'main;
+                    }
+                }
+                Event::Control(control) => {
+                    if let Err(e) = modules.control(&control) {
+                        log::warn!("ignoring control command: {}", e);
+                    } else if !paused && modules.write_refresh(out).is_err() {
+                        break 'main;
+                    }
+                }
+                Event::Reload => {
+                    if reload(modules, interval) {
+                        break;
+                    }
+                }
+            }
+        }
+    }
+
+    if let Some(footer) = modules.output_format().footer() {
+        let _ = writeln!(out, "{}", footer);
+    }
+}
+
+/// Rebuilds the modules from the config file, keeping the current ones if
+/// it's gone or broken. Returns whether the modules were replaced.
+fn reload(modules: &mut Modules, interval: Option<Duration>) -> bool {
+    let Some(path) = config::default_path().filter(|p| p.exists()) else {
+        log::warn!("no config file to reload");
+        return false;
+    };
+    let reloaded = swap_modules(
+        modules,
+        config::load(&path).and_then(|c| c.build()),
+        interval,
+    );
+    if reloaded {
+        log::info!("reloaded {}", path.display());
+    }
+    reloaded
+}
+
+/// Swaps in freshly `built` modules, keeping the `--interval-ms` override,
+/// or logs why they couldn't be built and leaves `modules` as they are.
+fn swap_modules(
+    modules: &mut Modules,
+    built: Result<Modules, String>,
+    interval: Option<Duration>,
+) -> bool {
+    match built {
+        Ok(mut next) => {
+            if let Some(interval) = interval {
+                next = next.with_interval(interval);
+            }
+            modules.reload(next);
+            true
+        }
+        Err(e) => {
+            log::error!("keeping the current modules: {}", e);
+            false
+        }
+    }
+}
+
+enum Event {
+    Terminate,
+    Stop,
+    Cont,
+    /// Refresh a single module by index, or every module.
+    Refresh(Option<usize>),
+    /// Enable or disable a module by index.
+    Toggle(usize),
+    Click(ClickEvent),
+    Control(Control),
+    /// Rebuild the modules from the config file.
+    Reload,
+}
+
+/// Reads i3bar click events from stdin and forwards them to the main loop.
+fn spawn_click_thread(tx: Sender<Event>) {
+    thread::spawn(move || {
+        for line in stdin().lock().lines() {
+            let Ok(line) = line else { break };
+            if let Some(click) = ClickEvent::parse(&line) {
+                if tx.send(Event::Click(click)).is_err() {
+                    break;
+                }
+            }
+        }
+    });
+}
+
+/// Listens for [`Control`] commands on a Unix socket at `path`, replacing a
+/// leftover socket from an earlier run. Clients may send any number of
+/// lines, e.g. `echo "highlight net" | socat - UNIX-CONNECT:PATH`.
+fn spawn_control_thread(tx: Sender<Event>, path: &str) -> std::io::Result<()> {
+    let _ = std::fs::remove_file(path);
+    let listener = UnixListener::bind(path)?;
+    thread::spawn(move || {
+        for stream in listener.incoming() {
+            let stream = match stream {
+                Ok(stream) => stream,
+                Err(e) => {
+                    log::warn!("control connection failed: {}", e);
+                    continue;
+                }
+            };
+            for line in BufReader::new(stream).lines() {
+                let Ok(line) = line else { break };
+                match Control::parse(&line) {
+                    Ok(control) => {
+                        if tx.send(Event::Control(control)).is_err() {
+                            return;
+                        }
+                    }
+                    Err(e) => log::warn!("invalid control command `{}`: {}", line, e),
+                }
+            }
+        }
+    });
+    Ok(())
+}
+
+/// The real-time signal range, which only Linux has. Elsewhere the range is
+/// empty, so modules can only be refreshed all at once.
+#[cfg(target_os = "linux")]
+fn realtime_signals() -> (i32, i32) {
+    (libc::SIGRTMIN(), libc::SIGRTMAX())
+}
+
+#[cfg(not(target_os = "linux"))]
+fn realtime_signals() -> (i32, i32) {
+    (1, 0)
+}
+
+/// Forwards handled signals to the main loop, waking it from its sleep.
+///
+/// `SIGUSR1` refreshes every module immediately, while `SIGRTMIN+n` only
+/// refreshes the `n`th module and `SIGRTMAX-n` toggles it on or off. Each
+/// direction gets half of the real-time range, sized for the modules there
+/// are at startup. `SIGHUP` reloads the config file.
+fn spawn_signal_thread(tx: Sender<Event>, module_count: usize) {
+    let (rt_min, rt_max) = realtime_signals();
+    let rt_count = module_count.min((rt_max - rt_min + 1) as usize / 2) as i32;
+
+    let mut signals = Signals::new(
+        [SIGTERM, SIGINT, STOP_SIGNAL, CONT_SIGNAL, SIGUSR1, SIGHUP]
+            .into_iter()
+            .chain(rt_min..rt_min + rt_count)
+            .chain(rt_max - rt_count + 1..=rt_max),
+    )
+    .expect("failed to register signal handler");
+
+    thread::spawn(move || {
+        for sig in signals.forever() {
+            let event = match sig {
+                SIGTERM | SIGINT => Event::Terminate,
+                STOP_SIGNAL => Event::Stop,
+                CONT_SIGNAL => Event::Cont,
+                SIGUSR1 => Event::Refresh(None),
+                SIGHUP => Event::Reload,
+                sig if sig < rt_min + rt_count => Event::Refresh(Some((sig - rt_min) as usize)),
+                sig => Event::Toggle((rt_max - sig) as usize),
+            };
+            if tx.send(event).is_err() {
+                break;
+            }
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::cell::{Cell, RefCell};
+    use std::collections::VecDeque;
+
+    /// Shows the same text on every poll.
+    struct Fixed(&'static str);
+
+    impl Module for Fixed {
+        fn get_output(&mut self) -> ModuleRes {
+            Ok(ModuleOutput::new(self.0.to_string()))
+        }
+    }
+
+    fn fixed(content: &'static str) -> Modules {
+        Modules::new(vec![Box::new(Fixed(content))])
+    }
+
+    #[test]
+    fn shutdown_closes_the_i3bar_array() {
+        let (tx, rx) = channel();
+        tx.send(Event::Terminate).unwrap();
+        let mut out = Vec::new();
+        run(&mut fixed("a"), &mut out, &rx, None);
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "[{\"full_text\": \"a\", \"instance\": \"0\"}]\n]\n"
+        );
+
+        // Without a footer, the last line is left as is.
+        tx.send(Event::Terminate).unwrap();
+        let mut modules = fixed("a").with_output_format(OutputFormat::Plain(String::new()));
+        let mut out = Vec::new();
+        run(&mut modules, &mut out, &rx, None);
+        assert_eq!(String::from_utf8(out).unwrap(), "a\n");
+    }
+
+    #[test]
+    fn refresh_event_wakes_the_loop_early() {
+        let (tx, rx) = channel();
+        tx.send(Event::Refresh(None)).unwrap();
+        tx.send(Event::Refresh(Some(0))).unwrap();
+        tx.send(Event::Terminate).unwrap();
+        let mut modules = fixed("a")
+            .with_interval(Duration::from_secs(60))
+            .with_output_format(OutputFormat::Plain(String::new()));
+        let mut out = Vec::new();
+        let started = Instant::now();
+        run(&mut modules, &mut out, &rx, None);
+        assert!(started.elapsed() < Duration::from_secs(5));
+        // The tick's line, then one per refresh.
+        assert_eq!(String::from_utf8(out).unwrap(), "a\na\na\n");
+    }
+
+    /// Replays scripted events without sleeping: each entry moves the clock
+    /// on by its delay, then delivers its event or times out.
+    struct FakeEvents {
+        now: Cell<Instant>,
+        script: RefCell<VecDeque<(Duration, Option<Event>)>>,
+        waits: RefCell<Vec<Duration>>,
+    }
+
+    impl EventSource for FakeEvents {
+        fn now(&self) -> Instant {
+            self.now.get()
+        }
+
+        fn recv_timeout(&self, timeout: Duration) -> Result<Event, RecvTimeoutError> {
+            self.waits.borrow_mut().push(timeout);
+            let (delay, event) = self
+                .script
+                .borrow_mut()
+                .pop_front()
+                .expect("loop waited more often than scripted");
+            assert!(delay <= timeout, "{:?} is past the deadline", delay);
+            self.now.set(self.now.get() + delay);
+            event.ok_or(RecvTimeoutError::Timeout)
+        }
+    }
+
+    #[test]
+    fn ticks_follow_the_interval() {
+        let ms = Duration::from_millis;
+        let events = FakeEvents {
+            now: Cell::new(Instant::now()),
+            script: RefCell::new(VecDeque::from([
+                (ms(500), None),
+                (ms(200), Some(Event::Refresh(None))),
+                (ms(300), None),
+                (ms(0), Some(Event::Terminate)),
+            ])),
+            waits: RefCell::new(Vec::new()),
+        };
+        let mut modules = fixed("a")
+            .with_interval(ms(500))
+            .with_output_format(OutputFormat::Plain(String::new()));
+        let mut out = Vec::new();
+        run(&mut modules, &mut out, &events, None);
+        // A refresh doesn't push the next tick back.
+        assert_eq!(*events.waits.borrow(), [ms(500), ms(500), ms(300), ms(500)]);
+        // Three ticks and the refresh.
+        assert_eq!(String::from_utf8(out).unwrap(), "a\na\na\na\n");
+    }
+
+    #[test]
+    fn failed_reload_keeps_the_current_modules() {
+        let build = |raw: &str| config::parse(raw).and_then(|c| c.build());
+        let mut modules = build(
+            r#"
+            [output]
+            format = "plain"
+            separator = "|"
+            [[module]]
+            type = "spacer"
+            prefix = "a"
+            "#,
+        )
+        .unwrap();
+        assert_eq!(modules.combine_modules(), Some("a"));
+
+        let broken = build("[[module]]\ntype = \"no_such_module\"");
+        assert!(!swap_modules(&mut modules, broken, None));
+        assert_eq!(modules.len(), 1);
+        assert_eq!(modules.combine_modules(), Some("a"));
+
+        let next = build(
+            r#"
+            [[module]]
+            type = "spacer"
+            prefix = "b"
+            [[module]]
+            type = "spacer"
+            prefix = "c"
+            "#,
+        );
+        let interval = Duration::from_millis(250);
+        assert!(swap_modules(&mut modules, next, Some(interval)));
+        assert_eq!(modules.interval(), interval);
+        assert_eq!(modules.combine_modules(), Some("b|c"));
+    }
+
+    fn parse(args: &[&str]) -> Result<Args, String> {
+        parse_args(args.iter().map(|a| a.to_string()))
+    }
+
+    fn parse_err(args: &[&str]) -> String {
+        parse(args).err().expect("arguments should be rejected")
+    }
+
+    #[test]
+    fn interval_flag_takes_milliseconds() {
+        let ms = |args| parse(args).unwrap().interval;
+        assert_eq!(ms(&[]), None);
+        assert_eq!(ms(&["--interval-ms=500"]), Some(Duration::from_millis(500)));
+        assert_eq!(
+            ms(&["--interval-ms", "250"]),
+            Some(Duration::from_millis(250))
+        );
+
+        for bad in [
+            "--interval-ms=0",
+            "--interval-ms=-5",
+            "--interval-ms=1.5",
+            "--interval-ms=fast",
+        ] {
+            assert!(parse_err(&[bad]).starts_with("invalid interval"), "{}", bad);
+        }
+        assert_eq!(
+            parse_err(&["--interval-ms"]),
+            "`--interval-ms` needs a value"
+        );
+    }
+
+    #[test]
+    fn output_format_flag_accepts_each_format() {
+        let format = |name: &str| {
+            parse(&[&format!("--output-format={}", name)])
+                .unwrap()
+                .format
+        };
+        assert!(matches!(format("i3bar"), Some(OutputFormat::I3bar)));
+        assert!(matches!(format("waybar"), Some(OutputFormat::Waybar(_))));
+        assert!(matches!(format("plain"), Some(OutputFormat::Plain(_))));
+        assert!(matches!(format("diff"), Some(OutputFormat::Diff)));
+        assert!(matches!(format("json"), Some(OutputFormat::Json)));
+        assert!(matches!(
+            parse(&["--output-format", "plain"]).unwrap().format,
+            Some(OutputFormat::Plain(_))
+        ));
+        assert!(parse(&["--output-format=lemonbar"]).is_err());
+    }
+
+    #[test]
+    fn unknown_flags_are_rejected() {
+        let err = parse_err(&["--interval=5"]);
+        assert!(err.starts_with("unknown argument `--interval`"), "{}", err);
+        assert!(err.ends_with(USAGE), "{}", err);
+        assert!(parse(&["-v"]).is_err());
+
+        let args = parse(&["--no-click-events", "--no-header", "--check"]).unwrap();
+        assert!(!args.click_events && args.no_header && args.check);
+        assert!(parse(&[]).unwrap().click_events);
+
+        assert_eq!(
+            parse_err(&["--no-click-events=false"]),
+            "`--no-click-events` takes no value"
+        );
+        assert_eq!(parse_err(&["--check=no"]), "`--check` takes no value");
+        assert_eq!(parse_err(&["--no-header="]), "`--no-header` takes no value");
     }
 }
@@ -1,500 +1,10589 @@
-use chrono::Local;
-use dbus::blocking::Connection;
+use chrono::{DateTime, Days, Local, NaiveDate, NaiveDateTime, TimeZone, Utc};
+#[cfg(all(
+    target_os = "linux",
+    any(feature = "network", feature = "dbus-modules")
+))]
+use dbus::blocking::{stdintf::org_freedesktop_dbus::Properties, Connection};
+#[cfg(all(target_os = "linux", feature = "network"))]
 use networkmanager::{
     devices::{Any, Device, Wired, Wireless},
     NetworkManager,
 };
+use serde::Deserialize;
+#[cfg(any(target_os = "linux", feature = "sysinfo-modules"))]
+use std::env;
 use std::fmt::{Display, Write};
-use std::{fs::read_to_string, net::Ipv4Addr, path::PathBuf, thread::sleep, time::Duration};
-use sysinfo::{ComponentExt, CpuExt, DiskExt, System, SystemExt};
+use std::io::{Read, Write as IoWrite};
+#[cfg(target_os = "linux")]
+use std::net::Ipv4Addr;
+use std::os::unix::net::UnixStream;
+#[cfg(feature = "sysinfo-modules")]
+use std::thread::sleep;
+use std::{
+    borrow::Cow,
+    fs::{read_dir, read_to_string},
+    net::IpAddr,
+    panic::{self, AssertUnwindSafe},
+    path::{Path, PathBuf},
+    process::Command,
+    sync::Arc,
+    time::Duration,
+    time::Instant,
+};
+#[cfg(feature = "sysinfo-modules")]
+use sysinfo::{ComponentExt, CpuExt, DiskExt, ProcessExt, ProcessRefreshKind, System, SystemExt};
 
-#[derive(Default)]
-pub struct ModuleOutput {
-    content: String,
-    color_fg: Option<String>,
-    color_bg: Option<String>,
-    border: Option<String>,
-    separator: Option<bool>,
-    separator_block_width: Option<usize>,
+/// Minimum width of a block, either in pixels or as the width of a sample text.
+#[derive(Clone, Debug, PartialEq, Deserialize)]
+#[serde(untagged)]
+pub enum MinWidth {
+    Pixels(usize),
+    Text(String),
 }
 
-impl ModuleOutput {
-    pub fn new(content: String) -> Self {
-        ModuleOutput {
-            content,
-            color_fg: None,
-            color_bg: None,
-            border: None,
-            separator: None,
-            separator_block_width: None,
+#[derive(Clone, Copy, PartialEq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Align {
+    Left,
+    Center,
+    Right,
+}
+
+impl Display for Align {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Align::Left => write!(f, "left"),
+            Align::Center => write!(f, "center"),
+            Align::Right => write!(f, "right"),
         }
     }
+}
 
-    pub fn with_content(mut self, content: String) -> Self {
-        self.content = content;
-        self
-    }
+/// An `#RRGGBB` or `#RRGGBBAA` color as understood by i3bar. Configs may
+/// also name one of [`NAMED_COLORS`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize)]
+#[serde(try_from = "String")]
+pub struct Color {
+    r: u8,
+    g: u8,
+    b: u8,
+    a: Option<u8>,
+}
 
-    pub fn with_color_fg(mut self, color: String) -> Self {
-        self.color_fg = Some(color);
-        self
+impl Color {
+    pub const fn rgb(r: u8, g: u8, b: u8) -> Self {
+        Color { r, g, b, a: None }
     }
 
-    pub fn with_color_bg(mut self, color: String) -> Self {
-        self.color_bg = Some(color);
+    pub const fn with_alpha(mut self, a: u8) -> Self {
+        self.a = Some(a);
         self
     }
 
-    pub fn with_border(mut self, border: String) -> Self {
-        self.border = Some(border);
-        self
+    pub fn from_hex(hex: &str) -> Result<Self, String> {
+        let invalid = || format!("invalid color `{}`, expected #RRGGBB or #RRGGBBAA", hex);
+        let digits = hex.strip_prefix('#').ok_or_else(invalid)?;
+        if !digits.is_ascii() || !matches!(digits.len(), 6 | 8) {
+            return Err(invalid());
+        }
+        let channel = |i: usize| u8::from_str_radix(&digits[i..i + 2], 16).map_err(|_| invalid());
+        Ok(Color {
+            r: channel(0)?,
+            g: channel(2)?,
+            b: channel(4)?,
+            a: if digits.len() == 8 {
+                Some(channel(6)?)
+            } else {
+                None
+            },
+        })
     }
 
-    pub fn with_separator(mut self, seperator: bool) -> Self {
-        self.separator = Some(seperator);
-        self
+    /// Parses a hex color or one of [`NAMED_COLORS`], ignoring case.
+    pub fn parse(s: &str) -> Result<Self, String> {
+        if s.starts_with('#') {
+            return Color::from_hex(s);
+        }
+        NAMED_COLORS
+            .iter()
+            .find(|(name, _)| name.eq_ignore_ascii_case(s))
+            .map(|(_, color)| *color)
+            .ok_or_else(|| format!("unknown color `{}`, expected a name or #RRGGBB", s))
     }
 
-    pub fn with_separator_block_width(mut self, separator_block_width: usize) -> Self {
-        self.separator_block_width = Some(separator_block_width);
-        self
+    /// Linearly interpolates towards `other`, with `t` clamped to `0..=1`.
+    pub fn lerp(self, other: Color, t: f32) -> Color {
+        let t = t.clamp(0., 1.);
+        let mix = |a: u8, b: u8| (a as f32 + (b as f32 - a as f32) * t).round() as u8;
+        Color {
+            r: mix(self.r, other.r),
+            g: mix(self.g, other.g),
+            b: mix(self.b, other.b),
+            a: match (self.a, other.a) {
+                (None, None) => None,
+                (a, b) => Some(mix(a.unwrap_or(0xff), b.unwrap_or(0xff))),
+            },
+        }
     }
 }
 
-type ModuleRes = Result<ModuleOutput, Option<String>>;
-pub trait Module {
-    fn get_output(&mut self) -> ModuleRes;
-    fn rate(&self) -> usize {
-        1
+/// Color names accepted wherever a hex color is.
+pub const NAMED_COLORS: [(&str, Color); 11] = [
+    ("black", Color::rgb(0x00, 0x00, 0x00)),
+    ("white", Color::rgb(0xff, 0xff, 0xff)),
+    ("gray", Color::rgb(0x80, 0x80, 0x80)),
+    ("red", Color::rgb(0xff, 0x00, 0x00)),
+    ("green", Color::rgb(0x00, 0xff, 0x00)),
+    ("blue", Color::rgb(0x00, 0x00, 0xff)),
+    ("yellow", Color::rgb(0xff, 0xff, 0x00)),
+    ("orange", Color::rgb(0xff, 0xa5, 0x00)),
+    ("purple", Color::rgb(0x80, 0x00, 0x80)),
+    ("cyan", Color::rgb(0x00, 0xff, 0xff)),
+    ("magenta", Color::rgb(0xff, 0x00, 0xff)),
+];
+
+impl TryFrom<String> for Color {
+    type Error = String;
+
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        Color::parse(&s)
     }
 }
 
-macro_rules! modules {
-    ($($x:expr),*) => {
-        Modules::new([ $(Box::new($x)),* ])
-    };
+impl Display for Color {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "#{:02x}{:02x}{:02x}", self.r, self.g, self.b)?;
+        if let Some(a) = self.a {
+            write!(f, "{:02x}", a)?;
+        }
+        Ok(())
+    }
 }
 
-pub struct Modules<const N: usize> {
-    modules: [Box<dyn Module>; N],
-    cache: [Option<String>; N],
-    tick: usize,
+/// Resolves a color name or lowercases a `#RRGGBB`/`#RRGGBBAA` color,
+/// asserting in debug builds that it is valid.
+fn normalize_color(color: String) -> String {
+    match Color::parse(&color) {
+        Ok(parsed) => parsed.to_string(),
+        Err(e) => {
+            debug_assert!(false, "{}", e);
+            color
+        }
+    }
 }
 
-fn map_optional(key: &str, val: Option<impl Display>) -> String {
-    val.map(|v| format!(", \"{}\": {}", key, v))
-        .unwrap_or_else(|| "".to_string())
+/// Palette shared by modules instead of hardcoding colors.
+#[derive(Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Theme {
+    pub fg: Color,
+    pub ok: Color,
+    pub warn: Color,
+    pub crit: Color,
+    pub bg: Color,
+    pub border: Color,
+    pub accent: Color,
+    /// Text color of blocks reporting a module error.
+    pub error: Color,
+    /// Text color of blocks that haven't refreshed in a while.
+    pub stale: Color,
+    /// Filled in from the config's `[glyphs]` section.
+    #[serde(skip)]
+    pub glyphs: Glyphs,
 }
 
-fn map_optional_quotes(key: &str, val: Option<impl Display>) -> String {
-    val.map(|v| format!(", \"{}\": \"{}\"", key, v))
-        .unwrap_or_else(|| "".to_string())
-}
+/// Color of error blocks unless the theme sets one.
+pub const DEFAULT_ERROR_COLOR: Color = Color::rgb(0xff, 0x00, 0x00);
 
-impl<const N: usize> Modules<N> {
-    pub fn new(modules: [Box<dyn Module>; N]) -> Self {
-        Modules {
-            modules,
-            cache: [(); N].map(|_| None),
-            tick: 0,
+/// Color of stale blocks unless the theme sets one.
+pub const DEFAULT_STALE_COLOR: Color = Color::rgb(0x62, 0x72, 0xa4);
+
+impl Default for Theme {
+    fn default() -> Self {
+        Theme {
+            fg: Color::rgb(0xff, 0xff, 0xff),
+            ok: Color::rgb(0x50, 0xfa, 0x7b),
+            warn: Color::rgb(0xf1, 0xfa, 0x8c),
+            crit: Color::rgb(0xff, 0x55, 0x55),
+            bg: Color::rgb(0x44, 0x47, 0x5a),
+            border: Color::rgb(0x00, 0x00, 0x00),
+            accent: Color::rgb(0xbd, 0x93, 0xf9),
+            error: DEFAULT_ERROR_COLOR,
+            stale: DEFAULT_STALE_COLOR,
+            glyphs: Glyphs::default(),
         }
     }
+}
 
-    pub fn combine_modules(&mut self) -> String {
-        let mut res = String::from("[");
+impl Theme {
+    pub fn fg(&self) -> String {
+        self.fg.to_string()
+    }
 
-        if let Some(mods) = self
-            .modules
-            .iter_mut()
-            .enumerate()
-            .filter_map(|(i, v)| {
-                if self.tick % v.rate() != 0 {
-                    return self.cache[i].clone();
-                }
+    pub fn ok(&self) -> String {
+        self.ok.to_string()
+    }
 
-                let mut res_inner = String::with_capacity(20);
-                match v.get_output() {
-                    Ok(modout) => {
-                        write!(res_inner, "{{\"full_text\": \"{}\"", modout.content).unwrap();
-                        res_inner += &map_optional_quotes("color", modout.color_fg);
-                        res_inner += &map_optional_quotes("background", modout.color_bg);
-                        res_inner += &map_optional_quotes("border", modout.border);
-                        res_inner += &map_optional("separator", modout.separator);
-                        res_inner +=
-                            &map_optional("separator_block_width", modout.separator_block_width);
-                        res_inner += "}";
-                    }
-                    Err(Some(mes)) if !mes.is_empty() => {
-                        write!(
-                            res_inner,
-                            "{{\"full_text\": \"{}\", \"color\": \"#ff0000\"}}",
-                            mes
-                        )
-                        .unwrap();
-                    }
-                    Err(_) => {
-                        if v.rate() > 1 {
-                            self.cache[i] = None;
-                        }
-                        return None;
-                    }
-                }
+    pub fn warn(&self) -> String {
+        self.warn.to_string()
+    }
 
-                if v.rate() > 1 {
-                    self.cache[i] = Some(res_inner.clone());
-                }
-                Some(res_inner)
-            })
-            .reduce(|a, n| a + ", " + &n)
-        {
-            res += &mods;
-        }
+    pub fn crit(&self) -> String {
+        self.crit.to_string()
+    }
 
-        res += "]";
-        self.tick += 1;
-        res
+    pub fn bg(&self) -> String {
+        self.bg.to_string()
     }
-}
 
-pub struct DateTimeModule;
+    pub fn border(&self) -> String {
+        self.border.to_string()
+    }
 
-impl Module for DateTimeModule {
-    fn get_output(&mut self) -> ModuleRes {
-        let now = Local::now();
-        Ok(ModuleOutput::new(now.format("%d/%m/%y %H:%M").to_string()))
+    pub fn accent(&self) -> String {
+        self.accent.to_string()
+    }
+
+    /// Maps a 0–100 value onto the `ok` → `crit` gradient.
+    pub fn gradient_color(&self, value: f32) -> String {
+        self.ok.lerp(self.crit, value / 100.).to_string()
     }
 }
 
-pub struct RamModule {
-    system: System,
+/// Icon glyphs by name, shipped as Font Awesome codepoints.
+pub const DEFAULT_GLYPHS: [(&str, char); 22] = [
+    ("battery_empty", '\u{f244}'),
+    ("battery_quarter", '\u{f243}'),
+    ("battery_half", '\u{f242}'),
+    ("battery_three_quarters", '\u{f241}'),
+    ("battery_full", '\u{f240}'),
+    ("battery_plugged", '\u{f1e6}'),
+    ("volume", '\u{f028}'),
+    ("volume_muted", '\u{f026}'),
+    ("download", '\u{f063}'),
+    ("upload", '\u{f062}'),
+    ("brightness", '\u{f185}'),
+    ("containers", '\u{f395}'),
+    ("warning", '\u{f071}'),
+    ("throttle", '\u{f2c7}'),
+    ("vpn", '\u{f023}'),
+    ("git_branch", '\u{e0a0}'),
+    ("microphone", '🎤'),
+    ("power_performance", '\u{f0e7}'),
+    ("power_balanced", '\u{f24e}'),
+    ("power_saver", '\u{f06c}'),
+    ("notifications", '\u{f0f3}'),
+    ("notifications_off", '\u{f1f6}'),
+];
+
+/// Icons looked up by name, so they can be remapped for other icon fonts.
+/// Configured glyphs override single entries of [`DEFAULT_GLYPHS`].
+#[derive(Clone, Debug, PartialEq)]
+pub struct Glyphs {
+    glyphs: Vec<(String, char)>,
 }
 
-impl RamModule {
-    pub fn new() -> Self {
-        RamModule {
-            system: System::new(),
+impl Default for Glyphs {
+    fn default() -> Self {
+        Glyphs {
+            glyphs: DEFAULT_GLYPHS
+                .iter()
+                .map(|(name, glyph)| (name.to_string(), *glyph))
+                .collect(),
         }
     }
 }
 
-impl Module for RamModule {
-    fn get_output(&mut self) -> ModuleRes {
-        self.system.refresh_memory();
-
-        let ktog = |v| v as f32 / 1024. / 1024.;
-        Ok(ModuleOutput::new(format!(
-            "{:.1}/{:.1} GiB",
-            ktog(self.system.used_memory()),
-            ktog(self.system.total_memory())
-        )))
+impl Glyphs {
+    /// Replaces the glyph called `name`, failing for names no module uses.
+    pub fn set(&mut self, name: &str, glyph: char) -> Result<(), String> {
+        let entry = self
+            .glyphs
+            .iter_mut()
+            .find(|(n, _)| n == name)
+            .ok_or_else(|| format!("unknown glyph `{}`", name))?;
+        entry.1 = glyph;
+        Ok(())
     }
 
-    fn rate(&self) -> usize {
-        3
+    pub fn get(&self, name: &str) -> char {
+        let glyph = self.glyphs.iter().find(|(n, _)| n == name).map(|(_, g)| *g);
+        debug_assert!(glyph.is_some(), "unknown glyph `{}`", name);
+        glyph.unwrap_or('?')
     }
-}
-
-fn percentage_to_char(v: f32) -> Option<char> {
-    let v = (7. * v / 100.) as u32;
-    char::from_u32(0x2581 + v)
-}
 
-pub struct CpuModule {
-    system: System,
+    /// Battery glyphs from empty to full.
+    pub fn battery_levels(&self) -> Vec<char> {
+        [
+            "battery_empty",
+            "battery_quarter",
+            "battery_half",
+            "battery_three_quarters",
+            "battery_full",
+        ]
+        .iter()
+        .map(|name| self.get(name))
+        .collect()
+    }
 }
 
-impl CpuModule {
-    pub fn new() -> Self {
-        CpuModule {
-            system: System::new(),
+impl<'de> Deserialize<'de> for Glyphs {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let overrides = std::collections::BTreeMap::<String, char>::deserialize(deserializer)?;
+        let mut glyphs = Glyphs::default();
+        for (name, glyph) in overrides {
+            glyphs.set(&name, glyph).map_err(serde::de::Error::custom)?;
         }
+        Ok(glyphs)
     }
 }
 
-impl Module for CpuModule {
-    fn get_output(&mut self) -> ModuleRes {
-        self.system.refresh_cpu();
-        sleep(Duration::from_millis(200));
-        self.system.refresh_cpu();
+/// Warning and critical levels for a reading such as CPU usage in percent.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Thresholds {
+    pub warn: f32,
+    pub crit: f32,
+}
 
-        let cpus = self.system.cpus();
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Level {
+    Normal,
+    Warn,
+    Crit,
+}
 
-        let cpu_sparkline = cpus
-            .iter()
-            .map(|c| percentage_to_char(c.cpu_usage()).unwrap_or(' '))
-            .fold("".to_string(), |a, n| a + &n.to_string());
+impl Thresholds {
+    pub const fn new(warn: f32, crit: f32) -> Self {
+        Thresholds { warn, crit }
+    }
 
-        let mut out = ModuleOutput::new(cpu_sparkline)
-            .with_color_bg("#44475a".to_string())
-            .with_border("#000000".to_string());
+    fn level(&self, value: f32) -> Level {
+        if value >= self.crit {
+            Level::Crit
+        } else if value >= self.warn {
+            Level::Warn
+        } else {
+            Level::Normal
+        }
+    }
 
-        if self.system.global_cpu_info().cpu_usage() > 80. {
-            out = out.with_color_fg("#ff5555".to_string());
+    /// Colors `out` with the theme's `warn` or `crit` once `value` reaches
+    /// that level, and marks it urgent when critical.
+    pub fn apply(&self, value: f32, theme: &Theme, out: ModuleOutput) -> ModuleOutput {
+        match self.level(value) {
+            Level::Normal => out,
+            Level::Warn => out.with_color_fg(theme.warn()),
+            Level::Crit => out.with_color_fg(theme.crit()).with_urgent(true),
         }
+    }
+}
 
-        Ok(out)
+/// Blends from `ok` at `warn` to `crit_color` at `crit`, staying `ok` below
+/// the range and `crit_color` above it. An inverted range, with `crit` at or
+/// below `warn`, has no blend: values switch straight from `ok` to
+/// `crit_color` at `crit`.
+pub fn lerp_color(value: f32, warn: f32, crit: f32, ok: Color, crit_color: Color) -> Color {
+    if value >= crit {
+        return crit_color;
     }
+    if crit <= warn {
+        return ok;
+    }
+    ok.lerp(crit_color, (value - warn) / (crit - warn))
 }
 
-pub struct TemperatureModule {
-    system: System,
+#[derive(Default, PartialEq)]
+pub struct ModuleOutput {
+    content: String,
+    short_text: Option<String>,
+    color_fg: Option<String>,
+    color_bg: Option<String>,
+    border: Option<String>,
+    border_top: Option<usize>,
+    border_bottom: Option<usize>,
+    border_left: Option<usize>,
+    border_right: Option<usize>,
+    separator: Option<bool>,
+    separator_block_width: Option<usize>,
+    max_len: Option<usize>,
+    padding: (usize, usize),
+    min_width: Option<MinWidth>,
+    align: Option<Align>,
+    urgent: Option<bool>,
+    name: Option<String>,
+    instance: Option<String>,
+    /// Content is replaced by padding filling the bar, see [`SpacerModule::flexible`].
+    flexible: bool,
+    /// Raw readings behind the content, for the JSON output format.
+    metrics: Vec<(&'static str, f64)>,
 }
 
-impl TemperatureModule {
-    pub fn new() -> Self {
-        TemperatureModule {
-            system: System::new(),
+impl ModuleOutput {
+    pub fn new(content: String) -> Self {
+        ModuleOutput {
+            content,
+            short_text: None,
+            color_fg: None,
+            color_bg: None,
+            border: None,
+            border_top: None,
+            border_bottom: None,
+            border_left: None,
+            border_right: None,
+            separator: None,
+            separator_block_width: None,
+            max_len: None,
+            padding: (0, 0),
+            min_width: None,
+            align: None,
+            urgent: None,
+            name: None,
+            instance: None,
+            flexible: false,
+            metrics: Vec::new(),
         }
     }
-}
 
-impl Module for TemperatureModule {
-    fn get_output(&mut self) -> ModuleRes {
-        self.system.refresh_components_list();
-        self.system.refresh_components();
+    pub fn content(&self) -> &str {
+        &self.content
+    }
 
-        let cpu = self
-            .system
-            .components()
+    /// Attaches a raw reading, e.g. usage in percent, that frontends of the
+    /// JSON output format can render themselves.
+    pub fn with_metric(mut self, key: &'static str, value: f64) -> Self {
+        self.metrics.push((key, value));
+        self
+    }
+
+    /// The reading attached as `key` with [`Self::with_metric`], if any.
+    pub fn metric(&self, key: &str) -> Option<f64> {
+        self.metrics
             .iter()
-            .find(|c| c.label() == "CPU")
-            .ok_or_else(|| "CPU unavailable".to_string())?;
+            .find(|(k, _)| *k == key)
+            .map(|(_, value)| *value)
+    }
 
-        Ok(ModuleOutput::new(format!("{}°C", cpu.temperature())))
+    /// Marks the block as a flexible spacer, stretched to fill the bar.
+    pub fn with_flexible(mut self, flexible: bool) -> Self {
+        self.flexible = flexible;
+        self
     }
 
-    fn rate(&self) -> usize {
-        5
+    pub fn with_content(mut self, content: String) -> Self {
+        self.content = content;
+        self
     }
-}
 
-pub struct DiskSpaceModule {
-    dev: &'static str,
-    system: System,
-}
+    /// Puts `prefix` in front of the content.
+    pub fn with_prefix(mut self, prefix: &str) -> Self {
+        self.content.insert_str(0, prefix);
+        self
+    }
 
-impl DiskSpaceModule {
-    pub fn new(dev: &'static str) -> Self {
-        DiskSpaceModule {
-            dev,
-            system: System::new(),
+    /// Appends `suffix` to the content, e.g. a unit.
+    pub fn with_suffix(mut self, suffix: &str) -> Self {
+        self.content.push_str(suffix);
+        self
+    }
+
+    /// Shorter content i3bar falls back to when the bar runs out of space.
+    pub fn with_short_text(mut self, short_text: String) -> Self {
+        self.short_text = Some(short_text);
+        self
+    }
+
+    /// Derives `short_text` from `name: value` style content, unless already set.
+    pub fn auto_short(mut self) -> Self {
+        if self.short_text.is_none() {
+            self.short_text = auto_short_text(&self.content);
         }
+        self
     }
-}
 
-impl Module for DiskSpaceModule {
-    fn get_output(&mut self) -> ModuleRes {
-        self.system.refresh_disks();
-        self.system.refresh_disks_list();
+    pub fn with_color_fg(mut self, color: String) -> Self {
+        self.color_fg = Some(normalize_color(color));
+        self
+    }
 
-        let disk = self
-            .system
-            .disks()
-            .iter()
-            .find(|d| d.name() == self.dev)
-            .ok_or_else(|| "Disk unavailable".to_string())?;
+    pub fn with_color_bg(mut self, color: String) -> Self {
+        self.color_bg = Some(normalize_color(color));
+        self
+    }
 
-        Ok(ModuleOutput::new(format!(
-            "{} GiB",
-            disk.available_space() / 1024u64.pow(3)
-        )))
+    pub fn with_border(mut self, border: String) -> Self {
+        self.border = Some(normalize_color(border));
+        self
     }
 
-    fn rate(&self) -> usize {
-        5
+    pub fn with_border_top(mut self, width: usize) -> Self {
+        self.border_top = Some(width);
+        self
     }
-}
 
-pub struct NetworkModule {
-    device: &'static str,
-    name: Option<&'static str>,
-}
+    pub fn with_border_bottom(mut self, width: usize) -> Self {
+        self.border_bottom = Some(width);
+        self
+    }
 
-impl NetworkModule {
-    pub fn new(device: &'static str) -> Self {
-        NetworkModule { device, name: None }
+    pub fn with_border_left(mut self, width: usize) -> Self {
+        self.border_left = Some(width);
+        self
     }
 
-    pub fn with_name(mut self, name: &'static str) -> Self {
-        self.name = Some(name);
+    pub fn with_border_right(mut self, width: usize) -> Self {
+        self.border_right = Some(width);
         self
     }
-}
 
-impl Module for NetworkModule {
-    fn get_output(&mut self) -> ModuleRes {
-        let dbus = Connection::new_system().map_err(|_| "dbus unavailable".to_string())?;
-        let nm = NetworkManager::new(&dbus);
+    pub fn with_separator(mut self, seperator: bool) -> Self {
+        self.separator = Some(seperator);
+        self
+    }
 
-        let name = self.name.unwrap_or(self.device);
-        let dev = nm.get_device_by_ip_iface(self.device).map_err(|_| None)?;
+    pub fn with_separator_block_width(mut self, separator_block_width: usize) -> Self {
+        self.separator_block_width = Some(separator_block_width);
+        self
+    }
 
-        let ip_from_addr = |addr: Vec<Vec<u32>>| {
-            addr.iter()
-                .flatten()
-                .next()
-                .map(|ip| format!(" {}", Ipv4Addr::from(ip.to_be())))
-                .unwrap_or_else(|| "".to_string())
-        };
-        match dev {
-            Device::WiFi(dev) => {
-                let ap = dev.active_access_point().unwrap();
-                if let (Ok(ssid), Ok(strength), Ok(freq), Ok(Ok(addr))) = (
-                    ap.ssid(),
-                    ap.strength(),
-                    ap.frequency(),
-                    dev.ip4_config().map(|conf| conf.addresses()),
-                ) {
-                    Ok(ModuleOutput::new(format!(
-                        "{}: ({:3}% at {}, {} Mb/s){}",
-                        name,
-                        strength,
-                        ssid,
-                        freq / 1024,
-                        ip_from_addr(addr)
-                    ))
-                    .with_color_fg("#50fa7b".to_string()))
-                } else {
-                    Ok(ModuleOutput::new(format!("{}: down", name))
-                        .with_color_fg("#ff5555".to_string()))
-                }
-            }
-            Device::Ethernet(dev) => {
-                if let (Ok(true), Ok(speed), Ok(Ok(addr))) = (
-                    dev.carrier(),
-                    dev.speed(),
-                    dev.ip4_config().map(|conf| conf.addresses()),
-                ) {
-                    Ok(ModuleOutput::new(format!(
-                        "{}: ({} Mb/s){}",
-                        name,
-                        speed,
-                        ip_from_addr(addr)
-                    ))
-                    .with_color_fg("#50fa7b".to_string()))
-                } else {
-                    Ok(ModuleOutput::new(format!("{}: down", name))
-                        .with_color_fg("#ff5555".to_string()))
-                }
-            }
-            _ => Err(Some("Unsupported device".to_string())),
-        }
+    /// Truncates the content to `max_len` chars with a trailing `…` when serialized.
+    pub fn with_max_len(mut self, max_len: usize) -> Self {
+        self.max_len = Some(max_len);
+        self
     }
 
-    fn rate(&self) -> usize {
-        5
+    /// Pads the content with spaces on each side when serialized.
+    pub fn with_padding(mut self, left: usize, right: usize) -> Self {
+        self.padding = (left, right);
+        self
     }
-}
 
-pub struct BatteryModule<const N: usize> {
-    dev_path: [PathBuf; N],
-}
+    pub fn with_min_width(mut self, min_width: MinWidth) -> Self {
+        self.min_width = Some(min_width);
+        self
+    }
 
-impl<const N: usize> BatteryModule<N> {
-    pub fn new(path: [&str; N]) -> Self {
-        BatteryModule {
-            dev_path: path.map(PathBuf::from),
-        }
+    pub fn with_align(mut self, align: Align) -> Self {
+        self.align = Some(align);
+        self
     }
-}
 
-impl<const N: usize> Module for BatteryModule<N> {
-    fn get_output(&mut self) -> ModuleRes {
-        let get_measure = |file: &str| {
-            self.dev_path
-                .iter()
-                .map(|p| {
-                    read_to_string(p.join(file))
-                        .map(|v| v.trim().parse::<u64>().ok())
-                        .ok()
-                        .flatten()
-                })
-                .reduce(|a, n| Some(a? + n?))
-                .flatten()
+    pub fn with_urgent(mut self, urgent: bool) -> Self {
+        self.urgent = Some(urgent);
+        self
+    }
+
+    /// The block's i3bar `name`, for scripts that inspect the bar's output.
+    pub fn with_name(mut self, name: String) -> Self {
+        self.name = Some(name);
+        self
+    }
+
+    /// Identifies the block in click events sent back by i3bar.
+    pub fn with_instance(mut self, instance: String) -> Self {
+        self.instance = Some(instance);
+        self
+    }
+
+    fn full_text(&self) -> Cow<'_, str> {
+        let text = match self.max_len {
+            Some(max_len) => Cow::Owned(truncate(&self.content, max_len)),
+            None => Cow::Borrowed(self.content.as_str()),
         };
-        let ecap = get_measure("charge_full").ok_or(None)?;
-        let enow = get_measure("charge_now").ok_or(None)?;
-        let cnow = get_measure("current_now").ok_or(None)?;
-        let perc = (100 * enow) / ecap;
+        match self.padding {
+            (0, 0) => text,
+            (left, right) => Cow::Owned(format!("{:left$}{}{:right$}", "", text, "")),
+        }
+    }
+}
 
-        let mut hours_left = 0.;
-        let mut mins_left = 0.;
-        let mut secs_left = 0.;
+/// Why a module produced no regular block.
+#[derive(Debug, PartialEq, Eq)]
+pub enum ModuleError {
+    /// Nothing to show right now; the block is hidden until the next refresh.
+    Hidden,
+    /// Transient failure, shown as a red block.
+    Message(String),
+    /// The module can never work, e.g. its device doesn't exist; it is
+    /// dropped and not polled again.
+    Fatal(String),
+}
 
-        let mut out = ModuleOutput::new("".to_string());
-        let bat = char::from_u32(0xf244 - ((4 * perc) / 100) as u32).unwrap_or('');
-        if let Some(state) = self
-            .dev_path
-            .iter()
-            .map(|p| match read_to_string(p.join("status")) {
-                Ok(mes) if mes.trim() == "Charging" => 1,
-                Ok(mes) if mes.trim() == "Discharging" => -1,
-                _ => 0,
-            })
-            .find(|v| *v != 0)
-        {
-            match state {
-                1 => {
-                    out = out.with_color_fg("#50fa7b".to_string());
-                    hours_left = (ecap - enow) as f32 / cnow as f32;
+/// Keeps the older `Err(None)`/`Err(Some(msg))` convention working.
+impl From<Option<String>> for ModuleError {
+    fn from(err: Option<String>) -> Self {
+        match err {
+            None => ModuleError::Hidden,
+            Some(mes) => ModuleError::Message(mes),
+        }
+    }
+}
+
+impl From<String> for ModuleError {
+    fn from(mes: String) -> Self {
+        ModuleError::Message(mes)
+    }
+}
+
+pub type ModuleRes = Result<ModuleOutput, ModuleError>;
+pub trait Module {
+    fn get_output(&mut self) -> ModuleRes;
+    /// Number of ticks between refreshes. A tick lasts the bar's interval,
+    /// [`DEFAULT_INTERVAL`] unless configured otherwise, so a shorter
+    /// interval refreshes every module proportionally more often.
+    fn rate(&self) -> usize {
+        1
+    }
+    /// Consecutive hidden results after which the module is considered
+    /// permanently unavailable and no longer polled.
+    fn hidden_limit(&self) -> Option<usize> {
+        None
+    }
+    /// Switches between the compact and detailed view, for modules that have both.
+    fn set_expanded(&mut self, _expanded: bool) {}
+    /// Called when the module's block is clicked or scrolled on.
+    fn on_click(&mut self, _event: &ClickEvent) {}
+    /// Width of the widest content the module can show, used as the block's
+    /// `min_width` unless it sets one, so the bar doesn't shift as it changes.
+    fn min_width(&self) -> Option<MinWidth> {
+        None
+    }
+}
+
+impl<M: Module + ?Sized> Module for Box<M> {
+    fn get_output(&mut self) -> ModuleRes {
+        (**self).get_output()
+    }
+
+    fn rate(&self) -> usize {
+        (**self).rate()
+    }
+
+    fn hidden_limit(&self) -> Option<usize> {
+        (**self).hidden_limit()
+    }
+
+    fn set_expanded(&mut self, expanded: bool) {
+        (**self).set_expanded(expanded);
+    }
+
+    fn on_click(&mut self, event: &ClickEvent) {
+        (**self).on_click(event);
+    }
+
+    fn min_width(&self) -> Option<MinWidth> {
+        (**self).min_width()
+    }
+}
+
+pub const BUTTON_LEFT: u8 = 1;
+pub const BUTTON_RIGHT: u8 = 3;
+pub const BUTTON_SCROLL_UP: u8 = 4;
+pub const BUTTON_SCROLL_DOWN: u8 = 5;
+
+/// A click event as sent by i3bar on stdin.
+#[derive(Deserialize)]
+pub struct ClickEvent {
+    pub instance: Option<String>,
+    pub button: u8,
+}
+
+impl ClickEvent {
+    /// Parses one line of i3bar's click event stream, an endless JSON array
+    /// where each event sits on its own line.
+    pub fn parse(line: &str) -> Option<ClickEvent> {
+        let line = line.trim().trim_start_matches(['[', ',']);
+        serde_json::from_str(line).ok()
+    }
+}
+
+/// Ticks a highlight lasts unless the command says otherwise.
+pub const DEFAULT_HIGHLIGHT_TICKS: usize = 5;
+
+/// A command from another program, one per line on the control socket:
+///
+/// - `highlight <module> [<color>] [<ticks>]` colors the module's block, by
+///   default in the theme's accent color for [`DEFAULT_HIGHLIGHT_TICKS`].
+/// - `clear <module>` ends a highlight early.
+///
+/// Modules are named by their keys, see [`Modules::with_keys`], and colors
+/// are written as in the config.
+#[derive(Debug, PartialEq)]
+pub enum Control {
+    Highlight {
+        module: String,
+        color: Option<Color>,
+        ticks: usize,
+    },
+    Clear {
+        module: String,
+    },
+}
+
+impl Control {
+    pub fn parse(line: &str) -> Result<Control, String> {
+        let mut words = line.split_whitespace();
+        let command = words.next().ok_or("empty command")?;
+        let module = words
+            .next()
+            .ok_or_else(|| format!("`{}` needs a module", command))?
+            .to_string();
+        let control = match command {
+            "highlight" => {
+                let (mut color, mut ticks) = (None, None);
+                for word in words.by_ref() {
+                    match word.parse::<usize>() {
+                        Ok(0) => return Err("a highlight must last at least a tick".to_string()),
+                        Ok(n) if ticks.is_none() => ticks = Some(n),
+                        Err(_) if color.is_none() => color = Some(Color::parse(word)?),
+                        _ => return Err(format!("unexpected `{}`", word)),
+                    }
                 }
-                -1 => {
-                    out = out.with_color_fg("#ff5555".to_string());
-                    hours_left = enow as f32 / cnow as f32;
+                Control::Highlight {
+                    module,
+                    color,
+                    ticks: ticks.unwrap_or(DEFAULT_HIGHLIGHT_TICKS),
                 }
-                _ => (),
-            }
-        }
-        mins_left = hours_left.fract() * 60.;
-
-        if hours_left.floor() > 0.0 {
-            out = out.with_content(format!(
-                "{} {}% [{:.0}h {:.0}m]",
-                bat,
-                perc,
-                hours_left.floor(),
-                mins_left.floor()
-            ));
-        } else if mins_left > 0.0 {
-            out = out.with_content(format!(
-                "{} {}% [{:.0}m]",
-                bat,
-                perc,
-                mins_left.floor()
-            ));
-        } else {
-            out = out.with_content(format!(
-                "{} {}%",
-                bat,
-                perc,
-            ));
+            }
+            "clear" => Control::Clear { module },
+            other => return Err(format!("unknown command `{}`", other)),
+        };
+        match words.next() {
+            Some(word) => Err(format!("unexpected `{}`", word)),
+            None => Ok(control),
+        }
+    }
+}
+
+/// Builds [`Modules`] from a comma-separated list of modules. An entry may be
+/// written as `"name" => module` to set the i3bar `name` of its blocks.
+#[macro_export]
+macro_rules! modules {
+    (@acc [$($acc:expr),*]) => {
+        $crate::modules::Modules::new(vec![$($acc),*])
+    };
+    (@acc [$($acc:expr),*] $name:literal => $x:expr $(, $($rest:tt)*)?) => {
+        $crate::modules!(@acc [$($acc,)* Box::new(
+            $crate::modules::Decorated::new($x).with_name($name)
+        ) as Box<dyn $crate::modules::Module>] $($($rest)*)?)
+    };
+    (@acc [$($acc:expr),*] $x:expr $(, $($rest:tt)*)?) => {
+        $crate::modules!(@acc [$($acc,)* Box::new($x) as Box<dyn $crate::modules::Module>] $($($rest)*)?)
+    };
+    (@acc [$($acc:expr),*] $($bad:tt)+) => {
+        compile_error!(concat!(
+            "expected a module or `\"name\" => module`, found `",
+            stringify!($($bad)+),
+            "`"
+        ))
+    };
+    ($($rest:tt)*) => {
+        $crate::modules!(@acc [] $($rest)*)
+    };
+}
+
+#[derive(Clone, PartialEq, Eq)]
+pub enum OutputFormat {
+    /// JSON blocks following the i3bar protocol.
+    I3bar,
+    /// One JSON object per line for a Waybar `custom` module with
+    /// `"return-type": "json"`, colors rendered as Pango markup and blocks
+    /// joined by the given separator.
+    Waybar(String),
+    /// Bare module contents joined by the given separator, e.g. for `xsetroot -name`.
+    Plain(String),
+    /// Only the blocks that changed since the previous line, for custom
+    /// frontends: `{"changes": [[index, block], ...]}` with i3bar blocks, and
+    /// `null` for a block that went away. Nothing is written when no block changed.
+    Diff,
+    /// One JSON object per line keyed by module, for custom frontends:
+    /// `{"cpu": {"text": "12%", "usage": 12.3}, "ram": null}`, with each
+    /// module's metrics next to its text, `{"error": ...}` for a failed
+    /// module and `null` for a hidden one.
+    Json,
+}
+
+fn push_json_char(buf: &mut String, c: char) {
+    match c {
+        '"' => *buf += "\\\"",
+        '\\' => *buf += "\\\\",
+        '\n' => *buf += "\\n",
+        '\t' => *buf += "\\t",
+        c if (c as u32) < 0x20 => write!(buf, "\\u{:04x}", c as u32).unwrap(),
+        c => buf.push(c),
+    }
+}
+
+fn markup_entity(c: char) -> Option<&'static str> {
+    match c {
+        '&' => Some("&amp;"),
+        '<' => Some("&lt;"),
+        '>' => Some("&gt;"),
+        '\'' => Some("&apos;"),
+        '"' => Some("&quot;"),
+        _ => None,
+    }
+}
+
+/// Escapes everything written through it for a JSON string.
+struct JsonEscaper<'a>(&'a mut String);
+
+impl std::fmt::Write for JsonEscaper<'_> {
+    fn write_str(&mut self, s: &str) -> std::fmt::Result {
+        s.chars().for_each(|c| push_json_char(self.0, c));
+        Ok(())
+    }
+}
+
+/// Escapes everything written through it for Pango markup inside a JSON string.
+struct MarkupEscaper<'a>(&'a mut String);
 
+impl std::fmt::Write for MarkupEscaper<'_> {
+    fn write_str(&mut self, s: &str) -> std::fmt::Result {
+        for c in s.chars() {
+            match markup_entity(c) {
+                Some(entity) => *self.0 += entity,
+                None => push_json_char(self.0, c),
+            }
         }
+        Ok(())
+    }
+}
 
-        Ok(out)
+/// Values written as bare JSON literals rather than strings.
+trait JsonLiteral {
+    fn write_json(&self, buf: &mut String);
+}
+
+impl<T: JsonLiteral + ?Sized> JsonLiteral for &T {
+    fn write_json(&self, buf: &mut String) {
+        (**self).write_json(buf);
     }
+}
 
-    fn rate(&self) -> usize {
-        5
+impl JsonLiteral for bool {
+    fn write_json(&self, buf: &mut String) {
+        *buf += if *self { "true" } else { "false" };
     }
 }
 
-pub struct SpacerModule<const N: usize> {
-    data: String,
+macro_rules! impl_json_integer {
+    ($($t:ty),*) => {$(
+        impl JsonLiteral for $t {
+            fn write_json(&self, buf: &mut String) {
+                write!(buf, "{}", self).unwrap();
+            }
+        }
+    )*};
 }
 
-impl<const N: usize> SpacerModule<N> {
-    pub fn new() -> Self {
-        let mut data = String::with_capacity(N);
-        for _ in 0..N {
-            data.push(' ');
+impl_json_integer!(u8, u16, u32, u64, usize, i32, i64);
+
+/// Whole numbers drop the `.0`, and NaN or infinities, which JSON can't
+/// represent, become `null`.
+impl JsonLiteral for f64 {
+    fn write_json(&self, buf: &mut String) {
+        if !self.is_finite() {
+            *buf += "null";
+        } else if self.fract() == 0. && self.abs() < 1e15 {
+            write!(buf, "{}", *self as i64).unwrap();
+        } else {
+            write!(buf, "{}", self).unwrap();
         }
-        SpacerModule { data }
     }
 }
 
-impl<const N: usize> Module for SpacerModule<N> {
-    fn get_output(&mut self) -> ModuleRes {
-        Ok(ModuleOutput::new(self.data.clone()))
+impl JsonLiteral for f32 {
+    fn write_json(&self, buf: &mut String) {
+        (*self as f64).write_json(buf);
+    }
+}
+
+fn map_optional(buf: &mut String, key: &str, val: Option<impl JsonLiteral>) {
+    if let Some(v) = val {
+        write!(buf, ", \"{}\": ", key).unwrap();
+        v.write_json(buf);
+    }
+}
+
+fn map_optional_quotes(buf: &mut String, key: &str, val: Option<impl Display>) {
+    if let Some(v) = val {
+        write!(buf, ", \"{}\": \"", key).unwrap();
+        write!(JsonEscaper(buf), "{}", v).unwrap();
+        buf.push('"');
+    }
+}
+
+fn map_optional_markup(buf: &mut String, key: &str, val: Option<impl Display>) {
+    if let Some(v) = val {
+        write!(buf, " {}='", key).unwrap();
+        write!(MarkupEscaper(buf), "{}", v).unwrap();
+        buf.push('\'');
+    }
+}
+
+fn write_joined<'a>(
+    buf: &mut String,
+    blocks: impl IntoIterator<Item = &'a str>,
+    separator: impl Fn(&mut String),
+) {
+    for (i, block) in blocks.into_iter().enumerate() {
+        if i > 0 {
+            separator(buf);
+        }
+        *buf += block;
+    }
+}
+
+/// Separator between blocks in the plain format unless configured otherwise.
+pub const DEFAULT_PLAIN_SEPARATOR: &str = "  ";
+
+/// Signal i3bar is asked to send when the bar is hidden. Its default,
+/// `SIGSTOP`, can't be handled, so `SIGTSTP` is requested instead and the
+/// refresh loop pauses itself until [`CONT_SIGNAL`] arrives.
+pub const STOP_SIGNAL: i32 = signal_hook::consts::SIGTSTP;
+/// Signal i3bar is asked to send when the bar is shown again.
+pub const CONT_SIGNAL: i32 = signal_hook::consts::SIGCONT;
+
+impl OutputFormat {
+    /// Printed once before the first line, if the target expects one.
+    /// `click_events` asks i3bar to report clicks on stdin.
+    ///
+    /// `separator_block_width` is advertised as the bar-wide default. A width
+    /// set on a block always wins over it; i3bar itself ignores the header
+    /// key, so blocks without their own width still carry the default.
+    pub fn header(
+        &self,
+        click_events: bool,
+        separator_block_width: Option<usize>,
+    ) -> Option<String> {
+        match self {
+            OutputFormat::I3bar => {
+                let mut header = format!(
+                    "{{\"version\": 1, \"stop_signal\": {}, \"cont_signal\": {}, \"click_events\": {}",
+                    STOP_SIGNAL, CONT_SIGNAL, click_events
+                );
+                map_optional(&mut header, "separator_block_width", separator_block_width);
+                header += "}\n[";
+                Some(header)
+            }
+            OutputFormat::Waybar(_)
+            | OutputFormat::Plain(_)
+            | OutputFormat::Diff
+            | OutputFormat::Json => None,
+        }
+    }
+
+    /// Printed once on shutdown, closing whatever the header opened.
+    pub fn footer(&self) -> Option<&'static str> {
+        match self {
+            OutputFormat::I3bar => Some("]"),
+            OutputFormat::Waybar(_)
+            | OutputFormat::Plain(_)
+            | OutputFormat::Diff
+            | OutputFormat::Json => None,
+        }
+    }
+
+    /// Put between emitted lines, e.g. the commas separating the elements
+    /// of i3bar's endless array.
+    pub fn line_separator(&self) -> &'static str {
+        match self {
+            OutputFormat::I3bar => ",",
+            OutputFormat::Waybar(_)
+            | OutputFormat::Plain(_)
+            | OutputFormat::Diff
+            | OutputFormat::Json => "",
+        }
+    }
+
+    /// Joins already serialized blocks into a single output line, appended to
+    /// `buf`. The diff format lists every block as changed, and the JSON
+    /// format expects `"key": block` members, see [`Modules::with_keys`].
+    pub fn write_line<'a>(&self, buf: &mut String, blocks: impl IntoIterator<Item = &'a str>) {
+        match self {
+            OutputFormat::Diff => {
+                let changes = blocks.into_iter().enumerate().map(|(i, b)| (i, Some(b)));
+                write_diff(buf, changes);
+            }
+            OutputFormat::I3bar => {
+                buf.push('[');
+                write_joined(buf, blocks, |buf| *buf += ", ");
+                buf.push(']');
+            }
+            OutputFormat::Waybar(separator) => {
+                *buf += "{\"text\": \"";
+                write_joined(buf, blocks, |buf| {
+                    MarkupEscaper(buf).write_str(separator).unwrap()
+                });
+                *buf += "\"}";
+            }
+            OutputFormat::Plain(separator) => write_joined(buf, blocks, |buf| *buf += separator),
+            OutputFormat::Json => {
+                buf.push('{');
+                write_joined(buf, blocks, |buf| *buf += ", ");
+                buf.push('}');
+            }
+        }
+    }
+
+    /// Serializes a single module output into a block of this format.
+    pub fn serialize_block(&self, out: &ModuleOutput) -> String {
+        let mut res = String::new();
+        self.write_block(&mut res, out);
+        res
+    }
+
+    /// Like [`Self::serialize_block`], but appends to `buf`.
+    pub fn write_block(&self, buf: &mut String, out: &ModuleOutput) {
+        match self {
+            OutputFormat::I3bar | OutputFormat::Diff => {
+                *buf += "{\"full_text\": \"";
+                JsonEscaper(buf).write_str(&out.full_text()).unwrap();
+                buf.push('"');
+                map_optional_quotes(buf, "short_text", out.short_text.as_ref());
+                map_optional_quotes(buf, "color", out.color_fg.as_ref());
+                map_optional_quotes(buf, "background", out.color_bg.as_ref());
+                map_optional_quotes(buf, "border", out.border.as_ref());
+                map_optional(buf, "border_top", out.border_top);
+                map_optional(buf, "border_bottom", out.border_bottom);
+                map_optional(buf, "border_left", out.border_left);
+                map_optional(buf, "border_right", out.border_right);
+                map_optional(buf, "separator", out.separator);
+                map_optional(buf, "separator_block_width", out.separator_block_width);
+                match &out.min_width {
+                    Some(MinWidth::Pixels(px)) => map_optional(buf, "min_width", Some(px)),
+                    Some(MinWidth::Text(text)) => map_optional_quotes(buf, "min_width", Some(text)),
+                    None => (),
+                }
+                map_optional_quotes(buf, "align", out.align);
+                map_optional(buf, "urgent", out.urgent);
+                map_optional_quotes(buf, "name", out.name.as_ref());
+                map_optional_quotes(buf, "instance", out.instance.as_ref());
+                buf.push('}');
+            }
+            OutputFormat::Waybar(_) => {
+                let styled = out.color_fg.is_some() || out.color_bg.is_some();
+                if styled {
+                    *buf += "<span";
+                    map_optional_markup(buf, "foreground", out.color_fg.as_ref());
+                    map_optional_markup(buf, "background", out.color_bg.as_ref());
+                    buf.push('>');
+                }
+                MarkupEscaper(buf).write_str(&out.full_text()).unwrap();
+                if styled {
+                    *buf += "</span>";
+                }
+            }
+            OutputFormat::Plain(_) => *buf += &out.full_text(),
+            OutputFormat::Json => {
+                *buf += "{\"text\": \"";
+                JsonEscaper(buf).write_str(&out.full_text()).unwrap();
+                buf.push('"');
+                map_optional_quotes(buf, "short_text", out.short_text.as_ref());
+                map_optional_quotes(buf, "color", out.color_fg.as_ref());
+                map_optional_quotes(buf, "background", out.color_bg.as_ref());
+                map_optional(buf, "urgent", out.urgent);
+                for (key, value) in &out.metrics {
+                    map_optional(buf, key, Some(value));
+                }
+                buf.push('}');
+            }
+        }
+    }
+
+    /// Appends a block reporting a module error in `color` to `buf`.
+    pub fn write_error(&self, buf: &mut String, mes: &str, color: Color) {
+        if *self == OutputFormat::Json {
+            *buf += "{\"error\": \"";
+            JsonEscaper(buf).write_str(mes).unwrap();
+            *buf += "\"}";
+            return;
+        }
+        self.write_block(
+            buf,
+            &ModuleOutput::new(mes.to_string()).with_color_fg(color.to_string()),
+        )
+    }
+}
+
+/// Writes a diff line out of `(index, block)` changes, `None` for removed blocks.
+fn write_diff<'a>(buf: &mut String, changes: impl IntoIterator<Item = (usize, Option<&'a str>)>) {
+    *buf += "{\"changes\": [";
+    for (n, (i, block)) in changes.into_iter().enumerate() {
+        if n > 0 {
+            *buf += ", ";
+        }
+        write!(buf, "[{}, {}]", i, block.unwrap_or("null")).unwrap();
+    }
+    *buf += "]}";
+}
+
+/// Whether a block rendered `age` ago counts as stale, never without a
+/// `stale_after` limit.
+fn is_stale(age: Duration, stale_after: Option<Duration>) -> bool {
+    stale_after.is_some_and(|limit| age > limit)
+}
+
+/// Shown for a failed module that gave no message.
+const UNKNOWN_ERROR: &str = "error";
+
+/// Longest delay, in ticks, before retrying a failing module.
+const MAX_BACKOFF_TICKS: usize = 300;
+
+/// Polls `module`, turning a panic into an error so one broken module can't
+/// take down the whole bar.
+fn poll(module: &mut dyn Module) -> ModuleRes {
+    panic::catch_unwind(AssertUnwindSafe(|| module.get_output())).unwrap_or_else(|payload| {
+        let mes = payload
+            .downcast_ref::<&str>()
+            .copied()
+            .or_else(|| payload.downcast_ref::<String>().map(String::as_str))
+            .unwrap_or("unknown cause");
+        Err(format!("panicked: {}", mes).into())
+    })
+}
+
+/// Ticks to wait before retrying a module after `failures` consecutive
+/// failures, doubling the regular `rate` each time up to [`MAX_BACKOFF_TICKS`].
+fn backoff_ticks(rate: usize, failures: u32) -> usize {
+    let factor = 1usize.checked_shl(failures).unwrap_or(usize::MAX);
+    rate.saturating_mul(factor).min(MAX_BACKOFF_TICKS.max(rate))
+}
+
+/// What to do when every module is hidden on a tick.
+#[derive(Clone, PartialEq, Eq)]
+pub enum EmptyLine {
+    /// Emit the empty line as is, e.g. `[]` for i3bar.
+    Emit,
+    /// Don't emit a line at all, leaving the previous one on the bar.
+    Skip,
+    /// Emit a single block with the given content instead.
+    Placeholder(String),
+}
+
+/// Time between two ticks of the main loop.
+pub const DEFAULT_INTERVAL: Duration = Duration::from_millis(1000);
+
+/// A [`Module::rate`] that never comes due again: the module renders on the
+/// first tick and afterwards only when triggered, e.g. by a signal or click.
+pub const ON_DEMAND: usize = usize::MAX;
+
+pub struct Modules {
+    modules: Vec<Box<dyn Module>>,
+    cache: Vec<Option<String>>,
+    /// The output each cached block was serialized from, so an unchanged
+    /// output can reuse its block instead of being serialized again.
+    last: Vec<Option<ModuleOutput>>,
+    /// Blocks as of the previous line, for the diff format.
+    sent: Vec<Option<String>>,
+    pending: Vec<bool>,
+    /// Whether each module is followed by another of the same group, hiding its separator.
+    grouped: Vec<bool>,
+    /// Modules that failed fatally and are no longer polled.
+    retired: Vec<bool>,
+    /// Modules switched off at runtime; skipped until switched back on.
+    enabled: Vec<bool>,
+    /// Consecutive failures of each module, and the tick before which it
+    /// isn't retried.
+    failures: Vec<u32>,
+    backoff_until: Vec<usize>,
+    /// Consecutive hidden results of each module.
+    hidden: Vec<usize>,
+    tick: usize,
+    interval: Duration,
+    format: OutputFormat,
+    on_empty: EmptyLine,
+    /// Output line, reused across renders to avoid reallocating it every tick.
+    line: String,
+    /// Whether to write the format's header, see [`Self::with_header`].
+    header: bool,
+    /// Whether a line went out already, so the next one needs the format's
+    /// line separator.
+    emitted: bool,
+    /// Separator settings for blocks that don't set their own.
+    default_separator: Option<bool>,
+    default_separator_block_width: Option<usize>,
+    /// Names identifying modules in the debug dump.
+    names: Vec<String>,
+    /// Stable keys of the modules in the JSON format's objects.
+    keys: Vec<String>,
+    /// Where to write a JSON line per render describing each module's result.
+    dump: Option<Box<dyn IoWrite + Send>>,
+    /// Width of each cached block's text in characters.
+    widths: Vec<usize>,
+    /// Bar width in characters that flexible spacers stretch to fill.
+    bar_width: Option<usize>,
+    /// Length in characters blocks are truncated to unless they set their own.
+    max_len: Option<usize>,
+    error_color: Color,
+    /// When each module last rendered a block, shown in `stale_color` once
+    /// older than `stale_after`.
+    refreshed: Vec<Instant>,
+    stale_after: Option<Duration>,
+    stale_color: Color,
+    /// Colors forced on modules by [`Control::Highlight`], with the last tick
+    /// they show on, and the color used when the command names none.
+    highlights: Vec<Option<(Color, usize)>>,
+    highlight_color: Color,
+    /// Color each cached block was rewritten in by [`Self::restyle`].
+    restyled: Vec<Option<Color>>,
+    /// Errors before `started + startup_grace` hide the block instead, e.g.
+    /// while the network comes up.
+    started: Instant,
+    startup_grace: Duration,
+}
+
+impl Modules {
+    pub fn new(modules: Vec<Box<dyn Module>>) -> Self {
+        Modules {
+            cache: modules.iter().map(|_| None).collect(),
+            last: modules.iter().map(|_| None).collect(),
+            sent: modules.iter().map(|_| None).collect(),
+            pending: modules.iter().map(|_| false).collect(),
+            grouped: modules.iter().map(|_| false).collect(),
+            retired: modules.iter().map(|_| false).collect(),
+            enabled: modules.iter().map(|_| true).collect(),
+            failures: modules.iter().map(|_| 0).collect(),
+            backoff_until: modules.iter().map(|_| 0).collect(),
+            hidden: modules.iter().map(|_| 0).collect(),
+            widths: modules.iter().map(|_| 0).collect(),
+            refreshed: modules.iter().map(|_| Instant::now()).collect(),
+            highlights: modules.iter().map(|_| None).collect(),
+            restyled: modules.iter().map(|_| None).collect(),
+            names: (1..=modules.len()).map(|i| format!("#{}", i)).collect(),
+            keys: (1..=modules.len()).map(|i| i.to_string()).collect(),
+            modules,
+            tick: 0,
+            interval: DEFAULT_INTERVAL,
+            format: OutputFormat::I3bar,
+            on_empty: EmptyLine::Emit,
+            line: String::new(),
+            header: true,
+            emitted: false,
+            default_separator: None,
+            default_separator_block_width: None,
+            dump: None,
+            bar_width: None,
+            max_len: None,
+            error_color: DEFAULT_ERROR_COLOR,
+            stale_after: None,
+            stale_color: DEFAULT_STALE_COLOR,
+            highlight_color: Theme::default().accent,
+            started: Instant::now(),
+            startup_grace: Duration::ZERO,
+        }
+    }
+
+    /// Groups consecutive modules sharing a name, so only the last block of
+    /// each group draws a separator.
+    pub fn with_groups(mut self, groups: &[Option<String>]) -> Self {
+        for (i, grouped) in self.grouped.iter_mut().enumerate() {
+            *grouped = match (groups.get(i), groups.get(i + 1)) {
+                (Some(Some(a)), Some(Some(b))) => a == b,
+                _ => false,
+            };
+        }
+        self
+    }
+
+    /// Names the modules in order, for the debug dump.
+    pub fn with_names(mut self, names: &[String]) -> Self {
+        for (name, new) in self.names.iter_mut().zip(names) {
+            name.clone_from(new);
+        }
+        self
+    }
+
+    /// Keys the modules in order in the JSON format's objects, by default
+    /// their position counting from 1.
+    pub fn with_keys(mut self, keys: &[String]) -> Self {
+        for (key, new) in self.keys.iter_mut().zip(keys) {
+            key.clone_from(new);
+        }
+        self
+    }
+
+    /// Writes one JSON line per render to `out`, giving each module's name
+    /// and whether it rendered, failed, was hidden or kept its cached block.
+    pub fn with_dump(mut self, out: Box<dyn IoWrite + Send>) -> Self {
+        self.dump = Some(out);
+        self
+    }
+
+    /// Sets the i3bar separator and its spacing for every block that doesn't
+    /// set them itself, e.g. to turn off the default separators globally.
+    pub fn with_default_separator(mut self, separator: Option<bool>, width: Option<usize>) -> Self {
+        self.default_separator = separator;
+        self.default_separator_block_width = width;
+        self
+    }
+
+    /// Sets the bar width in characters, which flexible spacers pad the line
+    /// out to. i3bar can't stretch blocks itself, so this lets blocks after a
+    /// spacer sit at the right edge. Text is measured in characters, without
+    /// separators or font differences, so the width may need some tuning.
+    pub fn with_bar_width(mut self, width: Option<usize>) -> Self {
+        self.bar_width = width;
+        self
+    }
+
+    /// Truncates every block to `max_len` characters with a trailing `…`,
+    /// keeping a runaway module from taking over the bar. A block's own
+    /// [`ModuleOutput::with_max_len`] wins.
+    pub fn with_max_len(mut self, max_len: Option<usize>) -> Self {
+        self.max_len = max_len;
+        self
+    }
+
+    /// Sets the text color of blocks reporting a module error.
+    pub fn with_error_color(mut self, color: Color) -> Self {
+        self.error_color = color;
+        self
+    }
+
+    /// Sets the color of highlights that don't name their own, see [`Control`].
+    pub fn with_highlight_color(mut self, color: Color) -> Self {
+        self.highlight_color = color;
+        self
+    }
+
+    /// Shows blocks that haven't refreshed for `stale_after` in `color`, so
+    /// outdated readings of slow modules stand out. Off by default.
+    pub fn with_stale_after(mut self, stale_after: Option<Duration>, color: Color) -> Self {
+        self.stale_after = stale_after;
+        self.stale_color = color;
+        self
+    }
+
+    /// Hides blocks of failing modules, rather than showing the error, for
+    /// the first `grace` after startup. Modules needing the network usually
+    /// fail until it's up; they keep being polled at their normal rate.
+    pub fn with_startup_grace(mut self, grace: Duration) -> Self {
+        self.startup_grace = grace;
+        self
+    }
+
+    pub fn with_empty_line(mut self, on_empty: EmptyLine) -> Self {
+        self.on_empty = on_empty;
+        self
+    }
+
+    pub fn with_output_format(mut self, format: OutputFormat) -> Self {
+        self.format = format;
+        self
+    }
+
+    /// Sets the separator between blocks; only affects the plain format, so
+    /// it must come after [`Self::with_output_format`].
+    pub fn with_plain_separator(mut self, separator: &str) -> Self {
+        if let OutputFormat::Plain(current) = &mut self.format {
+            *current = separator.to_string();
+        }
+        self
+    }
+
+    pub fn output_format(&self) -> &OutputFormat {
+        &self.format
+    }
+
+    /// The protocol header for the configured format, advertising the
+    /// default separator width if one is set.
+    pub fn header(&self, click_events: bool) -> Option<String> {
+        self.format
+            .header(click_events, self.default_separator_block_width)
+    }
+
+    /// Whether [`Self::write_header`] writes the header. A bar that restarts
+    /// the program may still be reading the previous instance's stream and
+    /// choke on a second header; without one, lines continue that stream,
+    /// so even the first is preceded by the line separator.
+    pub fn with_header(mut self, header: bool) -> Self {
+        self.header = header;
+        self
+    }
+
+    /// Writes the protocol header, if the format has one and it isn't
+    /// switched off, before the first line.
+    pub fn write_header<W: IoWrite + ?Sized>(
+        &mut self,
+        out: &mut W,
+        click_events: bool,
+    ) -> std::io::Result<()> {
+        if !self.header {
+            self.emitted = true;
+            return Ok(());
+        }
+        match self.header(click_events) {
+            Some(header) => writeln!(out, "{}", header),
+            None => Ok(()),
+        }
+    }
+
+    /// Sets the tick length; module rates are counted in ticks.
+    pub fn with_interval(mut self, interval: Duration) -> Self {
+        self.interval = interval;
+        self
+    }
+
+    pub fn interval(&self) -> Duration {
+        self.interval
+    }
+
+    pub fn len(&self) -> usize {
+        self.modules.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.modules.is_empty()
+    }
+
+    /// Swaps in the modules and settings of `next`, e.g. built from an
+    /// edited config. The output format, debug dump and stream position stay
+    /// as they are, since a header may already be out; every module renders
+    /// afresh.
+    pub fn reload(&mut self, next: Modules) {
+        let format = std::mem::replace(&mut self.format, OutputFormat::I3bar);
+        let dump = self.dump.take();
+        if next.format != format {
+            log::warn!("output format can't change on reload, keeping it");
+        }
+        *self = Modules {
+            format,
+            dump,
+            header: self.header,
+            emitted: self.emitted,
+            ..next
+        };
+    }
+
+    /// Makes the module at `index` refresh on the next render regardless of its rate.
+    pub fn trigger(&mut self, index: usize) {
+        if let Some(pending) = self.pending.get_mut(index) {
+            *pending = true;
+        }
+    }
+
+    pub fn trigger_all(&mut self) {
+        self.pending.iter_mut().for_each(|p| *p = true);
+    }
+
+    /// Switches the module at `index` off, or back on with a fresh render.
+    pub fn toggle(&mut self, index: usize) {
+        if let Some(enabled) = self.enabled.get_mut(index) {
+            *enabled = !*enabled;
+            self.trigger(index);
+        }
+    }
+
+    /// Forwards a click to the module whose block it targets and refreshes it.
+    pub fn click(&mut self, event: &ClickEvent) {
+        let index = event
+            .instance
+            .as_ref()
+            .and_then(|i| i.parse::<usize>().ok());
+        if let Some(index) = index.filter(|i| *i < self.modules.len()) {
+            self.modules[index].on_click(event);
+            self.trigger(index);
+        }
+    }
+
+    /// Switches the view of the module at `index` and refreshes it on the next render.
+    pub fn set_expanded(&mut self, index: usize, expanded: bool) {
+        if let Some(module) = self.modules.get_mut(index) {
+            module.set_expanded(expanded);
+            self.trigger(index);
+        }
+    }
+
+    /// Renders the next line, or `None` if it should not be emitted.
+    pub fn combine_modules(&mut self) -> Option<&str> {
+        self.render(true)
+    }
+
+    /// Re-renders the current line out of schedule, only refreshing
+    /// triggered modules and leaving the tick count untouched.
+    pub fn refresh(&mut self) -> Option<&str> {
+        self.render(false)
+    }
+
+    /// Renders the next line and writes it to `out` with a newline, preceded
+    /// by the format's line separator unless it's the first. The line goes
+    /// out in a single write, so a sink that reconnects between writes never
+    /// receives a partial line.
+    pub fn write_line<W: IoWrite + ?Sized>(&mut self, out: &mut W) -> std::io::Result<()> {
+        self.write_rendered(true, out)
+    }
+
+    /// Like [`Self::write_line`], but re-renders out of schedule as [`Self::refresh`] does.
+    pub fn write_refresh<W: IoWrite + ?Sized>(&mut self, out: &mut W) -> std::io::Result<()> {
+        self.write_rendered(false, out)
+    }
+
+    fn write_rendered<W: IoWrite + ?Sized>(
+        &mut self,
+        timed: bool,
+        out: &mut W,
+    ) -> std::io::Result<()> {
+        if self.render(timed).is_none() {
+            return Ok(());
+        }
+        let separator = if self.emitted {
+            self.format.line_separator()
+        } else {
+            ""
+        };
+        self.line.insert_str(0, separator);
+        self.line.push('\n');
+        let res = out.write_all(self.line.as_bytes());
+        self.line.pop();
+        self.line.drain(..separator.len());
+        res?;
+        self.emitted = true;
+        out.flush()
+    }
+
+    /// Writes the blocks that differ from the previous line, if any.
+    fn render_diff(&mut self) -> Option<&str> {
+        let mut changes = Vec::new();
+        for (i, sent) in self.sent.iter_mut().enumerate() {
+            let block = self.cache[i].as_ref().filter(|_| self.enabled[i]);
+            if block != sent.as_ref() {
+                sent.clone_from(&block.cloned());
+                changes.push(i);
+            }
+        }
+        if changes.is_empty() {
+            return None;
+        }
+        let changes = changes.into_iter().map(|i| (i, self.sent[i].as_deref()));
+        write_diff(&mut self.line, changes);
+        Some(&self.line)
+    }
+
+    /// Writes every module's block keyed by [`Self::with_keys`], `null` for
+    /// hidden or disabled ones.
+    fn render_object(&mut self) -> Option<&str> {
+        let members: Vec<String> = (0..self.modules.len())
+            .map(|i| {
+                let mut member = String::from("\"");
+                JsonEscaper(&mut member).write_str(&self.keys[i]).unwrap();
+                member += "\": ";
+                match self.cache[i].as_ref().filter(|_| self.enabled[i]) {
+                    Some(block) => member += block,
+                    None => member += "null",
+                }
+                member
+            })
+            .collect();
+        self.format
+            .write_line(&mut self.line, members.iter().map(String::as_str));
+        Some(&self.line)
+    }
+
+    /// Polls every module once, outside the normal schedule, returning each
+    /// module's name and result.
+    pub fn check(&mut self) -> Vec<(&str, ModuleRes)> {
+        self.names
+            .iter()
+            .map(String::as_str)
+            .zip(self.modules.iter_mut().map(|m| poll(m.as_mut())))
+            .collect()
+    }
+
+    /// Applies a control command, naming modules by their keys, see
+    /// [`Self::with_keys`].
+    pub fn control(&mut self, command: &Control) -> Result<(), String> {
+        let module = match command {
+            Control::Highlight { module, .. } | Control::Clear { module } => module,
+        };
+        let i = self
+            .keys
+            .iter()
+            .position(|key| key == module)
+            .ok_or_else(|| format!("no module `{}`", module))?;
+        self.highlights[i] = match command {
+            Control::Highlight { color, ticks, .. } => Some((
+                color.unwrap_or(self.highlight_color),
+                self.tick.saturating_add(*ticks),
+            )),
+            Control::Clear { .. } => None,
+        };
+        self.restyle();
+        Ok(())
+    }
+
+    /// Recolors cached blocks that are highlighted or went stale, see
+    /// [`Self::control`] and [`Self::with_stale_after`], and restores them
+    /// once they no longer are.
+    fn restyle(&mut self) {
+        for i in 0..self.modules.len() {
+            let (Some(out), Some(block)) = (&mut self.last[i], &mut self.cache[i]) else {
+                continue;
+            };
+            let highlight = self.highlights[i]
+                .filter(|(_, until)| self.tick <= *until)
+                .map(|(color, _)| color);
+            let stale =
+                is_stale(self.refreshed[i].elapsed(), self.stale_after).then_some(self.stale_color);
+            let color = highlight.or(stale);
+            if color == self.restyled[i] {
+                continue;
+            }
+            let own = out.color_fg.clone();
+            if let Some(color) = color {
+                out.color_fg = Some(color.to_string());
+            }
+            block.clear();
+            self.format.write_block(block, out);
+            out.color_fg = own;
+            self.restyled[i] = color;
+        }
+    }
+
+    /// Pads flexible spacer blocks so the visible blocks add up to `bar_width`.
+    fn fill_spacers(&mut self, bar_width: usize) {
+        let visible: Vec<usize> = (0..self.modules.len())
+            .filter(|&i| self.enabled[i] && self.cache[i].is_some())
+            .collect();
+        let (flexible, fixed): (Vec<usize>, Vec<usize>) = visible
+            .into_iter()
+            .partition(|&i| self.last[i].as_ref().is_some_and(|out| out.flexible));
+        let fixed = fixed.iter().map(|&i| self.widths[i]).sum();
+        let widths = spacer_widths(bar_width, fixed, flexible.len());
+        for (i, width) in flexible.into_iter().zip(widths) {
+            let (Some(out), Some(block)) = (&mut self.last[i], &mut self.cache[i]) else {
+                continue;
+            };
+            if out.content.len() == width && out.content.bytes().all(|b| b == b' ') {
+                continue;
+            }
+            out.content = " ".repeat(width);
+            block.clear();
+            self.format.write_block(block, out);
+            self.widths[i] = width;
+        }
+    }
+
+    fn render(&mut self, timed: bool) -> Option<&str> {
+        let mut dump = self.dump.is_some().then(Vec::new);
+        for (i, module) in self.modules.iter_mut().enumerate() {
+            let triggered = std::mem::take(&mut self.pending[i]);
+            let due = timed
+                && self.tick.is_multiple_of(module.rate())
+                && self.tick >= self.backoff_until[i];
+            if self.retired[i] || !self.enabled[i] || !(triggered || due) {
+                if let Some(dump) = &mut dump {
+                    let status = if self.retired[i] {
+                        "retired"
+                    } else if !self.enabled[i] {
+                        "disabled"
+                    } else {
+                        "cached"
+                    };
+                    dump.push(serde_json::json!({ "name": self.names[i], "status": status }));
+                }
+                continue;
+            }
+
+            // Blocks are rendered into their cache slot, so the line can be
+            // assembled from the cache without copying unchanged blocks.
+            let res = poll(module.as_mut());
+            if let Some(dump) = &mut dump {
+                dump.push(match &res {
+                    // Reported below, once it's known whether it changed.
+                    Ok(_) => serde_json::Value::Null,
+                    Err(ModuleError::Message(mes)) => serde_json::json!({
+                        "name": self.names[i], "status": "error", "error": mes,
+                    }),
+                    Err(ModuleError::Fatal(mes)) => serde_json::json!({
+                        "name": self.names[i], "status": "fatal", "error": mes,
+                    }),
+                    Err(ModuleError::Hidden) => {
+                        serde_json::json!({ "name": self.names[i], "status": "hidden" })
+                    }
+                });
+            }
+            match res {
+                Ok(mut modout) => {
+                    self.failures[i] = 0;
+                    self.hidden[i] = 0;
+                    if self.grouped[i] {
+                        modout = modout.with_separator(false);
+                    }
+                    modout.separator = modout.separator.or(self.default_separator);
+                    modout.max_len = modout.max_len.or(self.max_len);
+                    if modout.min_width.is_none() {
+                        modout.min_width = module.min_width();
+                    }
+                    modout.separator_block_width = modout
+                        .separator_block_width
+                        .or(self.default_separator_block_width);
+                    let modout = modout.with_instance(i.to_string());
+                    self.refreshed[i] = Instant::now();
+                    let unchanged = self.restyled[i].is_none()
+                        && self.cache[i].is_some()
+                        && self.last[i].as_ref() == Some(&modout);
+                    if let Some(entry) = dump.as_mut().and_then(|d| d.last_mut()) {
+                        *entry = serde_json::json!({
+                            "name": self.names[i],
+                            "status": if unchanged { "unchanged" } else { "ok" },
+                            "content": modout.full_text(),
+                        });
+                    }
+                    if !unchanged {
+                        let block = self.cache[i].get_or_insert_with(String::new);
+                        block.clear();
+                        self.format.write_block(block, &modout);
+                        self.widths[i] = modout.full_text().chars().count();
+                        self.last[i] = Some(modout);
+                        self.restyled[i] = None;
+                    }
+                }
+                Err(ModuleError::Message(mut mes)) => {
+                    // An empty message is a module bug; still show that it failed.
+                    if mes.is_empty() {
+                        log::warn!("module #{} failed without a message", i + 1);
+                        mes = UNKNOWN_ERROR.to_string();
+                    } else {
+                        log::warn!("module #{} failed: {}", i + 1, mes);
+                    }
+                    if self.started.elapsed() < self.startup_grace {
+                        self.cache[i] = None;
+                        self.last[i] = None;
+                        continue;
+                    }
+                    if let Some(max_len) = self.max_len {
+                        mes = truncate(&mes, max_len);
+                    }
+                    self.hidden[i] = 0;
+                    self.failures[i] += 1;
+                    self.backoff_until[i] = self
+                        .tick
+                        .saturating_add(backoff_ticks(module.rate(), self.failures[i]));
+                    let block = self.cache[i].get_or_insert_with(String::new);
+                    block.clear();
+                    self.format.write_error(block, &mes, self.error_color);
+                    self.widths[i] = mes.chars().count();
+                    self.last[i] = None;
+                }
+                Err(ModuleError::Fatal(mes)) => {
+                    log::error!("module #{} disabled: {}", i + 1, mes);
+                    self.retired[i] = true;
+                    self.cache[i] = None;
+                    self.last[i] = None;
+                }
+                Err(ModuleError::Hidden) => {
+                    log::debug!("module #{} hidden", i + 1);
+                    self.cache[i] = None;
+                    self.last[i] = None;
+                    self.hidden[i] += 1;
+                    if module
+                        .hidden_limit()
+                        .is_some_and(|limit| self.hidden[i] >= limit)
+                    {
+                        log::info!("module #{} unavailable, no longer polling it", i + 1);
+                        self.retired[i] = true;
+                    }
+                }
+            }
+        }
+
+        if let (Some(out), Some(modules)) = (&mut self.dump, dump) {
+            let line = serde_json::json!({ "tick": self.tick, "modules": modules });
+            if let Err(e) = writeln!(out, "{}", line) {
+                log::warn!("can't write debug dump: {}", e);
+            }
+        }
+        if timed {
+            self.tick += 1;
+        }
+        self.restyle();
+        if let Some(bar_width) = self.bar_width {
+            self.fill_spacers(bar_width);
+        }
+        self.line.clear();
+        match self.format {
+            OutputFormat::Diff => return self.render_diff(),
+            OutputFormat::Json => return self.render_object(),
+            _ => (),
+        }
+        let mut blocks = self
+            .cache
+            .iter()
+            .zip(&self.enabled)
+            .filter_map(|(block, enabled)| block.as_deref().filter(|_| *enabled))
+            .peekable();
+        if blocks.peek().is_none() {
+            match &self.on_empty {
+                EmptyLine::Emit => self.format.write_line(&mut self.line, []),
+                EmptyLine::Skip => return None,
+                EmptyLine::Placeholder(content) => {
+                    let block = self
+                        .format
+                        .serialize_block(&ModuleOutput::new(content.clone()));
+                    self.format.write_line(&mut self.line, [block.as_str()]);
+                }
+            }
+        } else {
+            self.format.write_line(&mut self.line, blocks);
+        }
+        Some(&self.line)
+    }
+}
+
+/// Where time-based modules get the current time from, so tests can swap
+/// in a clock they control.
+pub trait Clock {
+    fn now(&self) -> DateTime<Local>;
+    fn instant(&self) -> Instant;
+}
+
+/// The system's clock.
+#[derive(Clone, Copy, Default)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> DateTime<Local> {
+        Local::now()
+    }
+
+    fn instant(&self) -> Instant {
+        Instant::now()
+    }
+}
+
+pub enum DateFormat {
+    /// A `strftime` style format string.
+    Custom(String),
+    /// ISO-8601 to the minute, e.g. `2024-01-02T15:04`.
+    Iso8601,
+    /// Seconds since the Unix epoch.
+    Unix,
+    /// Time elapsed since boot, e.g. `3d 4h 12m`.
+    SinceBoot,
+}
+
+fn format_date(format: &DateFormat, now: DateTime<Local>, uptime: u64) -> String {
+    match format {
+        DateFormat::Custom(format) => now.format(format).to_string(),
+        DateFormat::Iso8601 => now.format("%Y-%m-%dT%H:%M").to_string(),
+        DateFormat::Unix => now.timestamp().to_string(),
+        DateFormat::SinceBoot => format_duration(uptime),
+    }
+}
+
+/// Seconds since boot.
+#[cfg(feature = "sysinfo-modules")]
+fn uptime() -> u64 {
+    System::new().uptime()
+}
+
+/// Seconds since boot, read from procfs where sysinfo isn't built in; zero
+/// if that isn't available either.
+#[cfg(not(feature = "sysinfo-modules"))]
+fn uptime() -> u64 {
+    read_to_string("/proc/uptime")
+        .ok()
+        .and_then(|s| s.split_whitespace().next()?.parse::<f64>().ok())
+        .map_or(0, |secs| secs as u64)
+}
+
+pub struct DateTimeModule {
+    format: DateFormat,
+    clock: Box<dyn Clock>,
+}
+
+impl DateTimeModule {
+    pub fn new() -> Self {
+        DateTimeModule {
+            format: DateFormat::Custom("%d/%m/%y %H:%M".to_string()),
+            clock: Box::new(SystemClock),
+        }
+    }
+
+    pub fn with_clock(mut self, clock: impl Clock + 'static) -> Self {
+        self.clock = Box::new(clock);
+        self
+    }
+
+    pub fn with_format(self, format: &str) -> Self {
+        self.with_date_format(DateFormat::Custom(format.to_string()))
+    }
+
+    pub fn with_date_format(mut self, format: DateFormat) -> Self {
+        self.format = format;
+        self
+    }
+}
+
+impl Default for DateTimeModule {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Module for DateTimeModule {
+    fn get_output(&mut self) -> ModuleRes {
+        Ok(ModuleOutput::new(format_date(
+            &self.format,
+            self.clock.now(),
+            uptime(),
+        )))
+    }
+}
+
+#[cfg(feature = "sysinfo-modules")]
+#[derive(Clone, Copy, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum RamDisplay {
+    /// Used out of total memory.
+    Used,
+    /// Memory available to new programs, counting reclaimable cache as free.
+    Available,
+}
+
+#[cfg(feature = "sysinfo-modules")]
+pub struct RamModule {
+    system: System,
+    display: RamDisplay,
+    gradient: bool,
+    thresholds: Option<Thresholds>,
+    history: Option<History<f32>>,
+    grouping: Option<char>,
+    /// Replaces `display` when set, see [`memory_fields`].
+    template: Option<String>,
+    theme: Arc<Theme>,
+}
+
+#[cfg(feature = "sysinfo-modules")]
+impl RamModule {
+    pub fn new() -> Self {
+        RamModule {
+            system: System::new(),
+            display: RamDisplay::Used,
+            gradient: false,
+            thresholds: None,
+            history: None,
+            grouping: None,
+            template: None,
+            theme: Arc::default(),
+        }
+    }
+
+    fn content(&self, used: u64, available: u64, total: u64) -> String {
+        match &self.template {
+            Some(template) => render_template(
+                template,
+                &memory_fields(used, available, total, self.grouping),
+            ),
+            None => format_memory(self.display, used, available, total, self.grouping),
+        }
+    }
+
+    /// Renders the content from `template`, e.g. `"{used}/{total} ({pct}%)"`,
+    /// instead of the fixed `display` layouts.
+    pub fn with_template(mut self, template: &str) -> Self {
+        self.template = Some(template.to_string());
+        self
+    }
+
+    /// Separates thousands with `grouping`, see [`format_number`].
+    pub fn with_grouping(mut self, grouping: Option<char>) -> Self {
+        self.grouping = grouping;
+        self
+    }
+
+    pub fn with_display(mut self, display: RamDisplay) -> Self {
+        self.display = display;
+        self
+    }
+
+    /// Colors the block by memory usage in percent.
+    pub fn with_thresholds(mut self, thresholds: Thresholds) -> Self {
+        self.thresholds = Some(thresholds);
+        self
+    }
+
+    /// Appends a sparkline of the last `samples` usage readings.
+    pub fn with_history(mut self, samples: usize) -> Self {
+        self.history = Some(History::new(samples));
+        self
+    }
+
+    /// Colors the background by memory usage instead of leaving it unset.
+    pub fn with_gradient(mut self, gradient: bool) -> Self {
+        self.gradient = gradient;
+        self
+    }
+
+    pub fn with_theme(mut self, theme: Arc<Theme>) -> Self {
+        self.theme = theme;
+        self
+    }
+}
+
+#[cfg(feature = "sysinfo-modules")]
+impl Default for RamModule {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Template fields for memory amounts given in KiB: `used`, `available`
+/// and `total` in GiB, and `pct` used.
+#[cfg(feature = "sysinfo-modules")]
+fn memory_fields(
+    used: u64,
+    available: u64,
+    total: u64,
+    grouping: Option<char>,
+) -> Vec<(&'static str, String)> {
+    let gib = |v| format_number(v as f64 / 1024. / 1024., 1, grouping);
+    let pct = (100 * used).checked_div(total).unwrap_or(0);
+    vec![
+        ("used", gib(used)),
+        ("available", gib(available)),
+        ("total", gib(total)),
+        ("pct", pct.to_string()),
+    ]
+}
+
+/// Formats memory amounts given in KiB, as sysinfo reports them, as GiB.
+#[cfg(feature = "sysinfo-modules")]
+fn format_memory(
+    display: RamDisplay,
+    used: u64,
+    available: u64,
+    total: u64,
+    grouping: Option<char>,
+) -> String {
+    let gib = |v| format_number(v as f64 / 1024. / 1024., 1, grouping);
+    match display {
+        RamDisplay::Used => format!("{}/{} GiB", gib(used), gib(total)),
+        RamDisplay::Available => format!("{} GiB free", gib(available)),
+    }
+}
+
+#[cfg(feature = "sysinfo-modules")]
+impl Module for RamModule {
+    fn get_output(&mut self) -> ModuleRes {
+        self.system.refresh_memory();
+
+        let (used, total) = (self.system.used_memory(), self.system.total_memory());
+        let usage = if total > 0 {
+            100. * used as f32 / total as f32
+        } else {
+            0.
+        };
+        let mut content = self.content(used, self.system.available_memory(), total);
+        if let Some(history) = &mut self.history {
+            history.push(usage);
+            let samples: Vec<f32> = history.iter().copied().collect();
+            content = format!("{} {}", sparkline(&samples, 0., 100.), content);
+        }
+        let mut out = ModuleOutput::new(content)
+            .with_metric("used", used as f64 * 1024.)
+            .with_metric("available", self.system.available_memory() as f64 * 1024.)
+            .with_metric("total", total as f64 * 1024.)
+            .with_metric("usage", usage as f64);
+        if self.gradient && total > 0 {
+            out = out.with_color_bg(self.theme.gradient_color(usage));
+        }
+        if let Some(thresholds) = &self.thresholds {
+            out = thresholds.apply(usage, &self.theme, out);
+        }
+        Ok(out)
+    }
+
+    fn rate(&self) -> usize {
+        3
+    }
+
+    /// All memory in use; only the amounts' widths matter.
+    fn min_width(&self) -> Option<MinWidth> {
+        let total = self.system.total_memory();
+        let mut widest = self.content(total, total, total);
+        if let Some(history) = &self.history {
+            widest = format!("{} {}", "█".repeat(history.capacity), widest);
+        }
+        Some(MinWidth::Text(widest))
+    }
+}
+
+/// Conservatively shortens `name: value` content by dropping the label, and
+/// the unit too if the value is a plain `number unit` pair. Anything else
+/// yields `None`.
+fn auto_short_text(content: &str) -> Option<String> {
+    let (label, value) = content.split_once(": ")?;
+    if label.is_empty() || label.len() > 10 || label.contains(char::is_whitespace) {
+        return None;
+    }
+    let value = value.trim();
+    match value.split_whitespace().collect::<Vec<_>>()[..] {
+        [number, _unit] if number.parse::<f64>().is_ok() => Some(number.to_string()),
+        [] => None,
+        _ => Some(value.to_string()),
+    }
+}
+
+/// Truncates `s` to at most `max_len` chars, replacing the tail with `…`.
+fn truncate(s: &str, max_len: usize) -> String {
+    if s.chars().count() <= max_len {
+        return s.to_string();
+    }
+    let mut res: String = s.chars().take(max_len.saturating_sub(1)).collect();
+    res.push('…');
+    res
+}
+
+/// Fills in `{name}` placeholders in `template` from `fields`. Unknown names
+/// and unclosed braces are kept as written, so typos show up in the bar.
+pub fn render_template(template: &str, fields: &[(&str, String)]) -> String {
+    let mut res = String::with_capacity(template.len());
+    let mut rest = template;
+    while let Some(start) = rest.find('{') {
+        res.push_str(&rest[..start]);
+        let after = &rest[start + 1..];
+        let field = after.find('}').and_then(|end| {
+            let value = fields.iter().find(|(name, _)| *name == &after[..end])?;
+            Some((&value.1, end))
+        });
+        match field {
+            Some((value, end)) => {
+                res.push_str(value);
+                rest = &after[end + 1..];
+            }
+            None => {
+                res.push('{');
+                rest = after;
+            }
+        }
+    }
+    res.push_str(rest);
+    res
+}
+
+/// Formats `value` with `decimals` digits after the point, splitting the
+/// whole part into groups of three with `grouping` if given.
+fn format_number(value: f64, decimals: usize, grouping: Option<char>) -> String {
+    let digits = format!("{:.*}", decimals, value);
+    let Some(sep) = grouping else {
+        return digits;
+    };
+    let (sign, digits) = match digits.strip_prefix('-') {
+        Some(rest) => ("-", rest),
+        None => ("", digits.as_str()),
+    };
+    let (whole, fraction) = digits.split_at(digits.find('.').unwrap_or(digits.len()));
+    let mut res = sign.to_string();
+    for (i, c) in whole.chars().enumerate() {
+        if i > 0 && (whole.len() - i) % 3 == 0 {
+            res.push(sep);
+        }
+        res.push(c);
+    }
+    res + fraction
+}
+
+/// Formats a duration as `MM:SS`, or `HH:MM:SS` from an hour upwards.
+fn format_clock(secs: u64) -> String {
+    if secs >= 3600 {
+        format!("{:02}:{:02}:{:02}", secs / 3600, secs / 60 % 60, secs % 60)
+    } else {
+        format!("{:02}:{:02}", secs / 60, secs % 60)
+    }
+}
+
+/// Formats a duration as `Xd Yh Zm`, dropping leading units that are zero.
+fn format_duration(secs: u64) -> String {
+    let (days, hours, mins) = (secs / 86400, secs / 3600 % 24, secs / 60 % 60);
+    if days > 0 {
+        format!("{}d {}h {}m", days, hours, mins)
+    } else if hours > 0 {
+        format!("{}h {}m", hours, mins)
+    } else {
+        format!("{}m", mins)
+    }
+}
+
+/// Formats a remaining time given in hours, or `None` if less than a minute
+/// (or no meaningful estimate) is left.
+#[cfg(target_os = "linux")]
+fn format_time_remaining(hours: f32) -> Option<String> {
+    if !hours.is_finite() || hours * 60. < 1. {
+        return None;
+    }
+    Some(format_duration((hours * 3600.) as u64))
+}
+
+/// Time of day `hours` after `now`, or `None` under the same conditions as
+/// [`format_time_remaining`].
+#[cfg(target_os = "linux")]
+fn eta_clock<Tz: TimeZone>(now: DateTime<Tz>, hours: f32) -> Option<String>
+where
+    Tz::Offset: Display,
+{
+    format_time_remaining(hours)?;
+    let at = now + chrono::Duration::seconds((hours * 3600.) as i64);
+    Some(at.format("%H:%M").to_string())
+}
+
+/// Fixed-capacity ring buffer of the most recent samples, oldest first.
+pub struct History<T> {
+    samples: Vec<T>,
+    capacity: usize,
+    next: usize,
+}
+
+impl<T> History<T> {
+    pub fn new(capacity: usize) -> Self {
+        History {
+            samples: Vec::with_capacity(capacity),
+            capacity,
+            next: 0,
+        }
+    }
+
+    /// Appends a sample, overwriting the oldest one once full.
+    pub fn push(&mut self, sample: T) {
+        if self.capacity == 0 {
+            return;
+        }
+        if self.samples.len() < self.capacity {
+            self.samples.push(sample);
+        } else {
+            self.samples[self.next] = sample;
+        }
+        self.next = (self.next + 1) % self.capacity;
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &T> {
+        // Until the buffer wraps `next == len`, so `older` is empty.
+        let (newer, older) = self.samples.split_at(self.next);
+        older.iter().chain(newer)
+    }
+
+    pub fn len(&self) -> usize {
+        self.samples.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.samples.is_empty()
+    }
+
+    pub fn clear(&mut self) {
+        self.samples.clear();
+        self.next = 0;
+    }
+}
+
+/// Renders `pct` percent as a bar `width` characters wide, e.g.
+/// `██████░░░░` for 60%, using eighth blocks for the partly filled cell.
+pub fn progress_bar(pct: f32, width: usize) -> String {
+    const PARTIAL: [char; 7] = ['▏', '▎', '▍', '▌', '▋', '▊', '▉'];
+    let eighths = (pct.clamp(0., 100.) / 100. * width as f32 * 8.).round() as usize;
+    let (full, rest) = (eighths / 8, eighths % 8);
+    let mut bar = "█".repeat(full);
+    if rest > 0 {
+        bar.push(PARTIAL[rest - 1]);
+    }
+    let empty = width - bar.chars().count();
+    bar + &"░".repeat(empty)
+}
+
+/// Renders `values` as a `▁`…`█` block sparkline scaled between `min` and `max`.
+pub fn sparkline(values: &[f32], min: f32, max: f32) -> String {
+    let range = max - min;
+    values
+        .iter()
+        .map(|v| {
+            let level = if range > 0. {
+                (7. * ((v - min) / range).clamp(0., 1.)) as u32
+            } else {
+                0
+            };
+            char::from_u32(0x2581 + level).unwrap_or(' ')
+        })
+        .collect()
+}
+
+#[cfg(feature = "sysinfo-modules")]
+#[derive(Clone, Copy, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum CpuDisplay {
+    /// A sparkline bar per core.
+    Sparkline,
+    /// How many cores are saturated, e.g. `3/8 busy`.
+    Busy,
+    /// Overall usage, e.g. `12%`.
+    Usage,
+    /// Shares of user, system, I/O wait and steal time since the previous
+    /// refresh, e.g. `us 12 sy 3 io 1 st 0`, turning yellow while waiting
+    /// on I/O or the hypervisor takes a large share.
+    #[cfg(target_os = "linux")]
+    Breakdown,
+}
+
+#[cfg(all(target_os = "linux", feature = "sysinfo-modules"))]
+const PROC_STAT: &str = "/proc/stat";
+
+/// Share of I/O wait or steal time, in percent, that turns the breakdown yellow.
+#[cfg(all(target_os = "linux", feature = "sysinfo-modules"))]
+const WAIT_WARN_PERC: f32 = 20.;
+
+/// Cumulative CPU time counters from the aggregate `cpu` line of `/proc/stat`.
+#[cfg(all(target_os = "linux", feature = "sysinfo-modules"))]
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+struct CpuTimes {
+    user: u64,
+    system: u64,
+    idle: u64,
+    iowait: u64,
+    steal: u64,
+    /// Everything else: `nice`, `irq` and `softirq`.
+    other: u64,
+}
+
+#[cfg(all(target_os = "linux", feature = "sysinfo-modules"))]
+impl CpuTimes {
+    fn total(&self) -> u64 {
+        self.user + self.system + self.idle + self.iowait + self.steal + self.other
+    }
+}
+
+/// Parses the aggregate line of `/proc/stat`. Kernels before 2.6.11 lack
+/// the steal column, which then counts as zero; guest time is already part
+/// of user time.
+#[cfg(all(target_os = "linux", feature = "sysinfo-modules"))]
+fn parse_cpu_times(stat: &str) -> Option<CpuTimes> {
+    let line = stat.lines().find(|l| l.starts_with("cpu "))?;
+    let fields: Vec<u64> = line
+        .split_whitespace()
+        .skip(1)
+        .map(|f| f.parse().ok())
+        .collect::<Option<_>>()?;
+    let field = |i: usize| fields.get(i).copied().unwrap_or(0);
+    (fields.len() >= 4).then(|| CpuTimes {
+        user: field(0),
+        system: field(2),
+        idle: field(3),
+        iowait: field(4),
+        steal: field(7),
+        other: field(1) + field(5) + field(6),
+    })
+}
+
+/// Percentages of user, system, I/O wait and steal time between two
+/// snapshots, or `None` if no time passed between them.
+#[cfg(all(target_os = "linux", feature = "sysinfo-modules"))]
+fn cpu_breakdown(prev: &CpuTimes, now: &CpuTimes) -> Option<[f32; 4]> {
+    let total = now.total().checked_sub(prev.total()).filter(|&t| t > 0)? as f32;
+    let share = |now: u64, prev: u64| 100. * now.saturating_sub(prev) as f32 / total;
+    Some([
+        share(now.user, prev.user),
+        share(now.system, prev.system),
+        share(now.iowait, prev.iowait),
+        share(now.steal, prev.steal),
+    ])
+}
+
+/// Usage, in percent, above which a core counts as busy.
+#[cfg(feature = "sysinfo-modules")]
+const BUSY_CORE_PERC: f32 = 80.;
+
+#[cfg(feature = "sysinfo-modules")]
+fn busy_cores(usages: &[f32]) -> usize {
+    usages.iter().filter(|u| **u > BUSY_CORE_PERC).count()
+}
+
+#[cfg(feature = "sysinfo-modules")]
+pub struct CpuModule {
+    system: System,
+    display: CpuDisplay,
+    gradient: bool,
+    thresholds: Thresholds,
+    /// Also colors by CPU temperature, in °C, when set.
+    temp_thresholds: Option<Thresholds>,
+    /// Whether the component list is current, see [`TemperatureModule`].
+    listed: bool,
+    /// Counters as of the previous refresh, for the breakdown; zero at first,
+    /// so that shows the shares since boot.
+    #[cfg(target_os = "linux")]
+    times: CpuTimes,
+    theme: Arc<Theme>,
+}
+
+/// Overall CPU usage, in percent, at which the block turns yellow and red.
+#[cfg(feature = "sysinfo-modules")]
+pub const DEFAULT_CPU_THRESHOLDS: Thresholds = Thresholds::new(80., 95.);
+
+/// CPU temperature, in °C, at which a block turns yellow and red.
+#[cfg(feature = "sysinfo-modules")]
+pub const DEFAULT_TEMP_THRESHOLDS: Thresholds = Thresholds::new(70., 85.);
+
+/// Maps `value` from the scale of `from` onto that of `to`, so that their
+/// warn and crit points line up. Both must warn below their critical level,
+/// as the config ensures; an inverted `from` would divide by a negative span.
+#[cfg(feature = "sysinfo-modules")]
+fn rescale(value: f32, from: &Thresholds, to: &Thresholds) -> f32 {
+    debug_assert!(from.warn < from.crit && to.warn < to.crit);
+    if value >= from.crit {
+        to.crit + (value - from.crit)
+    } else if value >= from.warn {
+        to.warn + (value - from.warn) / (from.crit - from.warn) * (to.crit - to.warn)
+    } else {
+        value / from.warn * to.warn
+    }
+}
+
+/// The more alarming of CPU usage and temperature, expressed as usage in
+/// percent so it can be colored with the usage thresholds.
+#[cfg(feature = "sysinfo-modules")]
+fn cpu_severity(
+    usage: f32,
+    temp: Option<f32>,
+    usage_thresholds: &Thresholds,
+    temp_thresholds: &Thresholds,
+) -> f32 {
+    match temp {
+        Some(temp) => usage.max(rescale(temp, temp_thresholds, usage_thresholds)),
+        None => usage,
+    }
+}
+
+#[cfg(feature = "sysinfo-modules")]
+impl CpuModule {
+    pub fn new() -> Self {
+        CpuModule {
+            system: System::new(),
+            display: CpuDisplay::Sparkline,
+            gradient: false,
+            thresholds: DEFAULT_CPU_THRESHOLDS,
+            temp_thresholds: None,
+            listed: false,
+            #[cfg(target_os = "linux")]
+            times: CpuTimes::default(),
+            theme: Arc::default(),
+        }
+    }
+
+    pub fn with_display(mut self, display: CpuDisplay) -> Self {
+        self.display = display;
+        self
+    }
+
+    pub fn with_warn(mut self, perc: f32) -> Self {
+        self.thresholds.warn = perc;
+        self
+    }
+
+    pub fn with_crit(mut self, perc: f32) -> Self {
+        self.thresholds.crit = perc;
+        self
+    }
+
+    /// Colors the background by overall load instead of the theme's `bg`.
+    pub fn with_gradient(mut self, gradient: bool) -> Self {
+        self.gradient = gradient;
+        self
+    }
+
+    /// Colors by whichever of usage and CPU temperature is more alarming,
+    /// with `thresholds` in °C lined up against the usage thresholds.
+    pub fn with_temp_thresholds(mut self, thresholds: Thresholds) -> Self {
+        self.temp_thresholds = Some(thresholds);
+        self
+    }
+
+    pub fn with_theme(mut self, theme: Arc<Theme>) -> Self {
+        self.theme = theme;
+        self
+    }
+}
+
+#[cfg(feature = "sysinfo-modules")]
+impl Default for CpuModule {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(feature = "sysinfo-modules")]
+impl Module for CpuModule {
+    fn get_output(&mut self) -> ModuleRes {
+        self.system.refresh_cpu();
+        sleep(Duration::from_millis(200));
+        self.system.refresh_cpu();
+
+        let usages: Vec<f32> = self.system.cpus().iter().map(|c| c.cpu_usage()).collect();
+        // Whether I/O wait or steal time is high enough to color the block.
+        let (content, waiting) = match self.display {
+            CpuDisplay::Sparkline => (sparkline(&usages, 0., 100.), false),
+            CpuDisplay::Busy => (
+                format!("{}/{} busy", busy_cores(&usages), usages.len()),
+                false,
+            ),
+            CpuDisplay::Usage => (
+                format!("{:.0}%", self.system.global_cpu_info().cpu_usage()),
+                false,
+            ),
+            #[cfg(target_os = "linux")]
+            CpuDisplay::Breakdown => {
+                let times = read_to_string(PROC_STAT)
+                    .ok()
+                    .and_then(|stat| parse_cpu_times(&stat))
+                    .ok_or_else(|| "CPU times unavailable".to_string())?;
+                let prev = std::mem::replace(&mut self.times, times);
+                let [user, system, iowait, steal] =
+                    cpu_breakdown(&prev, &times).ok_or(ModuleError::Hidden)?;
+                (
+                    format!(
+                        "us {:.0} sy {:.0} io {:.0} st {:.0}",
+                        user, system, iowait, steal
+                    ),
+                    iowait >= WAIT_WARN_PERC || steal >= WAIT_WARN_PERC,
+                )
+            }
+        };
+
+        let mut usage = self.system.global_cpu_info().cpu_usage();
+        let mut metrics = vec![("usage", usage as f64)];
+        if let Some(temp_thresholds) = &self.temp_thresholds {
+            refresh_listed(
+                &mut self.system,
+                &mut self.listed,
+                System::refresh_components,
+                System::refresh_components_list,
+            );
+            let temp = cpu_temperature(&self.system, TempAggregate::First);
+            self.listed = temp.is_some();
+            metrics.extend(temp.map(|temp| ("temp", temp as f64)));
+            usage = cpu_severity(usage, temp, &self.thresholds, temp_thresholds);
+        }
+        let bg = if self.gradient {
+            self.theme.gradient_color(usage)
+        } else {
+            self.theme.bg()
+        };
+        let mut out = ModuleOutput::new(content)
+            .with_color_bg(bg)
+            .with_border(self.theme.border());
+        for (key, value) in metrics {
+            out = out.with_metric(key, value);
+        }
+
+        let out = self.thresholds.apply(usage, &self.theme, out);
+        if waiting && out.color_fg.is_none() {
+            return Ok(out.with_color_fg(self.theme.warn()));
+        }
+        Ok(out)
+    }
+}
+
+#[cfg(feature = "sysinfo-modules")]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum TempAggregate {
+    /// The sensor labeled `CPU`.
+    First,
+    /// The hottest of the CPU and per-core sensors.
+    Max,
+    /// The mean of the CPU and per-core sensors, for a smoother reading.
+    Mean,
+}
+
+#[cfg(feature = "sysinfo-modules")]
+fn aggregate_temps(temps: &[f32], aggregate: TempAggregate) -> Option<f32> {
+    match aggregate {
+        TempAggregate::First => temps.first().copied(),
+        TempAggregate::Max => temps.iter().copied().reduce(f32::max),
+        TempAggregate::Mean => {
+            (!temps.is_empty()).then(|| temps.iter().sum::<f32>() / temps.len() as f32)
+        }
+    }
+}
+
+/// Refreshes the readings of `system`, enumerating the list they belong to
+/// instead unless `listed` says it is current.
+#[cfg(feature = "sysinfo-modules")]
+fn refresh_listed<S>(
+    system: &mut S,
+    listed: &mut bool,
+    refresh: fn(&mut S),
+    refresh_list: fn(&mut S),
+) {
+    if *listed {
+        refresh(system);
+    } else {
+        refresh_list(system);
+        *listed = true;
+    }
+}
+
+#[cfg(feature = "sysinfo-modules")]
+pub struct TemperatureModule {
+    system: System,
+    aggregate: TempAggregate,
+    /// Whether the component list is current; enumerating it is far more
+    /// expensive than refreshing the readings.
+    listed: bool,
+    thresholds: Option<Thresholds>,
+    smooth: bool,
+    theme: Arc<Theme>,
+}
+
+#[cfg(feature = "sysinfo-modules")]
+impl TemperatureModule {
+    pub fn new() -> Self {
+        TemperatureModule {
+            system: System::new(),
+            aggregate: TempAggregate::First,
+            listed: false,
+            thresholds: None,
+            smooth: false,
+            theme: Arc::default(),
+        }
+    }
+
+    pub fn with_aggregate(mut self, aggregate: TempAggregate) -> Self {
+        self.aggregate = aggregate;
+        self
+    }
+
+    /// Colors the block by temperature in °C.
+    pub fn with_thresholds(mut self, thresholds: Thresholds) -> Self {
+        self.thresholds = Some(thresholds);
+        self
+    }
+
+    /// Fades the color from the theme's `ok` at the warn threshold to `crit`
+    /// at the critical one, see [`lerp_color`], instead of switching colors.
+    pub fn with_smooth_color(mut self, smooth: bool) -> Self {
+        self.smooth = smooth;
+        self
+    }
+
+    pub fn with_theme(mut self, theme: Arc<Theme>) -> Self {
+        self.theme = theme;
+        self
+    }
+}
+
+#[cfg(feature = "sysinfo-modules")]
+impl Default for TemperatureModule {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(all(target_os = "linux", feature = "sysinfo-modules"))]
+const THERMAL_ROOT: &str = "/sys/class/thermal";
+
+/// Whether a thermal zone `type` names the CPU package.
+#[cfg(all(target_os = "linux", feature = "sysinfo-modules"))]
+fn is_cpu_zone(kind: &str) -> bool {
+    let kind = kind.trim().to_ascii_lowercase();
+    kind == "x86_pkg_temp" || kind.contains("cpu") || kind == "soc_thermal"
+}
+
+/// Parses a thermal zone `temp` reading, given in millidegrees Celsius.
+#[cfg(all(target_os = "linux", feature = "sysinfo-modules"))]
+fn millidegrees_to_celsius(raw: &str) -> Option<f32> {
+    raw.trim().parse::<i64>().ok().map(|m| m as f32 / 1000.)
+}
+
+/// Reads the CPU temperature from the kernel's thermal zones, for machines
+/// where sysinfo doesn't label any component `CPU`.
+#[cfg(all(target_os = "linux", feature = "sysinfo-modules"))]
+fn thermal_zone_temperature() -> Option<f32> {
+    let mut zones: Vec<PathBuf> = read_dir(THERMAL_ROOT)
+        .ok()?
+        .flatten()
+        .map(|entry| entry.path())
+        .filter(|p| {
+            p.file_name()
+                .is_some_and(|n| n.to_string_lossy().starts_with("thermal_zone"))
+        })
+        .collect();
+    zones.sort();
+    zones
+        .iter()
+        .find(|zone| read_to_string(zone.join("type")).is_ok_and(|kind| is_cpu_zone(&kind)))
+        .and_then(|zone| millidegrees_to_celsius(&read_to_string(zone.join("temp")).ok()?))
+}
+
+/// Thermal zones are a Linux interface, so there's nothing to fall back to.
+#[cfg(all(not(target_os = "linux"), feature = "sysinfo-modules"))]
+fn thermal_zone_temperature() -> Option<f32> {
+    None
+}
+
+/// Renders a temperature in whole degrees, since means and thermal zones
+/// come with fractions sysinfo readings don't have.
+#[cfg(feature = "sysinfo-modules")]
+fn temperature_label(temp: f32) -> String {
+    format!("{:.0}°C", temp)
+}
+
+/// CPU temperature from `system`'s sensors, or from the thermal zones when
+/// none is labeled.
+#[cfg(feature = "sysinfo-modules")]
+fn cpu_temperature(system: &System, aggregate: TempAggregate) -> Option<f32> {
+    let temps: Vec<f32> = system
+        .components()
+        .iter()
+        .filter(|c| match aggregate {
+            TempAggregate::First => c.label() == "CPU",
+            _ => c.label() == "CPU" || c.label().contains("Core"),
+        })
+        .map(|c| c.temperature())
+        .collect();
+    aggregate_temps(&temps, aggregate).or_else(thermal_zone_temperature)
+}
+
+#[cfg(feature = "sysinfo-modules")]
+impl Module for TemperatureModule {
+    fn get_output(&mut self) -> ModuleRes {
+        refresh_listed(
+            &mut self.system,
+            &mut self.listed,
+            System::refresh_components,
+            System::refresh_components_list,
+        );
+
+        let Some(temp) = cpu_temperature(&self.system, self.aggregate) else {
+            // Sensors may show up later, e.g. once a driver loads.
+            self.listed = false;
+            return Err("CPU unavailable".to_string().into());
+        };
+
+        let out = ModuleOutput::new(temperature_label(temp));
+        Ok(match &self.thresholds {
+            Some(t) if self.smooth => {
+                let color = lerp_color(temp, t.warn, t.crit, self.theme.ok, self.theme.crit);
+                let out = out.with_color_fg(color.to_string());
+                if temp >= t.crit {
+                    out.with_urgent(true)
+                } else {
+                    out
+                }
+            }
+            Some(thresholds) => thresholds.apply(temp, &self.theme, out),
+            None => out,
+        })
+    }
+
+    fn rate(&self) -> usize {
+        5
+    }
+}
+
+#[cfg(feature = "sysinfo-modules")]
+#[derive(Clone, Copy, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum DiskDisplay {
+    /// Free space in GiB.
+    Space,
+    /// Share of inodes in use, e.g. `inodes 12%`, for filesystems holding
+    /// many small files.
+    Inodes,
+}
+
+#[cfg(feature = "sysinfo-modules")]
+pub struct DiskSpaceModule {
+    devs: Vec<String>,
+    system: System,
+    /// Whether the disk list is current, see [`TemperatureModule`].
+    listed: bool,
+    display: DiskDisplay,
+    grouping: Option<char>,
+    /// Whether to warn about disks mounted read-only, see [`read_only_mounts`].
+    read_only_warning: bool,
+    theme: Arc<Theme>,
+}
+
+#[cfg(feature = "sysinfo-modules")]
+impl DiskSpaceModule {
+    pub fn new(dev: &str) -> Self {
+        Self::multi([dev])
+    }
+
+    /// Sums the free space of several disks, each given by device name or
+    /// mount point. Disks that aren't present are skipped.
+    pub fn multi<S: AsRef<str>>(devs: impl IntoIterator<Item = S>) -> Self {
+        DiskSpaceModule {
+            devs: devs.into_iter().map(|d| d.as_ref().to_string()).collect(),
+            system: System::new(),
+            listed: false,
+            display: DiskDisplay::Space,
+            grouping: None,
+            read_only_warning: false,
+            theme: Arc::default(),
+        }
+    }
+
+    pub fn with_display(mut self, display: DiskDisplay) -> Self {
+        self.display = display;
+        self
+    }
+
+    /// Separates thousands with `grouping`, see [`format_number`].
+    pub fn with_grouping(mut self, grouping: Option<char>) -> Self {
+        self.grouping = grouping;
+        self
+    }
+
+    /// Prefixes the block with `RO` in the theme's `crit` color while one of
+    /// the disks is mounted read-only, which the kernel does to filesystems
+    /// on failing disks. Off by default, since some mounts are read-only by
+    /// design; `/proc/mounts` is only read while it's on.
+    pub fn with_read_only_warning(mut self, warn: bool) -> Self {
+        self.read_only_warning = warn;
+        self
+    }
+
+    pub fn with_theme(mut self, theme: Arc<Theme>) -> Self {
+        self.theme = theme;
+        self
+    }
+
+    #[cfg(target_os = "linux")]
+    fn read_only(&self) -> bool {
+        read_to_string(MOUNTS).is_ok_and(|mounts| {
+            read_only_mounts(&mounts)
+                .iter()
+                .any(|(dev, mount)| disk_matches(&self.devs, dev, mount))
+        })
+    }
+
+    #[cfg(not(target_os = "linux"))]
+    fn read_only(&self) -> bool {
+        false
+    }
+
+    fn warn_read_only(&self, out: ModuleOutput) -> ModuleOutput {
+        if !self.read_only_warning || !self.read_only() {
+            return out;
+        }
+        out.with_prefix("RO ")
+            .with_color_fg(self.theme.crit())
+            .with_urgent(true)
+    }
+}
+
+#[cfg(all(target_os = "linux", feature = "sysinfo-modules"))]
+const MOUNTS: &str = "/proc/mounts";
+
+/// Device and mount point of every read-only mount in a `/proc/mounts`
+/// listing, whose mount points escape spaces and the like as octal.
+#[cfg(all(target_os = "linux", feature = "sysinfo-modules"))]
+fn read_only_mounts(mounts: &str) -> Vec<(&str, PathBuf)> {
+    mounts
+        .lines()
+        .filter_map(|line| {
+            let mut fields = line.split_whitespace();
+            let (dev, mount, _, options) = (
+                fields.next()?,
+                fields.next()?,
+                fields.next()?,
+                fields.next()?,
+            );
+            options
+                .split(',')
+                .any(|o| o == "ro")
+                .then(|| (dev, PathBuf::from(unescape_octal(mount))))
+        })
+        .collect()
+}
+
+/// Undoes the `\040`-style octal escapes of `/proc/mounts` fields.
+#[cfg(all(target_os = "linux", feature = "sysinfo-modules"))]
+fn unescape_octal(field: &str) -> String {
+    let mut bytes = Vec::with_capacity(field.len());
+    let mut rest = field.as_bytes();
+    while let Some((&b, tail)) = rest.split_first() {
+        let code = tail
+            .get(..3)
+            .and_then(|digits| u8::from_str_radix(std::str::from_utf8(digits).ok()?, 8).ok());
+        match (b, code) {
+            (b'\\', Some(code)) => {
+                bytes.push(code);
+                rest = &tail[3..];
+            }
+            _ => {
+                bytes.push(b);
+                rest = tail;
+            }
+        }
+    }
+    String::from_utf8_lossy(&bytes).into_owned()
+}
+
+/// Whether a disk is one of `devs`, given by device name or mount point.
+#[cfg(feature = "sysinfo-modules")]
+fn disk_matches(devs: &[String], name: &str, mount: &Path) -> bool {
+    devs.iter()
+        .any(|d| d.as_str() == name || Path::new(d) == mount)
+}
+
+/// Sums `(available, total)` bytes over the disks matching one of `devs`.
+#[cfg(feature = "sysinfo-modules")]
+fn disk_totals<'a>(
+    disks: impl IntoIterator<Item = (&'a str, &'a Path, u64, u64)>,
+    devs: &[String],
+) -> Option<(u64, u64)> {
+    disks
+        .into_iter()
+        .filter(|(name, mount, _, _)| disk_matches(devs, name, mount))
+        .map(|(_, _, available, total)| (available, total))
+        .reduce(|(a, t), (an, tn)| (a + an, t + tn))
+}
+
+/// Inode `(total, free)` counts of the filesystem mounted at `mount`, which
+/// sysinfo doesn't report.
+#[cfg(feature = "sysinfo-modules")]
+fn statvfs_inodes(mount: &Path) -> Option<(u64, u64)> {
+    use std::os::unix::ffi::OsStrExt;
+
+    let path = std::ffi::CString::new(mount.as_os_str().as_bytes()).ok()?;
+    let mut stat = std::mem::MaybeUninit::<libc::statvfs>::uninit();
+    // SAFETY: `path` is NUL-terminated and `stat` is only read once statvfs
+    // reports having filled it.
+    let stat = unsafe {
+        if libc::statvfs(path.as_ptr(), stat.as_mut_ptr()) != 0 {
+            return None;
+        }
+        stat.assume_init()
+    };
+    #[allow(clippy::useless_conversion)] // The counts are 32-bit on macOS.
+    Some((u64::from(stat.f_files), u64::from(stat.f_ffree)))
+}
+
+/// Percentage of inodes in use, or `None` for filesystems without a fixed
+/// inode table, which report zero inodes.
+#[cfg(feature = "sysinfo-modules")]
+fn inode_usage(total: u64, free: u64) -> Option<u64> {
+    (total > 0).then(|| 100 * total.saturating_sub(free) / total)
+}
+
+#[cfg(feature = "sysinfo-modules")]
+impl Module for DiskSpaceModule {
+    fn get_output(&mut self) -> ModuleRes {
+        refresh_listed(
+            &mut self.system,
+            &mut self.listed,
+            System::refresh_disks,
+            System::refresh_disks_list,
+        );
+
+        if let DiskDisplay::Inodes = self.display {
+            let (total, free) = self
+                .system
+                .disks()
+                .iter()
+                .filter(|d| {
+                    disk_matches(
+                        &self.devs,
+                        d.name().to_str().unwrap_or_default(),
+                        d.mount_point(),
+                    )
+                })
+                .map(|d| statvfs_inodes(d.mount_point()))
+                .reduce(|a, b| Some((a?.0 + b?.0, a?.1 + b?.1)))
+                .flatten()
+                .ok_or(ModuleError::Hidden)?;
+            let usage = inode_usage(total, free).ok_or(ModuleError::Hidden)?;
+            let out = ModuleOutput::new(format!("inodes {}%", usage));
+            return Ok(self.warn_read_only(out));
+        }
+
+        let disks = self.system.disks().iter().map(|d| {
+            (
+                d.name().to_str().unwrap_or_default(),
+                d.mount_point(),
+                d.available_space(),
+                d.total_space(),
+            )
+        });
+        let Some((available, _)) = disk_totals(disks, &self.devs) else {
+            // Re-list next time in case the disk gets mounted.
+            self.listed = false;
+            return Err("Disk unavailable".to_string().into());
+        };
+
+        let gib = available / 1024u64.pow(3);
+        let out = ModuleOutput::new(format!(
+            "{} GiB",
+            format_number(gib as f64, 0, self.grouping)
+        ));
+        Ok(self.warn_read_only(out))
+    }
+
+    fn rate(&self) -> usize {
+        5
+    }
+}
+
+#[cfg(all(target_os = "linux", feature = "network"))]
+pub struct NetworkModule {
+    /// Interface to show, or `None` to follow NetworkManager's primary connection.
+    device: Option<String>,
+    name: Option<String>,
+    expanded: bool,
+    signal_icon: bool,
+    raw_frequency: bool,
+    addresses: AddressSelection,
+    down: DownBehavior,
+    /// Refreshes to highlight the block for after the SSID changes; 0 is off.
+    ssid_flash: usize,
+    last_ssid: Option<String>,
+    /// Refresh count, and the refresh before which the block is highlighted.
+    polls: usize,
+    flash_until: usize,
+    theme: Arc<Theme>,
+}
+
+/// The refresh until which to highlight the block, given the SSID seen on
+/// refresh `poll`: switching from a known SSID highlights it for the next
+/// `length` refreshes, otherwise a running highlight carries on.
+#[cfg(all(target_os = "linux", feature = "network"))]
+fn ssid_flash_until(
+    previous: Option<&str>,
+    current: &str,
+    poll: usize,
+    until: usize,
+    length: usize,
+) -> usize {
+    match previous {
+        Some(previous) if previous != current => poll + length,
+        _ => until,
+    }
+}
+
+/// What [`NetworkModule`] shows while its interface is down or offline.
+#[cfg(all(target_os = "linux", feature = "network"))]
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum DownBehavior {
+    /// `name: down` in the theme's `crit` color.
+    Default,
+    Hide,
+    /// Custom text, in the theme's `crit` color.
+    Text(String),
+}
+
+/// Which of an interface's IPv4 addresses to show.
+#[cfg(all(target_os = "linux", feature = "network"))]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum AddressSelection {
+    First,
+    All,
+    /// The first address inside the network given as address and prefix length.
+    Subnet(Ipv4Addr, u8),
+}
+
+#[cfg(all(target_os = "linux", feature = "network"))]
+impl AddressSelection {
+    /// Parses `first`, `all` or a subnet in CIDR notation such as `10.0.0.0/8`.
+    pub fn parse(s: &str) -> Option<Self> {
+        match s {
+            "first" => Some(AddressSelection::First),
+            "all" => Some(AddressSelection::All),
+            _ => {
+                let (net, prefix) = s.split_once('/')?;
+                let prefix = prefix.parse().ok().filter(|p| *p <= 32)?;
+                Some(AddressSelection::Subnet(net.parse().ok()?, prefix))
+            }
+        }
+    }
+}
+
+/// Picks addresses out of NetworkManager's `[address, prefix, gateway]`
+/// entries, whose addresses are in network byte order.
+#[cfg(all(target_os = "linux", feature = "network"))]
+fn select_addresses(entries: &[Vec<u32>], selection: AddressSelection) -> Vec<Ipv4Addr> {
+    let addrs = entries
+        .iter()
+        .filter_map(|entry| entry.first())
+        .map(|ip| Ipv4Addr::from(ip.to_be()));
+    match selection {
+        AddressSelection::First => addrs.take(1).collect(),
+        AddressSelection::All => addrs.collect(),
+        AddressSelection::Subnet(net, prefix) => {
+            let mask = u32::MAX.checked_shl(32 - prefix as u32).unwrap_or(0);
+            addrs
+                .filter(|ip| u32::from(*ip) & mask == u32::from(net) & mask)
+                .take(1)
+                .collect()
+        }
+    }
+}
+
+/// Formats a bitrate as reported by NetworkManager, in Kb/s.
+#[cfg(all(target_os = "linux", feature = "network"))]
+fn format_bitrate(kbps: u32) -> String {
+    format!("{} Mb/s", kbps / 1000)
+}
+
+/// Classifies a WiFi channel frequency in MHz into its band.
+#[cfg(all(target_os = "linux", feature = "network"))]
+fn wifi_band(freq: u32) -> Option<&'static str> {
+    match freq {
+        2400..=2500 => Some("2.4G"),
+        5150..=5895 => Some("5G"),
+        5925..=7125 => Some("6G"),
+        _ => None,
+    }
+}
+
+/// Bars for a WiFi signal strength in percent, one per started quarter.
+#[cfg(all(target_os = "linux", feature = "network"))]
+fn signal_bars(strength: u8) -> &'static str {
+    const BARS: &str = "▂▄▆█";
+    let count = (strength.min(100) as usize / 25 + 1).min(4);
+    &BARS[..count * '▂'.len_utf8()]
+}
+
+/// Formats a connected interface; the `detail` (signal, speed) is only shown when expanded.
+#[cfg(all(target_os = "linux", feature = "network"))]
+fn network_label(name: &str, detail: &str, ip: &str, expanded: bool) -> String {
+    if expanded {
+        format!("{}: ({}){}", name, detail, ip)
+    } else {
+        format!("{}:{}", name, ip)
+    }
+}
+
+#[cfg(all(target_os = "linux", feature = "network"))]
+impl NetworkModule {
+    pub fn new(device: &str) -> Self {
+        NetworkModule {
+            device: Some(device.to_string()),
+            name: None,
+            expanded: true,
+            signal_icon: false,
+            raw_frequency: false,
+            addresses: AddressSelection::First,
+            down: DownBehavior::Default,
+            ssid_flash: 0,
+            last_ssid: None,
+            polls: 0,
+            flash_until: 0,
+            theme: Arc::default(),
+        }
+    }
+
+    /// Highlights the block in the theme's accent color for `refreshes`
+    /// refreshes after switching WiFi networks.
+    pub fn with_ssid_flash(mut self, refreshes: usize) -> Self {
+        self.ssid_flash = refreshes;
+        self
+    }
+
+    pub fn with_down_behavior(mut self, down: DownBehavior) -> Self {
+        self.down = down;
+        self
+    }
+
+    /// The block for an interface that is down, where `status` says how.
+    fn down(&self, name: &str, status: &str) -> ModuleRes {
+        let content = match &self.down {
+            DownBehavior::Default => format!("{}: {}", name, status),
+            DownBehavior::Hide => return Err(ModuleError::Hidden),
+            DownBehavior::Text(text) => text.clone(),
+        };
+        Ok(ModuleOutput::new(content).with_color_fg(self.theme.crit()))
+    }
+
+    /// The selected addresses, each preceded by a space.
+    fn ip_from_addr(&self, addr: &[Vec<u32>]) -> String {
+        select_addresses(addr, self.addresses)
+            .iter()
+            .map(|ip| format!(" {}", ip))
+            .collect()
+    }
+
+    /// The block for a WiFi interface, given its access point's `(ssid,
+    /// strength, frequency)`. There's no access point while scanning or
+    /// roaming, which counts as down.
+    fn wifi(
+        &mut self,
+        name: &str,
+        ap: Option<(String, u8, u32)>,
+        addr: Option<Vec<Vec<u32>>>,
+        bitrate: Option<u32>,
+    ) -> ModuleRes {
+        let (Some((ssid, strength, freq)), Some(addr)) = (ap, addr) else {
+            return self.down(name, "down");
+        };
+        let signal = if self.signal_icon {
+            signal_bars(strength).to_string()
+        } else {
+            format!("{:3}%", strength)
+        };
+        let band = match wifi_band(freq) {
+            Some(band) if !self.raw_frequency => band.to_string(),
+            _ => format!("{} MHz", freq),
+        };
+        let mut detail = format!("{} at {}, {}", signal, ssid, band);
+        if let Some(kbps) = bitrate {
+            detail += ", ";
+            detail += &format_bitrate(kbps);
+        }
+        let mut color = self.theme.ok();
+        if self.ssid_flash > 0 {
+            self.polls += 1;
+            self.flash_until = ssid_flash_until(
+                self.last_ssid.as_deref(),
+                &ssid,
+                self.polls,
+                self.flash_until,
+                self.ssid_flash,
+            );
+            if self.polls < self.flash_until {
+                color = self.theme.accent();
+            }
+            self.last_ssid = Some(ssid);
+        }
+        Ok(ModuleOutput::new(network_label(
+            name,
+            &detail,
+            &self.ip_from_addr(&addr),
+            self.expanded,
+        ))
+        .with_color_fg(color))
+    }
+
+    /// Shows WiFi signal strength as bars rather than a percentage.
+    pub fn with_signal_icon(mut self, signal_icon: bool) -> Self {
+        self.signal_icon = signal_icon;
+        self
+    }
+
+    /// Shows the WiFi channel frequency in MHz rather than its band.
+    pub fn with_raw_frequency(mut self, raw_frequency: bool) -> Self {
+        self.raw_frequency = raw_frequency;
+        self
+    }
+
+    pub fn with_addresses(mut self, addresses: AddressSelection) -> Self {
+        self.addresses = addresses;
+        self
+    }
+
+    /// Shows whichever interface carries the primary connection, following
+    /// switches between ethernet and wifi.
+    pub fn auto() -> Self {
+        NetworkModule {
+            device: None,
+            ..Self::new("")
+        }
+    }
+
+    pub fn with_expanded(mut self, expanded: bool) -> Self {
+        self.expanded = expanded;
+        self
+    }
+
+    pub fn with_theme(mut self, theme: Arc<Theme>) -> Self {
+        self.theme = theme;
+        self
+    }
+
+    pub fn with_name(mut self, name: &str) -> Self {
+        self.name = Some(name.to_string());
+        self
+    }
+}
+
+#[cfg(all(target_os = "linux", feature = "network"))]
+impl Module for NetworkModule {
+    fn get_output(&mut self) -> ModuleRes {
+        let dbus = Connection::new_system().map_err(|_| "dbus unavailable".to_string())?;
+        let nm = NetworkManager::new(&dbus);
+
+        let device = match &self.device {
+            Some(device) => device.clone(),
+            None => match primary_interface(&dbus) {
+                Some(device) => device,
+                None => return self.down(self.name.as_deref().unwrap_or("net"), "offline"),
+            },
+        };
+        let name = self.name.clone().unwrap_or_else(|| device.clone());
+        let dev = nm.get_device_by_ip_iface(&device).map_err(|_| None)?;
+
+        match dev {
+            Device::WiFi(dev) => {
+                let ap = dev
+                    .active_access_point()
+                    .and_then(|ap| Ok((ap.ssid()?, ap.strength()?, ap.frequency()?)));
+                let addr = dev.ip4_config().and_then(|conf| conf.addresses());
+                self.wifi(&name, ap.ok(), addr.ok(), dev.bitrate().ok())
+            }
+            Device::Ethernet(dev) => {
+                if let (Ok(true), Ok(speed), Ok(Ok(addr))) = (
+                    dev.carrier(),
+                    dev.speed(),
+                    dev.ip4_config().map(|conf| conf.addresses()),
+                ) {
+                    let detail = format!("{} Mb/s", speed);
+                    Ok(ModuleOutput::new(network_label(
+                        &name,
+                        &detail,
+                        &self.ip_from_addr(&addr),
+                        self.expanded,
+                    ))
+                    .with_color_fg(self.theme.ok()))
+                } else {
+                    self.down(&name, "down")
+                }
+            }
+            _ if self.device.is_none() => {
+                Err(format!("{}: unsupported device type", device).into())
+            }
+            _ => Err(ModuleError::Fatal(format!(
+                "{}: unsupported device type",
+                device
+            ))),
+        }
+    }
+
+    fn rate(&self) -> usize {
+        5
+    }
+
+    fn set_expanded(&mut self, expanded: bool) {
+        self.expanded = expanded;
+    }
+}
+
+#[cfg(target_os = "linux")]
+#[derive(Clone, Copy, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum BatteryDisplay {
+    /// Charge percentage and time remaining.
+    Charge,
+    /// Charge percentage and the time of day it's expected to be full or
+    /// empty, e.g. `full 14:32`.
+    Eta,
+    /// Full capacity relative to the design capacity.
+    Health,
+    /// Charge cycle count.
+    Cycles,
+    /// Instantaneous power draw in watts.
+    Power,
+}
+
+#[cfg(target_os = "linux")]
+pub struct BatteryModule {
+    dev_path: Vec<PathBuf>,
+    display: BatteryDisplay,
+    low_threshold: u64,
+    blink: bool,
+    blink_on: bool,
+    hide_when_full: bool,
+    /// Charge glyphs overriding the theme's battery levels.
+    icons: Option<Vec<char>>,
+    /// Width of a progress bar shown instead of the charge glyph.
+    progress_width: Option<usize>,
+    /// Recent `current_now` readings, averaged for the time-remaining estimate.
+    currents: History<u64>,
+    /// Recent charge percentages, whose trend is preferred for the estimate
+    /// once it's clear, see [`percent_trend`].
+    percents: History<(Instant, u64)>,
+    last_state: Option<BatteryState>,
+    was_low: bool,
+    /// Called with every [`BatteryEvent`], e.g. to send a notification.
+    on_state_change: Option<Box<dyn FnMut(BatteryEvent)>>,
+    theme: Arc<Theme>,
+}
+
+/// A change of the battery's state worth telling the user about.
+#[cfg(target_os = "linux")]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum BatteryEvent {
+    /// Discharging dropped below the low threshold, at this percentage.
+    Low(u64),
+    ChargingStarted,
+    /// Unplugged, whether charging or already full, or done charging.
+    ChargingStopped,
+}
+
+#[cfg(target_os = "linux")]
+impl Display for BatteryEvent {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            BatteryEvent::Low(_) => "low",
+            BatteryEvent::ChargingStarted => "charging_started",
+            BatteryEvent::ChargingStopped => "charging_stopped",
+        })
+    }
+}
+
+/// Number of `current_now` readings averaged unless configured otherwise.
+#[cfg(target_os = "linux")]
+const DEFAULT_BATTERY_SMOOTHING: usize = 6;
+
+/// Number of charge percentages the trend is fitted over; at the battery's
+/// rate of 5 ticks that's about 10 minutes.
+#[cfg(target_os = "linux")]
+const BATTERY_TREND_SAMPLES: usize = 120;
+
+/// Percentage points the charge must have moved by before its trend is
+/// trusted, since single steps of a whole percent say little about the rate.
+#[cfg(target_os = "linux")]
+const MIN_TREND_PERCENT: f32 = 2.;
+
+#[cfg(target_os = "linux")]
+impl BatteryModule {
+    pub fn new<P: AsRef<Path>>(path: impl IntoIterator<Item = P>) -> Self {
+        BatteryModule {
+            dev_path: path.into_iter().map(|p| p.as_ref().to_path_buf()).collect(),
+            display: BatteryDisplay::Charge,
+            low_threshold: 10,
+            blink: false,
+            blink_on: false,
+            hide_when_full: false,
+            icons: None,
+            progress_width: None,
+            currents: History::new(DEFAULT_BATTERY_SMOOTHING),
+            percents: History::new(BATTERY_TREND_SAMPLES),
+            last_state: None,
+            was_low: false,
+            on_state_change: None,
+            theme: Arc::default(),
+        }
+    }
+
+    /// Replaces the charge glyphs, ordered from empty to full and spread
+    /// evenly over the percentage range. An empty set keeps the theme's.
+    pub fn with_icons(mut self, icons: impl IntoIterator<Item = char>) -> Self {
+        let icons: Vec<char> = icons.into_iter().collect();
+        if !icons.is_empty() {
+            self.icons = Some(icons);
+        }
+        self
+    }
+
+    pub fn with_theme(mut self, theme: Arc<Theme>) -> Self {
+        self.theme = theme;
+        self
+    }
+
+    /// Marks the block urgent when discharging below `perc` percent.
+    pub fn with_low_threshold(mut self, perc: u64) -> Self {
+        self.low_threshold = perc;
+        self
+    }
+
+    /// Alternates the foreground color each refresh while the battery is low.
+    pub fn with_blink(mut self, blink: bool) -> Self {
+        self.blink = blink;
+        self
+    }
+
+    /// Hides the block while plugged in and fully charged.
+    pub fn with_hide_when_full(mut self, hide: bool) -> Self {
+        self.hide_when_full = hide;
+        self
+    }
+
+    pub fn with_display(mut self, display: BatteryDisplay) -> Self {
+        self.display = display;
+        self
+    }
+
+    /// Averages the last `samples` current readings for the time-remaining
+    /// estimate; 1 uses the instantaneous reading.
+    pub fn with_smoothing(mut self, samples: usize) -> Self {
+        self.currents = History::new(samples.max(1));
+        self
+    }
+
+    /// Shows the charge as a progress bar `width` characters wide, e.g.
+    /// `[██████░░░░] 60%`, instead of a glyph.
+    pub fn with_progress_bar(mut self, width: usize) -> Self {
+        self.progress_width = Some(width);
+        self
+    }
+
+    /// Calls `callback` when charging starts or stops and when the battery
+    /// runs low, see [`battery_events`].
+    pub fn with_on_state_change(mut self, callback: impl FnMut(BatteryEvent) + 'static) -> Self {
+        self.on_state_change = Some(Box::new(callback));
+        self
+    }
+}
+
+/// Runs `command` with `sh -c` on every battery event, passing the event as
+/// `$BATTERY_EVENT` (`low`, `charging_started` or `charging_stopped`) and
+/// the charge as `$BATTERY_PERCENT` for low batteries.
+#[cfg(target_os = "linux")]
+pub fn battery_event_command(command: String) -> impl FnMut(BatteryEvent) {
+    move |event| {
+        let mut cmd = Command::new("sh");
+        cmd.args(["-c", &command])
+            .env("BATTERY_EVENT", event.to_string());
+        if let BatteryEvent::Low(perc) = event {
+            cmd.env("BATTERY_PERCENT", perc.to_string());
+        }
+        spawn_reaped(cmd, "battery event");
+    }
+}
+
+/// Starts a hook command without waiting for it, reaping it in the
+/// background so a slow hook can't stall the bar.
+fn spawn_reaped(mut cmd: Command, what: &str) {
+    match cmd.spawn() {
+        Ok(mut child) => drop(std::thread::spawn(move || child.wait())),
+        Err(e) => log::warn!("can't run {} command: {}", what, e),
+    }
+}
+
+#[cfg(target_os = "linux")]
+#[derive(Clone, Copy, PartialEq, Debug)]
+enum BatteryState {
+    Charging,
+    Discharging,
+    /// Plugged in and done charging: `Full` or `Not charging`.
+    Full,
+    /// Unknown, or a mix of full and unknown.
+    Idle,
+}
+
+/// Combines the `status` of several batteries: any discharging battery means
+/// the system runs on battery, even if another one is still charging.
+#[cfg(target_os = "linux")]
+fn battery_state<S: AsRef<str>>(statuses: impl IntoIterator<Item = S>) -> BatteryState {
+    let mut state = None;
+    for status in statuses {
+        match status.as_ref().trim() {
+            "Discharging" => return BatteryState::Discharging,
+            "Charging" => state = Some(BatteryState::Charging),
+            "Full" | "Not charging" if state.is_none() => state = Some(BatteryState::Full),
+            "Full" | "Not charging" => (),
+            _ if state == Some(BatteryState::Charging) => (),
+            _ => state = Some(BatteryState::Idle),
+        }
+    }
+    state.unwrap_or(BatteryState::Idle)
+}
+
+/// Whether every battery reports being done charging while plugged in.
+#[cfg(target_os = "linux")]
+fn battery_full_on_ac<S: AsRef<str>>(statuses: &[S], perc: u64) -> bool {
+    perc >= 99 && battery_state(statuses) == BatteryState::Full
+}
+
+/// Power draw in watts, from `power_now` (µW) when the driver has it, or
+/// else from `voltage_now` (µV) times `current_now` (µA).
+#[cfg(target_os = "linux")]
+fn battery_watts(power: Option<u64>, voltage: Option<u64>, current: Option<u64>) -> Option<f64> {
+    match (power, voltage, current) {
+        (Some(power), _, _) => Some(power as f64 / 1e6),
+        (None, Some(voltage), Some(current)) => Some(voltage as f64 * current as f64 / 1e12),
+        _ => None,
+    }
+}
+
+/// Picks the icon for `perc` from a set ordered from empty to full.
+#[cfg(target_os = "linux")]
+fn battery_icon(icons: &[char], perc: u64) -> char {
+    let last = icons.len().saturating_sub(1);
+    let index = (perc.min(100) as usize * last) / 100;
+    icons.get(index).copied().unwrap_or(' ')
+}
+
+/// Mean of the buffered current readings, `None` while there are none.
+#[cfg(target_os = "linux")]
+fn smoothed_current(currents: &History<u64>) -> Option<f32> {
+    (!currents.is_empty()).then(|| currents.iter().sum::<u64>() as f32 / currents.len() as f32)
+}
+
+/// Hours until a least-squares line through `(seconds, percent)` samples
+/// reaches `target`, or `None` unless the charge moved by at least
+/// [`MIN_TREND_PERCENT`] and is heading toward `target`.
+#[cfg(target_os = "linux")]
+fn percent_trend(samples: &[(f32, f32)], target: f32) -> Option<f32> {
+    let (low, high) = samples
+        .iter()
+        .fold((f32::MAX, f32::MIN), |(lo, hi), (_, p)| {
+            (lo.min(*p), hi.max(*p))
+        });
+    if high - low < MIN_TREND_PERCENT {
+        return None;
+    }
+    let n = samples.len() as f32;
+    let mean_t = samples.iter().map(|(t, _)| t).sum::<f32>() / n;
+    let mean_p = samples.iter().map(|(_, p)| p).sum::<f32>() / n;
+    let (cov, var) = samples.iter().fold((0., 0.), |(cov, var), (t, p)| {
+        (
+            cov + (t - mean_t) * (p - mean_p),
+            var + (t - mean_t).powi(2),
+        )
+    });
+    let slope = cov / var;
+    let (last_t, _) = samples.last()?;
+    let now = mean_p + slope * (last_t - mean_t);
+    let secs = (target - now) / slope;
+    (secs.is_finite() && secs > 0.).then(|| secs / 3600.)
+}
+
+/// Events for going from the `previous` state and low flag to the current
+/// ones. A low battery is reported once per dip below the threshold, even
+/// right at startup; charging changes need a known previous state. Leaving
+/// `Charging` and starting to discharge both count as charging stopped.
+#[cfg(target_os = "linux")]
+fn battery_events(
+    previous: Option<(BatteryState, bool)>,
+    state: BatteryState,
+    low: bool,
+    perc: u64,
+) -> Vec<BatteryEvent> {
+    let mut events = Vec::new();
+    let was_low = previous.is_some_and(|(_, low)| low);
+    if let Some((prev, _)) = previous {
+        let charging = |s| s == BatteryState::Charging;
+        let discharging = |s| s == BatteryState::Discharging;
+        if !charging(prev) && charging(state) {
+            events.push(BatteryEvent::ChargingStarted);
+        } else if charging(prev) && !charging(state) || !discharging(prev) && discharging(state) {
+            events.push(BatteryEvent::ChargingStopped);
+        }
+    }
+    if low && !was_low {
+        events.push(BatteryEvent::Low(perc));
+    }
+    events
+}
+
+#[cfg(target_os = "linux")]
+fn battery_low(perc: u64, discharging: bool, threshold: u64) -> bool {
+    discharging && perc < threshold
+}
+
+#[cfg(target_os = "linux")]
+fn battery_health(full: u64, full_design: u64) -> Option<u64> {
+    (full_design > 0).then(|| 100 * full / full_design)
+}
+
+#[cfg(target_os = "linux")]
+impl Module for BatteryModule {
+    fn get_output(&mut self) -> ModuleRes {
+        let get_measure = |file: &str| {
+            self.dev_path
+                .iter()
+                .map(|p| {
+                    read_to_string(p.join(file))
+                        .map(|v| v.trim().parse::<u64>().ok())
+                        .ok()
+                        .flatten()
+                })
+                .reduce(|a, n| Some(a? + n?))
+                .flatten()
+        };
+
+        match self.display {
+            BatteryDisplay::Health => {
+                let full = get_measure("charge_full").ok_or(None)?;
+                let design = get_measure("charge_full_design").ok_or(None)?;
+                let health = battery_health(full, design).ok_or(None)?;
+                return Ok(ModuleOutput::new(format!("health {}%", health)));
+            }
+            BatteryDisplay::Cycles => {
+                let cycles = get_measure("cycle_count").ok_or(None)?;
+                return Ok(ModuleOutput::new(format!("{} cycles", cycles)));
+            }
+            BatteryDisplay::Power => {
+                let read = |p: &PathBuf, file: &str| {
+                    read_to_string(p.join(file))
+                        .ok()?
+                        .trim()
+                        .parse::<u64>()
+                        .ok()
+                };
+                let watts = self
+                    .dev_path
+                    .iter()
+                    .filter_map(|p| {
+                        battery_watts(
+                            read(p, "power_now"),
+                            read(p, "voltage_now"),
+                            read(p, "current_now"),
+                        )
+                    })
+                    .reduce(|a, n| a + n)
+                    .ok_or(None)?;
+                return Ok(ModuleOutput::new(format!("{:.1}W", watts)));
+            }
+            BatteryDisplay::Charge | BatteryDisplay::Eta => (),
+        }
+
+        let ecap = get_measure("charge_full");
+        let enow = get_measure("charge_now");
+        let cnow = get_measure("current_now");
+        // The driver's own `capacity` accounts for the discharge curve, so
+        // it's preferred over our charge ratio whenever every battery has it.
+        let capacity = self
+            .dev_path
+            .iter()
+            .map(|p| {
+                read_to_string(p.join("capacity"))
+                    .ok()?
+                    .trim()
+                    .parse::<u64>()
+                    .ok()
+            })
+            .collect::<Option<Vec<_>>>()
+            .filter(|c| !c.is_empty());
+        let perc = match (capacity, ecap, enow) {
+            (Some(c), _, _) => c.iter().sum::<u64>() / c.len() as u64,
+            (None, Some(ecap), Some(enow)) if ecap > 0 => (100 * enow) / ecap,
+            _ => return Err(ModuleError::Hidden),
+        };
+
+        let mut hours_left = 0.;
+
+        let mut out = ModuleOutput::new("".to_string());
+        let mut bat = match &self.icons {
+            Some(icons) => battery_icon(icons, perc),
+            None => battery_icon(&self.theme.glyphs.battery_levels(), perc),
+        };
+        let statuses: Vec<String> = self
+            .dev_path
+            .iter()
+            .filter_map(|p| read_to_string(p.join("status")).ok())
+            .collect();
+        if self.hide_when_full && battery_full_on_ac(&statuses, perc) {
+            return Err(ModuleError::Hidden);
+        }
+        let state = battery_state(&statuses);
+        let previous = self.last_state.replace(state);
+        // Charging and discharging currents don't mix, so start over on change.
+        if previous != Some(state) {
+            self.currents.clear();
+            self.percents.clear();
+        }
+        if let Some(cnow) = cnow {
+            self.currents.push(cnow);
+        }
+        self.percents.push((Instant::now(), perc));
+        let charge = ecap.zip(enow).zip(smoothed_current(&self.currents));
+        let trend = |target| {
+            let (start, _) = self.percents.iter().next()?;
+            let samples: Vec<(f32, f32)> = self
+                .percents
+                .iter()
+                .map(|(at, p)| (at.duration_since(*start).as_secs_f32(), *p as f32))
+                .collect();
+            percent_trend(&samples, target)
+        };
+        match state {
+            BatteryState::Charging => {
+                out = out.with_color_fg(self.theme.ok());
+                if let Some(hours) = trend(100.) {
+                    hours_left = hours;
+                } else if let Some(((ecap, enow), cnow)) = charge {
+                    hours_left = ecap.saturating_sub(enow) as f32 / cnow;
+                }
+            }
+            BatteryState::Discharging => {
+                out = out.with_color_fg(self.theme.crit());
+                if let Some(hours) = trend(0.) {
+                    hours_left = hours;
+                } else if let Some(((_, enow), cnow)) = charge {
+                    hours_left = enow as f32 / cnow;
+                }
+            }
+            BatteryState::Full => {
+                out = out.with_color_fg(self.theme.fg());
+                bat = self.theme.glyphs.get("battery_plugged");
+            }
+            BatteryState::Idle => (),
+        }
+
+        let low = battery_low(perc, state == BatteryState::Discharging, self.low_threshold);
+        if let Some(callback) = &mut self.on_state_change {
+            let previous = previous.map(|prev| (prev, self.was_low));
+            for event in battery_events(previous, state, low, perc) {
+                callback(event);
+            }
+        }
+        self.was_low = low;
+        if low {
+            out = out.with_urgent(true);
+            if self.blink {
+                self.blink_on = !self.blink_on;
+                if self.blink_on {
+                    out = out.with_color_fg(self.theme.fg());
+                }
+            }
+        }
+
+        let bat = match self.progress_width {
+            Some(width) => format!("[{}]", progress_bar(perc as f32, width)),
+            None => bat.to_string(),
+        };
+        let left = match self.display {
+            BatteryDisplay::Eta => {
+                let until = if state == BatteryState::Charging {
+                    "full"
+                } else {
+                    "empty"
+                };
+                eta_clock(Local::now(), hours_left).map(|at| format!("{} {}", until, at))
+            }
+            _ => format_time_remaining(hours_left),
+        };
+        out = match left {
+            Some(left) => out.with_content(format!("{} {}% [{}]", bat, perc, left)),
+            None => out.with_content(format!("{} {}%", bat, perc)),
+        };
+
+        Ok(out)
+    }
+
+    fn rate(&self) -> usize {
+        5
+    }
+
+    /// Stops polling when no configured battery is present, e.g. on a desktop,
+    /// unless the block is also hidden on purpose while on AC.
+    fn hidden_limit(&self) -> Option<usize> {
+        (!self.hide_when_full).then_some(3)
+    }
+
+    /// A full battery; the time remaining comes and goes, so it isn't covered.
+    fn min_width(&self) -> Option<MinWidth> {
+        let widest = match self.display {
+            BatteryDisplay::Charge | BatteryDisplay::Eta => {
+                let bat = match (&self.icons, self.progress_width) {
+                    (_, Some(width)) => format!("[{}]", progress_bar(100., width)),
+                    (Some(icons), None) => battery_icon(icons, 100).to_string(),
+                    (None, None) => {
+                        battery_icon(&self.theme.glyphs.battery_levels(), 100).to_string()
+                    }
+                };
+                format!("{} 100%", bat)
+            }
+            BatteryDisplay::Health => "health 100%".to_string(),
+            BatteryDisplay::Cycles | BatteryDisplay::Power => return None,
+        };
+        Some(MinWidth::Text(widest))
+    }
+}
+
+pub struct SpacerModule {
+    data: String,
+    flexible: bool,
+}
+
+impl SpacerModule {
+    pub fn new(width: usize) -> Self {
+        let mut data = String::with_capacity(width);
+        for _ in 0..width {
+            data.push(' ');
+        }
+        SpacerModule {
+            data,
+            flexible: false,
+        }
+    }
+
+    /// A spacer taking up whatever room the other blocks leave on a bar of
+    /// the width given by [`Modules::with_bar_width`], pushing the blocks
+    /// after it to the right.
+    pub fn flexible() -> Self {
+        SpacerModule {
+            data: String::new(),
+            flexible: true,
+        }
+    }
+}
+
+/// Splits the room `fixed` columns of other blocks leave on a `total` wide
+/// bar between `spacers` flexible spacers, the first ones taking any rest.
+fn spacer_widths(total: usize, fixed: usize, spacers: usize) -> Vec<usize> {
+    let free = total.saturating_sub(fixed);
+    (0..spacers)
+        .map(|i| free / spacers + usize::from(i < free % spacers))
+        .collect()
+}
+
+impl Module for SpacerModule {
+    fn get_output(&mut self) -> ModuleRes {
+        Ok(ModuleOutput::new(self.data.clone()).with_flexible(self.flexible))
+    }
+}
+
+pub struct PublicIpModule {
+    endpoint: String,
+    agent: ureq::Agent,
+}
+
+impl PublicIpModule {
+    pub fn new() -> Self {
+        PublicIpModule {
+            endpoint: "https://api.ipify.org".to_string(),
+            agent: ureq::AgentBuilder::new()
+                .timeout(Duration::from_secs(2))
+                .build(),
+        }
+    }
+
+    pub fn with_endpoint(mut self, endpoint: &str) -> Self {
+        self.endpoint = endpoint.to_string();
+        self
+    }
+}
+
+/// Parses the plain-text body returned by services like ipify or ifconfig.me.
+fn parse_ip_response(body: &str) -> Option<IpAddr> {
+    body.trim().parse().ok()
+}
+
+impl Default for PublicIpModule {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Module for PublicIpModule {
+    fn get_output(&mut self) -> ModuleRes {
+        let body = self
+            .agent
+            .get(&self.endpoint)
+            .call()
+            .ok()
+            .and_then(|res| res.into_string().ok())
+            .ok_or(None)?;
+        let ip = parse_ip_response(&body).ok_or(None)?;
+
+        Ok(ModuleOutput::new(ip.to_string()))
+    }
+
+    fn rate(&self) -> usize {
+        300
+    }
+}
+
+#[cfg(all(target_os = "linux", feature = "network"))]
+const NM_BUS: &str = "org.freedesktop.NetworkManager";
+#[cfg(all(target_os = "linux", feature = "network"))]
+const NM_ACTIVE_IFACE: &str = "org.freedesktop.NetworkManager.Connection.Active";
+#[cfg(all(target_os = "linux", feature = "network"))]
+const NM_ACTIVE_ACTIVATING: u32 = 1;
+#[cfg(all(target_os = "linux", feature = "network"))]
+const NM_ACTIVE_ACTIVATED: u32 = 2;
+
+#[cfg(all(target_os = "linux", feature = "network"))]
+const NM_DEVICE_IFACE: &str = "org.freedesktop.NetworkManager.Device";
+
+/// Picks the interface of the `primary` active connection out of each active
+/// connection's `(path, interfaces)`. NetworkManager reports `/` as the
+/// primary connection while offline.
+#[cfg(all(target_os = "linux", feature = "network"))]
+fn select_primary_interface(primary: &str, active: &[(String, Vec<String>)]) -> Option<String> {
+    active
+        .iter()
+        .find(|(path, _)| path == primary)
+        .and_then(|(_, ifaces)| ifaces.first().cloned())
+}
+
+/// Asks NetworkManager for the interface of the primary connection.
+#[cfg(all(target_os = "linux", feature = "network"))]
+fn primary_interface(dbus: &Connection) -> Option<String> {
+    let timeout = Duration::from_millis(500);
+    let nm = dbus.with_proxy(NM_BUS, "/org/freedesktop/NetworkManager", timeout);
+    let primary: dbus::Path = nm.get(NM_BUS, "PrimaryConnection").ok()?;
+    let active: Vec<dbus::Path> = nm.get(NM_BUS, "ActiveConnections").ok()?;
+
+    let active: Vec<(String, Vec<String>)> = active
+        .into_iter()
+        .filter_map(|path| {
+            let conn = dbus.with_proxy(NM_BUS, path.clone(), timeout);
+            let devices: Vec<dbus::Path> = conn.get(NM_ACTIVE_IFACE, "Devices").ok()?;
+            let ifaces = devices
+                .into_iter()
+                .filter_map(|dev| {
+                    dbus.with_proxy(NM_BUS, dev, timeout)
+                        .get(NM_DEVICE_IFACE, "IpInterface")
+                        .ok()
+                })
+                .collect();
+            Some((path.to_string(), ifaces))
+        })
+        .collect();
+    select_primary_interface(&primary, &active)
+}
+
+#[cfg(all(target_os = "linux", feature = "network"))]
+pub struct VpnModule {
+    theme: Arc<Theme>,
+}
+
+#[cfg(all(target_os = "linux", feature = "network"))]
+impl VpnModule {
+    pub fn new() -> Self {
+        VpnModule {
+            theme: Arc::default(),
+        }
+    }
+
+    pub fn with_theme(mut self, theme: Arc<Theme>) -> Self {
+        self.theme = theme;
+        self
+    }
+}
+
+#[cfg(all(target_os = "linux", feature = "network"))]
+impl Default for VpnModule {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Maps the `(name, state)` of every active tunnel connection to the block
+/// content and whether all of them are fully up. Returns `None` when no
+/// tunnel is up or coming up.
+#[cfg(all(target_os = "linux", feature = "network"))]
+fn vpn_status(tunnels: &[(String, u32)], glyphs: &Glyphs) -> Option<(String, bool)> {
+    let names: Vec<&str> = tunnels
+        .iter()
+        .filter(|(_, state)| matches!(*state, NM_ACTIVE_ACTIVATING | NM_ACTIVE_ACTIVATED))
+        .map(|(name, _)| name.as_str())
+        .collect();
+    if names.is_empty() {
+        return None;
+    }
+
+    let up = tunnels
+        .iter()
+        .all(|(_, state)| *state != NM_ACTIVE_ACTIVATING);
+    Some((format!("{} {}", glyphs.get("vpn"), names.join(", ")), up))
+}
+
+#[cfg(all(target_os = "linux", feature = "network"))]
+impl Module for VpnModule {
+    fn get_output(&mut self) -> ModuleRes {
+        let dbus = Connection::new_system().map_err(|_| "dbus unavailable".to_string())?;
+        let timeout = Duration::from_millis(500);
+
+        let nm = dbus.with_proxy(NM_BUS, "/org/freedesktop/NetworkManager", timeout);
+        let active: Vec<dbus::Path> = nm.get(NM_BUS, "ActiveConnections").map_err(|_| None)?;
+
+        let tunnels: Vec<(String, u32)> = active
+            .into_iter()
+            .filter_map(|path| {
+                let conn = dbus.with_proxy(NM_BUS, path, timeout);
+                let vpn: bool = conn.get(NM_ACTIVE_IFACE, "Vpn").ok()?;
+                let kind: String = conn.get(NM_ACTIVE_IFACE, "Type").ok()?;
+                if !vpn && kind != "wireguard" && kind != "tun" {
+                    return None;
+                }
+                Some((
+                    conn.get(NM_ACTIVE_IFACE, "Id").ok()?,
+                    conn.get(NM_ACTIVE_IFACE, "State").ok()?,
+                ))
+            })
+            .collect();
+
+        let (content, up) = vpn_status(&tunnels, &self.theme.glyphs).ok_or(None)?;
+        Ok(ModuleOutput::new(content).with_color_fg(if up {
+            self.theme.ok()
+        } else {
+            self.theme.warn()
+        }))
+    }
+
+    fn rate(&self) -> usize {
+        5
+    }
+}
+
+#[cfg(all(target_os = "linux", feature = "dbus-modules"))]
+const MPRIS_PREFIX: &str = "org.mpris.MediaPlayer2.";
+#[cfg(all(target_os = "linux", feature = "dbus-modules"))]
+const MPRIS_PLAYER_IFACE: &str = "org.mpris.MediaPlayer2.Player";
+
+#[cfg(all(target_os = "linux", feature = "dbus-modules"))]
+pub struct MediaModule {
+    max_len: usize,
+}
+
+#[cfg(all(target_os = "linux", feature = "dbus-modules"))]
+impl MediaModule {
+    pub fn new() -> Self {
+        MediaModule { max_len: 40 }
+    }
+
+    pub fn with_max_len(mut self, max_len: usize) -> Self {
+        self.max_len = max_len;
+        self
+    }
+}
+
+#[cfg(all(target_os = "linux", feature = "dbus-modules"))]
+fn media_label(status: &str, artist: Option<&str>, title: Option<&str>) -> String {
+    let icon = match status {
+        "Playing" => '▶',
+        "Paused" => '⏸',
+        _ => '■',
+    };
+    let track = match (artist, title) {
+        (Some(artist), Some(title)) => format!("{} – {}", artist, title),
+        (None, Some(title)) => title.to_string(),
+        (Some(artist), None) => artist.to_string(),
+        (None, None) => "unknown".to_string(),
+    };
+    format!("{} {}", icon, track)
+}
+
+#[cfg(all(target_os = "linux", feature = "dbus-modules"))]
+impl Default for MediaModule {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(all(target_os = "linux", feature = "dbus-modules"))]
+impl Module for MediaModule {
+    fn get_output(&mut self) -> ModuleRes {
+        let dbus = Connection::new_session().map_err(|_| None)?;
+        let timeout = Duration::from_millis(500);
+
+        let (names,): (Vec<String>,) = dbus
+            .with_proxy("org.freedesktop.DBus", "/org/freedesktop/DBus", timeout)
+            .method_call("org.freedesktop.DBus", "ListNames", ())
+            .map_err(|_| None)?;
+
+        let players: Vec<(String, dbus::arg::PropMap)> = names
+            .into_iter()
+            .filter(|name| name.starts_with(MPRIS_PREFIX))
+            .filter_map(|name| {
+                let player = dbus.with_proxy(name, "/org/mpris/MediaPlayer2", timeout);
+                let status: String = player.get(MPRIS_PLAYER_IFACE, "PlaybackStatus").ok()?;
+                let meta = player.get(MPRIS_PLAYER_IFACE, "Metadata").ok()?;
+                Some((status, meta))
+            })
+            .collect();
+
+        let (status, meta) = players
+            .iter()
+            .find(|(status, _)| status == "Playing")
+            .or_else(|| players.first())
+            .ok_or(None)?;
+
+        let title = meta.get("xesam:title").and_then(|v| v.0.as_str());
+        let artist = meta
+            .get("xesam:artist")
+            .and_then(|v| v.0.as_iter())
+            .and_then(|mut artists| artists.next())
+            .and_then(|a| a.as_str().map(str::to_string));
+
+        Ok(
+            ModuleOutput::new(media_label(status, artist.as_deref(), title))
+                .with_max_len(self.max_len),
+        )
+    }
+}
+
+#[cfg(target_os = "linux")]
+#[derive(Clone, Copy, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum UpdatesBackend {
+    /// `checkupdates` from pacman-contrib.
+    Pacman,
+    /// `apt list --upgradable`.
+    Apt,
+}
+
+#[cfg(target_os = "linux")]
+pub struct UpdatesModule {
+    backend: UpdatesBackend,
+}
+
+#[cfg(target_os = "linux")]
+impl UpdatesModule {
+    pub fn new(backend: UpdatesBackend) -> Self {
+        UpdatesModule { backend }
+    }
+}
+
+#[cfg(target_os = "linux")]
+fn count_updates(backend: UpdatesBackend, output: &str) -> usize {
+    let lines = output.lines().filter(|l| !l.trim().is_empty());
+    match backend {
+        UpdatesBackend::Pacman => lines.count(),
+        UpdatesBackend::Apt => lines.filter(|l| l.contains("[upgradable from")).count(),
+    }
+}
+
+#[cfg(target_os = "linux")]
+impl Module for UpdatesModule {
+    fn get_output(&mut self) -> ModuleRes {
+        let output = match self.backend {
+            UpdatesBackend::Pacman => Command::new("checkupdates").output(),
+            UpdatesBackend::Apt => Command::new("apt").args(["list", "--upgradable"]).output(),
+        }
+        .map_err(|_| None)?;
+
+        let count = count_updates(self.backend, &String::from_utf8_lossy(&output.stdout));
+        if count == 0 {
+            return Err(ModuleError::Hidden);
+        }
+
+        Ok(ModuleOutput::new(format!("⬆ {}", count)))
+    }
+
+    fn rate(&self) -> usize {
+        1800
+    }
+}
+
+pub struct GitModule {
+    path: PathBuf,
+    theme: Arc<Theme>,
+}
+
+impl GitModule {
+    pub fn new(path: &str) -> Self {
+        GitModule {
+            path: PathBuf::from(path),
+            theme: Arc::default(),
+        }
+    }
+
+    pub fn with_theme(mut self, theme: Arc<Theme>) -> Self {
+        self.theme = theme;
+        self
+    }
+}
+
+/// Parses `git status --porcelain -b` into the branch name and whether the
+/// working tree has uncommitted changes.
+fn parse_git_status(output: &str) -> Option<(String, bool)> {
+    let mut lines = output.lines();
+    let header = lines.next()?.strip_prefix("## ")?;
+    let header = header.strip_prefix("No commits yet on ").unwrap_or(header);
+    let branch = header
+        .split("...")
+        .next()?
+        .split(' ')
+        .next()
+        .filter(|b| !b.is_empty())?;
+    let dirty = lines.any(|l| !l.trim().is_empty());
+    Some((branch.to_string(), dirty))
+}
+
+impl Module for GitModule {
+    fn get_output(&mut self) -> ModuleRes {
+        let output = Command::new("git")
+            .arg("-C")
+            .arg(&self.path)
+            .args(["status", "--porcelain", "-b"])
+            .output()
+            .map_err(|_| None)?;
+        if !output.status.success() {
+            return Err(ModuleError::Hidden);
+        }
+
+        let (branch, dirty) =
+            parse_git_status(&String::from_utf8_lossy(&output.stdout)).ok_or(None)?;
+        Ok(ModuleOutput::new(format!(
+            "{} {}{}",
+            self.theme.glyphs.get("git_branch"),
+            branch,
+            if dirty { "*" } else { "" }
+        )))
+    }
+
+    fn rate(&self) -> usize {
+        5
+    }
+}
+
+#[cfg(feature = "sysinfo-modules")]
+pub struct ProcessCountModule {
+    threshold: usize,
+    system: System,
+    theme: Arc<Theme>,
+}
+
+#[cfg(feature = "sysinfo-modules")]
+impl ProcessCountModule {
+    pub fn new() -> Self {
+        ProcessCountModule {
+            threshold: 1000,
+            system: System::new(),
+            theme: Arc::default(),
+        }
+    }
+
+    pub fn with_theme(mut self, theme: Arc<Theme>) -> Self {
+        self.theme = theme;
+        self
+    }
+
+    pub fn with_threshold(mut self, threshold: usize) -> Self {
+        self.threshold = threshold;
+        self
+    }
+}
+
+#[cfg(feature = "sysinfo-modules")]
+fn process_count_label(count: usize) -> String {
+    format!("procs: {}", count)
+}
+
+#[cfg(feature = "sysinfo-modules")]
+impl Default for ProcessCountModule {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(feature = "sysinfo-modules")]
+impl Module for ProcessCountModule {
+    fn get_output(&mut self) -> ModuleRes {
+        self.system.refresh_processes();
+
+        let count = self.system.processes().len();
+        let mut out = ModuleOutput::new(process_count_label(count));
+        if count > self.threshold {
+            out = out.with_color_fg(self.theme.crit());
+        }
+
+        Ok(out)
+    }
+
+    fn rate(&self) -> usize {
+        2
+    }
+}
+
+#[cfg(feature = "sysinfo-modules")]
+pub struct UptimeModule {
+    system: System,
+}
+
+#[cfg(feature = "sysinfo-modules")]
+impl UptimeModule {
+    pub fn new() -> Self {
+        UptimeModule {
+            system: System::new(),
+        }
+    }
+}
+
+#[cfg(feature = "sysinfo-modules")]
+impl Default for UptimeModule {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(feature = "sysinfo-modules")]
+impl Module for UptimeModule {
+    fn get_output(&mut self) -> ModuleRes {
+        Ok(ModuleOutput::new(format!(
+            "up {}",
+            format_duration(self.system.uptime())
+        )))
+    }
+
+    fn rate(&self) -> usize {
+        30
+    }
+}
+
+pub struct WeatherModule {
+    latitude: f32,
+    longitude: f32,
+    endpoint: String,
+    agent: ureq::Agent,
+}
+
+impl WeatherModule {
+    pub fn new(latitude: f32, longitude: f32) -> Self {
+        WeatherModule {
+            latitude,
+            longitude,
+            endpoint: "https://api.open-meteo.com/v1/forecast".to_string(),
+            agent: ureq::AgentBuilder::new()
+                .timeout(Duration::from_secs(2))
+                .build(),
+        }
+    }
+
+    /// Sets an Open-Meteo compatible forecast endpoint.
+    pub fn with_endpoint(mut self, endpoint: &str) -> Self {
+        self.endpoint = endpoint.to_string();
+        self
+    }
+}
+
+#[derive(Deserialize)]
+struct CurrentWeather {
+    temperature: f32,
+    weathercode: u32,
+}
+
+#[derive(Deserialize)]
+struct WeatherResponse {
+    current_weather: CurrentWeather,
+}
+
+/// Parses an Open-Meteo `current_weather` response into the temperature and WMO weather code.
+fn parse_weather(body: &str) -> Option<(f32, u32)> {
+    let res: WeatherResponse = serde_json::from_str(body).ok()?;
+    Some((
+        res.current_weather.temperature,
+        res.current_weather.weathercode,
+    ))
+}
+
+fn weather_glyph(code: u32) -> char {
+    match code {
+        0 => '☀',
+        1..=3 => '⛅',
+        45 | 48 => '🌫',
+        51..=67 | 80..=82 => '🌧',
+        71..=77 | 85 | 86 => '❄',
+        95..=99 => '⛈',
+        _ => '?',
+    }
+}
+
+impl Module for WeatherModule {
+    fn get_output(&mut self) -> ModuleRes {
+        let body = self
+            .agent
+            .get(&self.endpoint)
+            .query("latitude", &self.latitude.to_string())
+            .query("longitude", &self.longitude.to_string())
+            .query("current_weather", "true")
+            .call()
+            .ok()
+            .and_then(|res| res.into_string().ok())
+            .ok_or(None)?;
+        let (temp, code) = parse_weather(&body).ok_or(None)?;
+
+        Ok(ModuleOutput::new(format!(
+            "{} {:.0}°C",
+            weather_glyph(code),
+            temp
+        )))
+    }
+
+    fn rate(&self) -> usize {
+        600
+    }
+}
+
+#[cfg(target_os = "linux")]
+pub struct MicModule {
+    theme: Arc<Theme>,
+}
+
+#[cfg(target_os = "linux")]
+impl MicModule {
+    pub fn new() -> Self {
+        MicModule {
+            theme: Arc::default(),
+        }
+    }
+
+    pub fn with_theme(mut self, theme: Arc<Theme>) -> Self {
+        self.theme = theme;
+        self
+    }
+}
+
+#[cfg(target_os = "linux")]
+impl Default for MicModule {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Parses the output of `pactl get-source-mute`, e.g. `Mute: yes`.
+#[cfg(target_os = "linux")]
+fn parse_pactl_mute(output: &str) -> Option<bool> {
+    match output.trim().strip_prefix("Mute:")?.trim() {
+        "yes" => Some(true),
+        "no" => Some(false),
+        _ => None,
+    }
+}
+
+#[cfg(target_os = "linux")]
+fn mic_glyph(muted: bool, glyphs: &Glyphs) -> String {
+    let mic = glyphs.get("microphone");
+    if muted {
+        format!("{}\u{338}", mic)
+    } else {
+        mic.to_string()
+    }
+}
+
+#[cfg(target_os = "linux")]
+impl Module for MicModule {
+    fn get_output(&mut self) -> ModuleRes {
+        let output = Command::new("pactl")
+            .args(["get-source-mute", "@DEFAULT_SOURCE@"])
+            .output()
+            .map_err(|_| None)?;
+        let muted = parse_pactl_mute(&String::from_utf8_lossy(&output.stdout)).ok_or(None)?;
+
+        let mut out = ModuleOutput::new(mic_glyph(muted, &self.theme.glyphs));
+        if muted {
+            out = out.with_color_fg(self.theme.crit());
+        }
+        Ok(out)
+    }
+}
+
+#[cfg(target_os = "linux")]
+const LOCK_CAPS: u8 = 1;
+#[cfg(target_os = "linux")]
+const LOCK_NUM: u8 = 1 << 1;
+
+#[cfg(target_os = "linux")]
+pub struct LockKeysModule;
+
+#[cfg(target_os = "linux")]
+fn lock_keys_label(mask: u8) -> String {
+    [(LOCK_CAPS, "CAPS"), (LOCK_NUM, "NUM")]
+        .iter()
+        .filter(|(bit, _)| mask & bit != 0)
+        .map(|(_, label)| *label)
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+#[cfg(target_os = "linux")]
+impl Module for LockKeysModule {
+    fn get_output(&mut self) -> ModuleRes {
+        let leds = read_dir("/sys/class/leds").map_err(|_| None)?;
+
+        let mask = leds
+            .filter_map(|e| e.ok())
+            .filter_map(|e| {
+                let name = e.file_name().into_string().ok()?;
+                let bit = if name.ends_with("::capslock") {
+                    LOCK_CAPS
+                } else if name.ends_with("::numlock") {
+                    LOCK_NUM
+                } else {
+                    return None;
+                };
+                let on = read_to_string(e.path().join("brightness")).ok()?.trim() != "0";
+                on.then_some(bit)
+            })
+            .fold(0, |a, n| a | n);
+
+        Ok(ModuleOutput::new(lock_keys_label(mask)))
+    }
+}
+
+/// Source of the focused window's title, e.g. an X server or a compositor.
+#[cfg(target_os = "linux")]
+pub trait WindowTitleBackend {
+    fn title(&mut self) -> Option<String>;
+}
+
+/// Reads `_NET_ACTIVE_WINDOW` and `_NET_WM_NAME` through `xprop`.
+#[cfg(target_os = "linux")]
+pub struct X11WindowTitle;
+
+/// Finds the focused node in `swaymsg -t get_tree`.
+#[cfg(target_os = "linux")]
+pub struct SwayWindowTitle;
+
+/// Returns the value part of an `xprop` line such as
+/// `_NET_WM_NAME(UTF8_STRING) = "title"` or `_NET_ACTIVE_WINDOW(WINDOW): window id # 0x1c00007`.
+#[cfg(target_os = "linux")]
+fn parse_xprop_value(output: &str) -> Option<String> {
+    let line = output.lines().next()?;
+    if let Some((_, value)) = line.split_once(" = ") {
+        let value = value.trim();
+        let value = value
+            .strip_prefix('"')
+            .and_then(|v| v.strip_suffix('"'))
+            .unwrap_or(value);
+        return Some(value.replace("\\\"", "\""));
+    }
+    line.split_once("# ").map(|(_, id)| id.trim().to_string())
+}
+
+#[cfg(target_os = "linux")]
+fn run_xprop(args: &[&str]) -> Option<String> {
+    let output = Command::new("xprop").args(args).output().ok()?;
+    if !output.status.success() {
+        return None;
+    }
+    parse_xprop_value(&String::from_utf8_lossy(&output.stdout))
+}
+
+#[cfg(target_os = "linux")]
+impl WindowTitleBackend for X11WindowTitle {
+    fn title(&mut self) -> Option<String> {
+        let id = run_xprop(&["-root", "_NET_ACTIVE_WINDOW"])?;
+        run_xprop(&["-id", &id, "_NET_WM_NAME"])
+    }
+}
+
+#[cfg(target_os = "linux")]
+fn find_focused_title(node: &serde_json::Value) -> Option<String> {
+    if node["focused"].as_bool() == Some(true) {
+        return node["name"].as_str().map(str::to_string);
+    }
+    ["nodes", "floating_nodes"]
+        .iter()
+        .filter_map(|key| node[key].as_array())
+        .flatten()
+        .find_map(find_focused_title)
+}
+
+#[cfg(target_os = "linux")]
+impl WindowTitleBackend for SwayWindowTitle {
+    fn title(&mut self) -> Option<String> {
+        let output = Command::new("swaymsg")
+            .args(["-t", "get_tree"])
+            .output()
+            .ok()?;
+        find_focused_title(&serde_json::from_slice(&output.stdout).ok()?)
+    }
+}
+
+#[cfg(target_os = "linux")]
+pub struct WindowTitleModule {
+    backend: Option<Box<dyn WindowTitleBackend>>,
+    max_len: usize,
+}
+
+#[cfg(target_os = "linux")]
+impl WindowTitleModule {
+    pub fn new(backend: impl WindowTitleBackend + 'static) -> Self {
+        WindowTitleModule {
+            backend: Some(Box::new(backend)),
+            max_len: 50,
+        }
+    }
+
+    /// Picks sway when `$SWAYSOCK` is set, falling back to X11 when `$DISPLAY` is.
+    pub fn detect() -> Self {
+        let backend: Option<Box<dyn WindowTitleBackend>> = if env::var_os("SWAYSOCK").is_some() {
+            Some(Box::new(SwayWindowTitle))
+        } else if env::var_os("DISPLAY").is_some() {
+            Some(Box::new(X11WindowTitle))
+        } else {
+            None
+        };
+        WindowTitleModule {
+            backend,
+            max_len: 50,
+        }
+    }
+
+    pub fn with_max_len(mut self, max_len: usize) -> Self {
+        self.max_len = max_len;
+        self
+    }
+}
+
+#[cfg(target_os = "linux")]
+impl Module for WindowTitleModule {
+    fn get_output(&mut self) -> ModuleRes {
+        let title = self.backend.as_mut().ok_or(None)?.title().ok_or(None)?;
+        Ok(ModuleOutput::new(title).with_max_len(self.max_len))
+    }
+}
+
+#[cfg(target_os = "linux")]
+const IPC_MAGIC: &[u8] = b"i3-ipc";
+#[cfg(target_os = "linux")]
+const IPC_GET_WORKSPACES: u32 = 1;
+
+#[cfg(target_os = "linux")]
+pub struct WorkspaceModule;
+
+/// Sends a single message over the i3/sway IPC socket and returns the reply payload.
+#[cfg(target_os = "linux")]
+fn ipc_request(socket: &str, kind: u32) -> Option<Vec<u8>> {
+    let mut stream = UnixStream::connect(socket).ok()?;
+    stream
+        .set_read_timeout(Some(Duration::from_millis(500)))
+        .ok()?;
+
+    let mut req = IPC_MAGIC.to_vec();
+    req.extend(0u32.to_ne_bytes());
+    req.extend(kind.to_ne_bytes());
+    stream.write_all(&req).ok()?;
+
+    let mut header = [0u8; 14];
+    stream.read_exact(&mut header).ok()?;
+    let len = u32::from_ne_bytes(header[6..10].try_into().ok()?) as usize;
+    let mut payload = vec![0u8; len];
+    stream.read_exact(&mut payload).ok()?;
+    Some(payload)
+}
+
+/// Returns the name of the focused workspace in a `GET_WORKSPACES` reply.
+#[cfg(target_os = "linux")]
+fn focused_workspace(reply: &str) -> Option<String> {
+    let workspaces: serde_json::Value = serde_json::from_str(reply).ok()?;
+    workspaces
+        .as_array()?
+        .iter()
+        .find(|ws| ws["focused"].as_bool() == Some(true))
+        .and_then(|ws| ws["name"].as_str())
+        .map(str::to_string)
+}
+
+#[cfg(target_os = "linux")]
+impl Module for WorkspaceModule {
+    fn get_output(&mut self) -> ModuleRes {
+        let socket = env::var("SWAYSOCK")
+            .or_else(|_| env::var("I3SOCK"))
+            .map_err(|_| "no ipc".to_string())?;
+        let reply = ipc_request(&socket, IPC_GET_WORKSPACES).ok_or("no ipc".to_string())?;
+        let name = focused_workspace(&String::from_utf8_lossy(&reply)).ok_or(None)?;
+
+        Ok(ModuleOutput::new(name))
+    }
+}
+
+pub struct TimerModule {
+    duration: Duration,
+    started: Instant,
+    paused_at: Option<Instant>,
+    theme: Arc<Theme>,
+}
+
+impl TimerModule {
+    pub fn new(duration: Duration) -> Self {
+        TimerModule {
+            duration,
+            started: Instant::now(),
+            paused_at: None,
+            theme: Arc::default(),
+        }
+    }
+
+    pub fn with_theme(mut self, theme: Arc<Theme>) -> Self {
+        self.theme = theme;
+        self
+    }
+
+    pub fn pause(&mut self) {
+        self.paused_at.get_or_insert_with(Instant::now);
+    }
+
+    pub fn resume(&mut self) {
+        if let Some(paused_at) = self.paused_at.take() {
+            self.started += paused_at.elapsed();
+        }
+    }
+
+    /// Restarts the countdown from the full duration.
+    pub fn reset(&mut self) {
+        self.started = Instant::now();
+        self.paused_at = None;
+    }
+
+    fn remaining(&self, now: Instant) -> Duration {
+        let now = self.paused_at.unwrap_or(now);
+        self.duration
+            .saturating_sub(now.saturating_duration_since(self.started))
+    }
+}
+
+impl Module for TimerModule {
+    fn get_output(&mut self) -> ModuleRes {
+        let remaining = self.remaining(Instant::now());
+        let mut out = ModuleOutput::new(format!(
+            "⏲ {}",
+            format_clock(remaining.as_secs_f32().ceil() as u64)
+        ));
+        if remaining.is_zero() {
+            out = out.with_color_fg(self.theme.crit()).with_urgent(true);
+        }
+        Ok(out)
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum PomodoroPhase {
+    Work,
+    Break,
+}
+
+pub struct PomodoroModule {
+    work: Duration,
+    rest: Duration,
+    started: Instant,
+}
+
+impl PomodoroModule {
+    pub fn new() -> Self {
+        PomodoroModule {
+            work: Duration::from_secs(25 * 60),
+            rest: Duration::from_secs(5 * 60),
+            started: Instant::now(),
+        }
+    }
+
+    pub fn with_durations(mut self, work: Duration, rest: Duration) -> Self {
+        self.work = work;
+        self.rest = rest;
+        self
+    }
+}
+
+impl Default for PomodoroModule {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Returns the current phase and the time left in it, `elapsed` after the
+/// first work interval started.
+fn pomodoro_phase(elapsed: Duration, work: Duration, rest: Duration) -> (PomodoroPhase, Duration) {
+    let cycle = (work + rest).as_millis().max(1);
+    let into_cycle = Duration::from_millis((elapsed.as_millis() % cycle) as u64);
+    if into_cycle < work {
+        (PomodoroPhase::Work, work - into_cycle)
+    } else {
+        (PomodoroPhase::Break, work + rest - into_cycle)
+    }
+}
+
+impl Module for PomodoroModule {
+    fn get_output(&mut self) -> ModuleRes {
+        let (phase, left) = pomodoro_phase(self.started.elapsed(), self.work, self.rest);
+        let icon = match phase {
+            PomodoroPhase::Work => '🍅',
+            PomodoroPhase::Break => '☕',
+        };
+        Ok(ModuleOutput::new(format!(
+            "{} {}",
+            icon,
+            format_clock(left.as_secs_f32().ceil() as u64)
+        )))
+    }
+}
+
+pub struct UsersModule;
+
+/// Counts the distinct users in the output of `who`.
+fn count_logged_in(who: &str) -> usize {
+    let mut names: Vec<&str> = who
+        .lines()
+        .filter_map(|l| l.split_whitespace().next())
+        .collect();
+    names.sort_unstable();
+    names.dedup();
+    names.len()
+}
+
+fn users_label(count: Option<usize>) -> String {
+    match count {
+        Some(count) => format!("users: {}", count),
+        None => "users: ?".to_string(),
+    }
+}
+
+impl Module for UsersModule {
+    fn get_output(&mut self) -> ModuleRes {
+        let count = Command::new("who")
+            .output()
+            .ok()
+            .filter(|o| o.status.success())
+            .map(|o| count_logged_in(&String::from_utf8_lossy(&o.stdout)));
+        Ok(ModuleOutput::new(users_label(count)))
+    }
+
+    fn rate(&self) -> usize {
+        30
+    }
+}
+
+#[cfg(feature = "sysinfo-modules")]
+#[derive(Clone, Copy, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum OsField {
+    /// Kernel release, e.g. `6.8.0`.
+    Kernel,
+    /// Distribution name, e.g. `Arch Linux`.
+    Name,
+    /// Distribution version, e.g. `22.04`.
+    Version,
+}
+
+#[cfg(feature = "sysinfo-modules")]
+pub struct OsModule {
+    field: OsField,
+    system: System,
+}
+
+#[cfg(feature = "sysinfo-modules")]
+impl OsModule {
+    pub fn new(field: OsField) -> Self {
+        OsModule {
+            field,
+            system: System::new(),
+        }
+    }
+}
+
+#[cfg(feature = "sysinfo-modules")]
+fn os_label(field: OsField, system: &impl SystemExt) -> Option<String> {
+    match field {
+        OsField::Kernel => system.kernel_version(),
+        OsField::Name => system.name(),
+        OsField::Version => system.os_version(),
+    }
+}
+
+#[cfg(feature = "sysinfo-modules")]
+impl Module for OsModule {
+    fn get_output(&mut self) -> ModuleRes {
+        Ok(ModuleOutput::new(
+            os_label(self.field, &self.system).ok_or(None)?,
+        ))
+    }
+
+    fn rate(&self) -> usize {
+        ON_DEMAND
+    }
+}
+
+#[cfg(all(target_os = "linux", feature = "dbus-modules"))]
+const PPD_BUS: &str = "net.hadess.PowerProfiles";
+
+#[cfg(all(target_os = "linux", feature = "dbus-modules"))]
+pub struct PowerProfileModule {
+    theme: Arc<Theme>,
+}
+
+#[cfg(all(target_os = "linux", feature = "dbus-modules"))]
+impl PowerProfileModule {
+    pub fn new() -> Self {
+        PowerProfileModule {
+            theme: Arc::default(),
+        }
+    }
+
+    pub fn with_theme(mut self, theme: Arc<Theme>) -> Self {
+        self.theme = theme;
+        self
+    }
+}
+
+#[cfg(all(target_os = "linux", feature = "dbus-modules"))]
+impl Default for PowerProfileModule {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Maps a power-profiles-daemon profile to its glyph and color.
+#[cfg(all(target_os = "linux", feature = "dbus-modules"))]
+fn power_profile_glyph(profile: &str, theme: &Theme) -> Option<(char, String)> {
+    match profile {
+        "performance" => Some((theme.glyphs.get("power_performance"), theme.crit())),
+        "balanced" => Some((theme.glyphs.get("power_balanced"), theme.fg())),
+        "power-saver" => Some((theme.glyphs.get("power_saver"), theme.ok())),
+        _ => None,
+    }
+}
+
+#[cfg(all(target_os = "linux", feature = "dbus-modules"))]
+impl Module for PowerProfileModule {
+    fn get_output(&mut self) -> ModuleRes {
+        let dbus = Connection::new_system().map_err(|_| "dbus unavailable".to_string())?;
+        let profile: String = dbus
+            .with_proxy(
+                PPD_BUS,
+                "/net/hadess/PowerProfiles",
+                Duration::from_millis(500),
+            )
+            .get(PPD_BUS, "ActiveProfile")
+            .map_err(|_| None)?;
+
+        let (glyph, color) = power_profile_glyph(&profile, &self.theme).ok_or(None)?;
+        Ok(ModuleOutput::new(glyph.to_string()).with_color_fg(color))
+    }
+
+    fn rate(&self) -> usize {
+        5
+    }
+}
+
+#[cfg(target_os = "linux")]
+#[derive(Clone, Copy, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum GpuBackend {
+    /// `nvidia-smi` queries.
+    Nvidia,
+    /// amdgpu sysfs attributes of `card0`.
+    Amd,
+}
+
+#[cfg(target_os = "linux")]
+pub struct GpuModule {
+    backend: GpuBackend,
+}
+
+#[cfg(target_os = "linux")]
+impl GpuModule {
+    pub fn new(backend: GpuBackend) -> Self {
+        GpuModule { backend }
+    }
+}
+
+/// Parses the first line of
+/// `nvidia-smi --query-gpu=utilization.gpu,temperature.gpu --format=csv,noheader,nounits`.
+#[cfg(target_os = "linux")]
+fn parse_nvidia_smi(output: &str) -> Option<(u32, u32)> {
+    let mut fields = output
+        .lines()
+        .next()?
+        .split(',')
+        .map(|f| f.trim().parse().ok());
+    Some((fields.next()??, fields.next()??))
+}
+
+#[cfg(target_os = "linux")]
+fn read_amd_gpu() -> Option<(u32, u32)> {
+    let device = Path::new("/sys/class/drm/card0/device");
+    let usage = read_to_string(device.join("gpu_busy_percent"))
+        .ok()?
+        .trim()
+        .parse()
+        .ok()?;
+    let temp: u32 = read_dir(device.join("hwmon"))
+        .ok()?
+        .filter_map(|e| e.ok())
+        .find_map(|e| read_to_string(e.path().join("temp1_input")).ok())?
+        .trim()
+        .parse()
+        .ok()?;
+    Some((usage, temp / 1000))
+}
+
+#[cfg(target_os = "linux")]
+impl Module for GpuModule {
+    fn get_output(&mut self) -> ModuleRes {
+        let (usage, temp) = match self.backend {
+            GpuBackend::Nvidia => {
+                let output = Command::new("nvidia-smi")
+                    .args([
+                        "--query-gpu=utilization.gpu,temperature.gpu",
+                        "--format=csv,noheader,nounits",
+                    ])
+                    .output()
+                    .map_err(|_| None)?;
+                parse_nvidia_smi(&String::from_utf8_lossy(&output.stdout))
+            }
+            GpuBackend::Amd => read_amd_gpu(),
+        }
+        .ok_or(None)?;
+
+        Ok(ModuleOutput::new(format!("GPU {}% {}°C", usage, temp)))
+    }
+
+    fn rate(&self) -> usize {
+        3
+    }
+}
+
+#[cfg(target_os = "linux")]
+pub struct MemoryPressureModule {
+    threshold: f32,
+    theme: Arc<Theme>,
+}
+
+#[cfg(target_os = "linux")]
+impl MemoryPressureModule {
+    pub fn new() -> Self {
+        MemoryPressureModule {
+            threshold: 10.,
+            theme: Arc::default(),
+        }
+    }
+
+    pub fn with_theme(mut self, theme: Arc<Theme>) -> Self {
+        self.theme = theme;
+        self
+    }
+
+    pub fn with_threshold(mut self, threshold: f32) -> Self {
+        self.threshold = threshold;
+        self
+    }
+}
+
+#[cfg(target_os = "linux")]
+impl Default for MemoryPressureModule {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Extracts `avg10` from the `some` line of a PSI file such as `/proc/pressure/memory`.
+#[cfg(target_os = "linux")]
+fn parse_psi_some_avg10(contents: &str) -> Option<f32> {
+    contents
+        .lines()
+        .find_map(|l| l.strip_prefix("some "))?
+        .split_whitespace()
+        .find_map(|field| field.strip_prefix("avg10="))?
+        .parse()
+        .ok()
+}
+
+#[cfg(target_os = "linux")]
+impl Module for MemoryPressureModule {
+    fn get_output(&mut self) -> ModuleRes {
+        let contents = read_to_string("/proc/pressure/memory").map_err(|_| None)?;
+        let avg10 = parse_psi_some_avg10(&contents).ok_or(None)?;
+
+        let mut out = ModuleOutput::new(format!("mem psi {:.1}%", avg10));
+        if avg10 > self.threshold {
+            out = out.with_color_fg(self.theme.crit());
+        }
+        Ok(out)
+    }
+
+    fn rate(&self) -> usize {
+        2
+    }
+}
+
+#[cfg(target_os = "linux")]
+pub struct VolumeModule {
+    step: u32,
+    theme: Arc<Theme>,
+}
+
+#[cfg(target_os = "linux")]
+impl VolumeModule {
+    pub fn new() -> Self {
+        VolumeModule {
+            step: 5,
+            theme: Arc::default(),
+        }
+    }
+
+    pub fn with_theme(mut self, theme: Arc<Theme>) -> Self {
+        self.theme = theme;
+        self
+    }
+
+    /// Percentage points to change the volume by per scroll step.
+    pub fn with_step(mut self, step: u32) -> Self {
+        self.step = step;
+        self
+    }
+}
+
+#[cfg(target_os = "linux")]
+impl Default for VolumeModule {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Parses the first channel of `pactl get-sink-volume`, e.g.
+/// `Volume: front-left: 42597 /  65% / -11.23 dB, ...`.
+#[cfg(target_os = "linux")]
+fn parse_pactl_volume(output: &str) -> Option<u32> {
+    output
+        .split('/')
+        .nth(1)?
+        .trim()
+        .strip_suffix('%')?
+        .parse()
+        .ok()
+}
+
+/// Builds the `pactl` arguments for a scroll `button`, clamping the new
+/// volume to `0..=100`.
+#[cfg(target_os = "linux")]
+fn volume_adjust_args(button: u8, current: u32, step: u32) -> Option<Vec<String>> {
+    let target = match button {
+        BUTTON_SCROLL_UP => current.saturating_add(step).min(100),
+        BUTTON_SCROLL_DOWN => current.saturating_sub(step),
+        _ => return None,
+    };
+    Some(vec![
+        "set-sink-volume".to_string(),
+        "@DEFAULT_SINK@".to_string(),
+        format!("{}%", target),
+    ])
+}
+
+#[cfg(target_os = "linux")]
+fn pactl(args: &[&str]) -> Option<String> {
+    let output = Command::new("pactl").args(args).output().ok()?;
+    Some(String::from_utf8_lossy(&output.stdout).into_owned())
+}
+
+#[cfg(target_os = "linux")]
+impl Module for VolumeModule {
+    fn get_output(&mut self) -> ModuleRes {
+        let volume = pactl(&["get-sink-volume", "@DEFAULT_SINK@"])
+            .and_then(|out| parse_pactl_volume(&out))
+            .ok_or(None)?;
+        let muted = pactl(&["get-sink-mute", "@DEFAULT_SINK@"])
+            .and_then(|out| parse_pactl_mute(&out))
+            .unwrap_or(false);
+
+        let glyphs = &self.theme.glyphs;
+        Ok(if muted {
+            ModuleOutput::new(glyphs.get("volume_muted").to_string())
+        } else {
+            ModuleOutput::new(format!("{} {}%", glyphs.get("volume"), volume))
+        })
+    }
+
+    fn on_click(&mut self, event: &ClickEvent) {
+        let current =
+            pactl(&["get-sink-volume", "@DEFAULT_SINK@"]).and_then(|out| parse_pactl_volume(&out));
+        if let Some(args) = current.and_then(|v| volume_adjust_args(event.button, v, self.step)) {
+            let _ = Command::new("pactl").args(args).status();
+        }
+    }
+}
+
+pub struct StopwatchModule {
+    /// Time from completed segments, i.e. before the last pause.
+    accumulated: Duration,
+    /// Start of the running segment, or `None` while paused.
+    segment_start: Option<Instant>,
+}
+
+impl StopwatchModule {
+    pub fn new() -> Self {
+        StopwatchModule {
+            accumulated: Duration::ZERO,
+            segment_start: Some(Instant::now()),
+        }
+    }
+
+    pub fn pause(&mut self) {
+        if let Some(start) = self.segment_start.take() {
+            self.accumulated += start.elapsed();
+        }
+    }
+
+    pub fn resume(&mut self) {
+        self.segment_start.get_or_insert_with(Instant::now);
+    }
+
+    pub fn reset(&mut self) {
+        self.accumulated = Duration::ZERO;
+        self.segment_start = self.segment_start.map(|_| Instant::now());
+    }
+}
+
+impl Default for StopwatchModule {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Total elapsed time: completed segments plus the running one, if any.
+fn stopwatch_elapsed(
+    accumulated: Duration,
+    segment_start: Option<Instant>,
+    now: Instant,
+) -> Duration {
+    accumulated + segment_start.map_or(Duration::ZERO, |start| now.saturating_duration_since(start))
+}
+
+impl Module for StopwatchModule {
+    fn get_output(&mut self) -> ModuleRes {
+        let elapsed = stopwatch_elapsed(self.accumulated, self.segment_start, Instant::now());
+        Ok(ModuleOutput::new(format!(
+            "⏱ {}",
+            format_clock(elapsed.as_secs())
+        )))
+    }
+
+    /// Left click toggles pause, right click resets.
+    fn on_click(&mut self, event: &ClickEvent) {
+        match event.button {
+            BUTTON_LEFT if self.segment_start.is_some() => self.pause(),
+            BUTTON_LEFT => self.resume(),
+            BUTTON_RIGHT => self.reset(),
+            _ => (),
+        }
+    }
+}
+
+pub struct MoonPhaseModule;
+
+const MOON_PHASES: [&str; 8] = ["🌑", "🌒", "🌓", "🌔", "🌕", "🌖", "🌗", "🌘"];
+const SYNODIC_MONTH_DAYS: f64 = 29.530588853;
+
+/// Index into [`MOON_PHASES`] for `time`, counting lunations from the new
+/// moon of 2000-01-06 18:14 UTC.
+fn moon_phase_index(time: DateTime<Utc>) -> usize {
+    let reference = Utc.with_ymd_and_hms(2000, 1, 6, 18, 14, 0).unwrap();
+    let days = (time - reference).num_seconds() as f64 / 86400.;
+    let age = days.rem_euclid(SYNODIC_MONTH_DAYS) / SYNODIC_MONTH_DAYS;
+    (age * 8.).round() as usize % 8
+}
+
+impl Module for MoonPhaseModule {
+    fn get_output(&mut self) -> ModuleRes {
+        Ok(ModuleOutput::new(
+            MOON_PHASES[moon_phase_index(Utc::now())].to_string(),
+        ))
+    }
+
+    fn rate(&self) -> usize {
+        3600
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum SunTimes {
+    Normal {
+        sunrise: DateTime<Utc>,
+        sunset: DateTime<Utc>,
+    },
+    /// The sun doesn't set on this day.
+    PolarDay,
+    /// The sun doesn't rise on this day.
+    PolarNight,
+}
+
+/// Sunrise and sunset on `date` at the given position (degrees, east and
+/// north positive), using the sunrise equation.
+fn sun_times(date: NaiveDate, latitude: f64, longitude: f64) -> SunTimes {
+    const J2000: f64 = 2451545.;
+    const UNIX_EPOCH_JD: f64 = 2440587.5;
+
+    let epoch = NaiveDate::from_ymd_opt(2000, 1, 1).unwrap();
+    let n = (date - epoch).num_days() as f64;
+    let mean_solar_noon = n - longitude / 360.;
+    let anomaly = (357.5291 + 0.98560028 * mean_solar_noon)
+        .rem_euclid(360.)
+        .to_radians();
+    let center =
+        1.9148 * anomaly.sin() + 0.02 * (2. * anomaly).sin() + 0.0003 * (3. * anomaly).sin();
+    let ecliptic_longitude = (anomaly.to_degrees() + center + 180. + 102.9372)
+        .rem_euclid(360.)
+        .to_radians();
+    let transit =
+        J2000 + mean_solar_noon + 0.0053 * anomaly.sin() - 0.0069 * (2. * ecliptic_longitude).sin();
+    let declination = (ecliptic_longitude.sin() * 23.4397f64.to_radians().sin()).asin();
+
+    let latitude = latitude.to_radians();
+    let cos_hour_angle = ((-0.833f64).to_radians().sin() - latitude.sin() * declination.sin())
+        / (latitude.cos() * declination.cos());
+    if cos_hour_angle < -1. {
+        return SunTimes::PolarDay;
+    }
+    if cos_hour_angle > 1. {
+        return SunTimes::PolarNight;
+    }
+    let hour_angle = cos_hour_angle.acos().to_degrees();
+
+    let to_utc = |julian: f64| {
+        Utc.timestamp_opt(((julian - UNIX_EPOCH_JD) * 86400.) as i64, 0)
+            .unwrap()
+    };
+    SunTimes::Normal {
+        sunrise: to_utc(transit - hour_angle / 360.),
+        sunset: to_utc(transit + hour_angle / 360.),
+    }
+}
+
+pub struct SunModule {
+    latitude: f64,
+    longitude: f64,
+}
+
+impl SunModule {
+    pub fn new(latitude: f64, longitude: f64) -> Self {
+        SunModule {
+            latitude,
+            longitude,
+        }
+    }
+}
+
+impl Module for SunModule {
+    fn get_output(&mut self) -> ModuleRes {
+        let now = Utc::now();
+        let today = now.with_timezone(&Local).date_naive();
+        let (glyph, event) = match sun_times(today, self.latitude, self.longitude) {
+            SunTimes::PolarDay => return Ok(ModuleOutput::new("☀ all day".to_string())),
+            SunTimes::PolarNight => return Ok(ModuleOutput::new("☾ all night".to_string())),
+            SunTimes::Normal { sunrise, .. } if now < sunrise => ("☀↑", sunrise),
+            SunTimes::Normal { sunset, .. } if now < sunset => ("☀↓", sunset),
+            SunTimes::Normal { .. } => {
+                let tomorrow = today.checked_add_days(Days::new(1)).ok_or(None)?;
+                match sun_times(tomorrow, self.latitude, self.longitude) {
+                    SunTimes::Normal { sunrise, .. } => ("☀↑", sunrise),
+                    _ => return Err(ModuleError::Hidden),
+                }
+            }
+        };
+        Ok(ModuleOutput::new(format!(
+            "{} {}",
+            glyph,
+            event.with_timezone(&Local).format("%H:%M")
+        )))
+    }
+
+    fn rate(&self) -> usize {
+        60
+    }
+}
+
+pub struct TickerModule {
+    coin: String,
+    currency: String,
+    label: String,
+    endpoint: String,
+    agent: ureq::Agent,
+    last_price: Option<f64>,
+    grouping: Option<char>,
+    theme: Arc<Theme>,
+}
+
+impl TickerModule {
+    /// Tracks `coin` (a CoinGecko id such as `bitcoin`) priced in `currency` (e.g. `usd`).
+    pub fn new(coin: &str, currency: &str) -> Self {
+        TickerModule {
+            coin: coin.to_string(),
+            currency: currency.to_lowercase(),
+            label: coin.to_uppercase(),
+            endpoint: "https://api.coingecko.com/api/v3/simple/price".to_string(),
+            agent: ureq::AgentBuilder::new()
+                .timeout(Duration::from_secs(2))
+                .build(),
+            last_price: None,
+            grouping: Some(','),
+            theme: Arc::default(),
+        }
+    }
+
+    pub fn with_label(mut self, label: &str) -> Self {
+        self.label = label.to_string();
+        self
+    }
+
+    /// Separates thousands with `grouping` instead of `,`, or not at all.
+    pub fn with_grouping(mut self, grouping: Option<char>) -> Self {
+        self.grouping = grouping;
+        self
+    }
+
+    /// Sets a CoinGecko `simple/price` compatible endpoint.
+    pub fn with_endpoint(mut self, endpoint: &str) -> Self {
+        self.endpoint = endpoint.to_string();
+        self
+    }
+
+    pub fn with_theme(mut self, theme: Arc<Theme>) -> Self {
+        self.theme = theme;
+        self
+    }
+}
+
+/// Parses a CoinGecko `simple/price` response, e.g. `{"bitcoin": {"usd": 42350}}`.
+fn parse_ticker_price(body: &str, coin: &str, currency: &str) -> Option<f64> {
+    let res: serde_json::Value = serde_json::from_str(body).ok()?;
+    res.get(coin)?.get(currency)?.as_f64()
+}
+
+/// Formats a price with thousands separators, keeping cents below 100.
+fn format_price(price: f64, currency: &str, grouping: Option<char>) -> String {
+    let digits = format_number(price, if price < 100. { 2 } else { 0 }, grouping);
+    match currency {
+        "usd" => format!("${}", digits),
+        "eur" => format!("€{}", digits),
+        "gbp" => format!("£{}", digits),
+        other => format!("{} {}", digits, other.to_uppercase()),
+    }
+}
+
+impl Module for TickerModule {
+    fn get_output(&mut self) -> ModuleRes {
+        let body = self
+            .agent
+            .get(&self.endpoint)
+            .query("ids", &self.coin)
+            .query("vs_currencies", &self.currency)
+            .call()
+            .ok()
+            .and_then(|res| res.into_string().ok())
+            .ok_or(None)?;
+        let price = parse_ticker_price(&body, &self.coin, &self.currency).ok_or(None)?;
+
+        let mut out = ModuleOutput::new(format!(
+            "{} {}",
+            self.label,
+            format_price(price, &self.currency, self.grouping)
+        ));
+        match self.last_price.replace(price) {
+            Some(last) if price > last => out = out.with_color_fg(self.theme.ok()),
+            Some(last) if price < last => out = out.with_color_fg(self.theme.crit()),
+            _ => (),
+        }
+        Ok(out)
+    }
+
+    fn rate(&self) -> usize {
+        300
+    }
+}
+
+#[cfg(feature = "sysinfo-modules")]
+pub struct HostnameModule {
+    ssh_only: bool,
+    system: System,
+}
+
+#[cfg(feature = "sysinfo-modules")]
+impl HostnameModule {
+    pub fn new() -> Self {
+        HostnameModule {
+            ssh_only: false,
+            system: System::new(),
+        }
+    }
+
+    /// Only shows the hostname inside an SSH session.
+    pub fn with_ssh_only(mut self, ssh_only: bool) -> Self {
+        self.ssh_only = ssh_only;
+        self
+    }
+}
+
+#[cfg(feature = "sysinfo-modules")]
+impl Default for HostnameModule {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Whether the hostname should be shown, given a lookup into the environment.
+#[cfg(feature = "sysinfo-modules")]
+fn hostname_visible(ssh_only: bool, env: impl Fn(&str) -> Option<String>) -> bool {
+    !ssh_only || env("SSH_CONNECTION").is_some_and(|v| !v.is_empty())
+}
+
+#[cfg(feature = "sysinfo-modules")]
+impl Module for HostnameModule {
+    fn get_output(&mut self) -> ModuleRes {
+        if !hostname_visible(self.ssh_only, |key| env::var(key).ok()) {
+            return Err(ModuleError::Hidden);
+        }
+        Ok(ModuleOutput::new(self.system.host_name().ok_or(None)?))
+    }
+
+    fn rate(&self) -> usize {
+        ON_DEMAND
+    }
+}
+
+#[cfg(all(target_os = "linux", feature = "dbus-modules"))]
+const NOTIFICATIONS_BUS: &str = "org.freedesktop.Notifications";
+#[cfg(all(target_os = "linux", feature = "dbus-modules"))]
+const DUNST_IFACE: &str = "org.dunstproject.cmd0";
+
+#[cfg(all(target_os = "linux", feature = "dbus-modules"))]
+pub struct DndModule {
+    theme: Arc<Theme>,
+}
+
+#[cfg(all(target_os = "linux", feature = "dbus-modules"))]
+impl DndModule {
+    pub fn new() -> Self {
+        DndModule {
+            theme: Arc::default(),
+        }
+    }
+
+    pub fn with_theme(mut self, theme: Arc<Theme>) -> Self {
+        self.theme = theme;
+        self
+    }
+}
+
+#[cfg(all(target_os = "linux", feature = "dbus-modules"))]
+impl Default for DndModule {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Whether `makoctl mode` lists the do-not-disturb mode as active.
+#[cfg(all(target_os = "linux", feature = "dbus-modules"))]
+fn parse_mako_modes(output: &str) -> bool {
+    output.lines().any(|mode| mode.trim() == "do-not-disturb")
+}
+
+#[cfg(all(target_os = "linux", feature = "dbus-modules"))]
+fn dnd_glyph(active: bool, glyphs: &Glyphs) -> char {
+    if active {
+        glyphs.get("notifications_off")
+    } else {
+        glyphs.get("notifications")
+    }
+}
+
+/// Queries dunst's pause state over the session bus.
+#[cfg(all(target_os = "linux", feature = "dbus-modules"))]
+fn dunst_paused() -> Option<bool> {
+    let dbus = Connection::new_session().ok()?;
+    let proxy = dbus.with_proxy(
+        NOTIFICATIONS_BUS,
+        "/org/freedesktop/Notifications",
+        Duration::from_millis(500),
+    );
+    proxy.get(DUNST_IFACE, "paused").ok()
+}
+
+#[cfg(all(target_os = "linux", feature = "dbus-modules"))]
+fn mako_dnd() -> Option<bool> {
+    let output = Command::new("makoctl").arg("mode").output().ok()?;
+    if !output.status.success() {
+        return None;
+    }
+    Some(parse_mako_modes(&String::from_utf8_lossy(&output.stdout)))
+}
+
+#[cfg(all(target_os = "linux", feature = "dbus-modules"))]
+impl Module for DndModule {
+    fn get_output(&mut self) -> ModuleRes {
+        let active = dunst_paused().or_else(mako_dnd).ok_or(None)?;
+        Ok(ModuleOutput::new(
+            dnd_glyph(active, &self.theme.glyphs).to_string(),
+        ))
+    }
+
+    fn rate(&self) -> usize {
+        5
+    }
+}
+
+pub struct ContainersModule {
+    socket: PathBuf,
+    theme: Arc<Theme>,
+}
+
+impl ContainersModule {
+    pub fn new() -> Self {
+        ContainersModule {
+            socket: PathBuf::from("/var/run/docker.sock"),
+            theme: Arc::default(),
+        }
+    }
+
+    pub fn with_theme(mut self, theme: Arc<Theme>) -> Self {
+        self.theme = theme;
+        self
+    }
+
+    /// Sets the Docker API socket, e.g. a Podman `podman.sock`.
+    pub fn with_socket<P: AsRef<Path>>(mut self, socket: P) -> Self {
+        self.socket = socket.as_ref().to_path_buf();
+        self
+    }
+}
+
+impl Default for ContainersModule {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Counts the containers in a raw HTTP response to `GET /containers/json`.
+fn parse_container_count(response: &str) -> Option<usize> {
+    let (head, body) = response.split_once("\r\n\r\n")?;
+    if head.split_whitespace().nth(1)? != "200" {
+        return None;
+    }
+    let containers: serde_json::Value = serde_json::from_str(body).ok()?;
+    Some(containers.as_array()?.len())
+}
+
+impl Module for ContainersModule {
+    fn get_output(&mut self) -> ModuleRes {
+        let mut stream = UnixStream::connect(&self.socket).map_err(|_| None)?;
+        stream
+            .set_read_timeout(Some(Duration::from_secs(2)))
+            .map_err(|_| None)?;
+        // HTTP/1.0 so the daemon closes the connection instead of chunking.
+        stream
+            .write_all(b"GET /containers/json HTTP/1.0\r\nHost: docker\r\n\r\n")
+            .map_err(|_| None)?;
+        let mut response = String::new();
+        stream.read_to_string(&mut response).map_err(|_| None)?;
+
+        let count = parse_container_count(&response).ok_or("docker api error".to_string())?;
+        Ok(ModuleOutput::new(format!(
+            "{} {}",
+            self.theme.glyphs.get("containers"),
+            count
+        )))
+    }
+
+    fn rate(&self) -> usize {
+        30
+    }
+}
+
+#[cfg(all(target_os = "linux", feature = "dbus-modules"))]
+const SYSTEMD_BUS: &str = "org.freedesktop.systemd1";
+#[cfg(all(target_os = "linux", feature = "dbus-modules"))]
+const SYSTEMD_MANAGER_IFACE: &str = "org.freedesktop.systemd1.Manager";
+
+#[cfg(all(target_os = "linux", feature = "dbus-modules"))]
+pub struct SystemdModule {
+    user: bool,
+    theme: Arc<Theme>,
+}
+
+#[cfg(all(target_os = "linux", feature = "dbus-modules"))]
+impl SystemdModule {
+    pub fn new() -> Self {
+        SystemdModule {
+            user: false,
+            theme: Arc::default(),
+        }
+    }
+
+    /// Watches the user's service manager instead of the system one.
+    pub fn with_user(mut self, user: bool) -> Self {
+        self.user = user;
+        self
+    }
+
+    pub fn with_theme(mut self, theme: Arc<Theme>) -> Self {
+        self.theme = theme;
+        self
+    }
+
+    fn failed_units(&self) -> Option<usize> {
+        let dbus = if self.user {
+            Connection::new_session()
+        } else {
+            Connection::new_system()
+        };
+        let from_bus = dbus.ok().and_then(|dbus| {
+            let manager = dbus.with_proxy(
+                SYSTEMD_BUS,
+                "/org/freedesktop/systemd1",
+                Duration::from_millis(500),
+            );
+            manager
+                .get::<u32>(SYSTEMD_MANAGER_IFACE, "NFailedUnits")
+                .ok()
+        });
+        if let Some(count) = from_bus {
+            return Some(count as usize);
+        }
+
+        let mut cmd = Command::new("systemctl");
+        if self.user {
+            cmd.arg("--user");
+        }
+        let output = cmd.args(["--failed", "--no-legend"]).output().ok()?;
+        output
+            .status
+            .success()
+            .then(|| count_failed_units(&String::from_utf8_lossy(&output.stdout)))
+    }
+}
+
+#[cfg(all(target_os = "linux", feature = "dbus-modules"))]
+impl Default for SystemdModule {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Counts the units listed by `systemctl --failed --no-legend`, one per line.
+#[cfg(all(target_os = "linux", feature = "dbus-modules"))]
+fn count_failed_units(output: &str) -> usize {
+    output.lines().filter(|l| !l.trim().is_empty()).count()
+}
+
+#[cfg(all(target_os = "linux", feature = "dbus-modules"))]
+impl Module for SystemdModule {
+    fn get_output(&mut self) -> ModuleRes {
+        let failed = self.failed_units().ok_or(None)?;
+        if failed == 0 {
+            return Err(ModuleError::Hidden);
+        }
+        Ok(ModuleOutput::new(format!(
+            "{} {} failed",
+            self.theme.glyphs.get("warning"),
+            failed
+        ))
+        .with_color_fg(self.theme.crit()))
+    }
+
+    fn rate(&self) -> usize {
+        30
+    }
+}
+
+pub struct MailModule {
+    maildir: PathBuf,
+}
+
+impl MailModule {
+    pub fn new<P: AsRef<Path>>(maildir: P) -> Self {
+        MailModule {
+            maildir: maildir.as_ref().to_path_buf(),
+        }
+    }
+}
+
+/// Counts unread messages in a Maildir, i.e. the files in its `new/` directory.
+fn count_maildir_new(maildir: &Path) -> Option<usize> {
+    let entries = read_dir(maildir.join("new")).ok()?;
+    Some(
+        entries
+            .filter_map(|e| e.ok())
+            .filter(|e| !e.file_name().to_string_lossy().starts_with('.'))
+            .count(),
+    )
+}
+
+impl Module for MailModule {
+    fn get_output(&mut self) -> ModuleRes {
+        let unread = count_maildir_new(&self.maildir).ok_or(None)?;
+        if unread == 0 {
+            return Err(ModuleError::Hidden);
+        }
+        Ok(ModuleOutput::new(format!("✉ {}", unread)))
+    }
+
+    fn rate(&self) -> usize {
+        10
+    }
+}
+
+pub struct CalendarModule {
+    path: PathBuf,
+}
+
+impl CalendarModule {
+    pub fn new<P: AsRef<Path>>(path: P) -> Self {
+        CalendarModule {
+            path: path.as_ref().to_path_buf(),
+        }
+    }
+}
+
+/// Parses an ICS `DTSTART` property, given its parameters and value. UTC
+/// times end in `Z`, all-day events start at local midnight, and anything
+/// else (including `TZID` times) is taken as local time.
+fn parse_ics_start(params: &str, value: &str) -> Option<DateTime<Local>> {
+    if params.contains("VALUE=DATE") && !params.contains("VALUE=DATE-TIME") {
+        let date = NaiveDate::parse_from_str(value, "%Y%m%d").ok()?;
+        return Local
+            .from_local_datetime(&date.and_hms_opt(0, 0, 0)?)
+            .earliest();
+    }
+    match value.strip_suffix('Z') {
+        Some(utc) => {
+            let time = NaiveDateTime::parse_from_str(utc, "%Y%m%dT%H%M%S").ok()?;
+            Some(Utc.from_utc_datetime(&time).with_timezone(&Local))
+        }
+        None => {
+            let time = NaiveDateTime::parse_from_str(value, "%Y%m%dT%H%M%S").ok()?;
+            Local.from_local_datetime(&time).earliest()
+        }
+    }
+}
+
+/// Finds the soonest event in an ICS calendar starting at or after `now`.
+/// Recurrence rules are not expanded, only each event's first occurrence counts.
+fn next_event(ics: &str, now: DateTime<Local>) -> Option<(String, DateTime<Local>)> {
+    // Undo line folding: continuation lines start with a space or tab.
+    let mut lines: Vec<String> = Vec::new();
+    for line in ics.lines() {
+        match (line.strip_prefix([' ', '\t']), lines.last_mut()) {
+            (Some(rest), Some(last)) => *last += rest,
+            _ => lines.push(line.to_string()),
+        }
+    }
+
+    let mut next: Option<(String, DateTime<Local>)> = None;
+    let (mut summary, mut start) = (None, None);
+    for line in &lines {
+        let Some((name, value)) = line.split_once(':') else {
+            continue;
+        };
+        let (name, params) = name.split_once(';').unwrap_or((name, ""));
+        match name {
+            "BEGIN" if value == "VEVENT" => (summary, start) = (None, None),
+            "SUMMARY" => summary = Some(value.replace("\\,", ",").replace("\\;", ";")),
+            "DTSTART" => start = parse_ics_start(params, value),
+            "END" if value == "VEVENT" => {
+                if let (Some(title), Some(at)) = (summary.take(), start.take()) {
+                    if at >= now && next.as_ref().is_none_or(|(_, soonest)| at < *soonest) {
+                        next = Some((title, at));
+                    }
+                }
+            }
+            _ => (),
+        }
+    }
+    next
+}
+
+impl Module for CalendarModule {
+    fn get_output(&mut self) -> ModuleRes {
+        let ics = read_to_string(&self.path).map_err(|_| None)?;
+        let now = Local::now();
+        let (title, at) = next_event(&ics, now).ok_or(None)?;
+        let when = if at.date_naive() == now.date_naive() {
+            at.format("%H:%M")
+        } else {
+            at.format("%d/%m %H:%M")
+        };
+        Ok(ModuleOutput::new(format!("📅 {} {}", title, when)))
+    }
+
+    fn rate(&self) -> usize {
+        60
+    }
+}
+
+pub enum TodoSource {
+    /// A `todo.txt` file, where done tasks start with `x `.
+    TodoTxt(PathBuf),
+    /// Pending Taskwarrior tasks, via `task +PENDING count`.
+    Taskwarrior,
+}
+
+pub struct TodoModule {
+    source: TodoSource,
+}
+
+impl TodoModule {
+    pub fn new(source: TodoSource) -> Self {
+        TodoModule { source }
+    }
+}
+
+/// Counts incomplete tasks in `todo.txt` contents, skipping blank lines.
+fn count_open_todos(contents: &str) -> usize {
+    contents
+        .lines()
+        .filter(|l| !l.trim().is_empty() && !l.starts_with("x "))
+        .count()
+}
+
+impl Module for TodoModule {
+    fn get_output(&mut self) -> ModuleRes {
+        let open = match &self.source {
+            TodoSource::TodoTxt(path) => count_open_todos(&read_to_string(path).map_err(|_| None)?),
+            TodoSource::Taskwarrior => {
+                let output = Command::new("task")
+                    .args(["+PENDING", "count"])
+                    .output()
+                    .map_err(|_| None)?;
+                String::from_utf8_lossy(&output.stdout)
+                    .trim()
+                    .parse()
+                    .map_err(|_| None)?
+            }
+        };
+        if open == 0 {
+            return Err(ModuleError::Hidden);
+        }
+        Ok(ModuleOutput::new(format!("☐ {}", open)))
+    }
+
+    fn rate(&self) -> usize {
+        10
+    }
+}
+
+#[cfg(target_os = "linux")]
+pub struct ThrottleModule {
+    cpu_root: PathBuf,
+    last_count: Option<u64>,
+    theme: Arc<Theme>,
+}
+
+#[cfg(target_os = "linux")]
+impl ThrottleModule {
+    pub fn new() -> Self {
+        ThrottleModule {
+            cpu_root: PathBuf::from("/sys/devices/system/cpu"),
+            last_count: None,
+            theme: Arc::default(),
+        }
+    }
+
+    pub fn with_theme(mut self, theme: Arc<Theme>) -> Self {
+        self.theme = theme;
+        self
+    }
+
+    /// Sums the core and package throttle counters of every CPU, or `None`
+    /// if the kernel doesn't expose them.
+    fn throttle_count(&self) -> Option<u64> {
+        let mut total = None;
+        for entry in read_dir(&self.cpu_root).ok()?.filter_map(|e| e.ok()) {
+            let dir = entry.path().join("thermal_throttle");
+            for counter in ["core_throttle_count", "package_throttle_count"] {
+                if let Some(count) = read_to_string(dir.join(counter))
+                    .ok()
+                    .and_then(|c| c.trim().parse::<u64>().ok())
+                {
+                    *total.get_or_insert(0) += count;
+                }
+            }
+        }
+        total
+    }
+}
+
+#[cfg(target_os = "linux")]
+impl Default for ThrottleModule {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Whether the throttle counters went up since the previous sample.
+#[cfg(target_os = "linux")]
+fn throttled_since(last: Option<u64>, now: u64) -> bool {
+    last.is_some_and(|last| now > last)
+}
+
+#[cfg(target_os = "linux")]
+impl Module for ThrottleModule {
+    fn get_output(&mut self) -> ModuleRes {
+        let count = self.throttle_count().ok_or_else(|| {
+            ModuleError::Fatal("thermal throttle counters unavailable".to_string())
+        })?;
+        if !throttled_since(self.last_count.replace(count), count) {
+            return Err(ModuleError::Hidden);
+        }
+        Ok(
+            ModuleOutput::new(format!("{} throttled", self.theme.glyphs.get("throttle")))
+                .with_color_fg(self.theme.warn()),
+        )
+    }
+
+    fn rate(&self) -> usize {
+        5
+    }
+}
+
+/// Received and transmitted byte counters of an interface at one point in time.
+#[cfg(target_os = "linux")]
+#[derive(Clone, Copy)]
+struct TrafficSample {
+    at: Instant,
+    rx: u64,
+    tx: u64,
+}
+
+/// Download and upload rates of a network interface.
+#[cfg(target_os = "linux")]
+pub struct ThroughputModule {
+    device: String,
+    last: Option<TrafficSample>,
+    theme: Arc<Theme>,
+}
+
+#[cfg(target_os = "linux")]
+impl ThroughputModule {
+    pub fn new(device: &str) -> Self {
+        ThroughputModule {
+            device: device.to_string(),
+            last: None,
+            theme: Arc::default(),
+        }
+    }
+
+    pub fn with_theme(mut self, theme: Arc<Theme>) -> Self {
+        self.theme = theme;
+        self
+    }
+
+    fn sample(&self) -> Option<TrafficSample> {
+        let stats = Path::new("/sys/class/net")
+            .join(&self.device)
+            .join("statistics");
+        let read = |file: &str| {
+            read_to_string(stats.join(file))
+                .ok()?
+                .trim()
+                .parse::<u64>()
+                .ok()
+        };
+        Some(TrafficSample {
+            at: Instant::now(),
+            rx: read("rx_bytes")?,
+            tx: read("tx_bytes")?,
+        })
+    }
+}
+
+/// Bytes per second received and sent between two samples, divided by the
+/// time that actually passed rather than the nominal tick. `None` if no time
+/// passed. A counter that went backwards, e.g. after the interface was
+/// recreated, counts as no traffic.
+#[cfg(target_os = "linux")]
+fn throughput(prev: TrafficSample, now: TrafficSample) -> Option<(f64, f64)> {
+    let secs = now.at.checked_duration_since(prev.at)?.as_secs_f64();
+    if secs <= 0. {
+        return None;
+    }
+    let rate = |prev: u64, now: u64| now.saturating_sub(prev) as f64 / secs;
+    Some((rate(prev.rx, now.rx), rate(prev.tx, now.tx)))
+}
+
+/// Formats a byte rate with a binary unit, e.g. `1.5 MiB/s`.
+#[cfg(target_os = "linux")]
+fn format_byte_rate(bytes_per_sec: f64) -> String {
+    const UNITS: [&str; 4] = ["B/s", "KiB/s", "MiB/s", "GiB/s"];
+    let mut value = bytes_per_sec;
+    let mut unit = 0;
+    while value >= 1024. && unit < UNITS.len() - 1 {
+        value /= 1024.;
+        unit += 1;
+    }
+    if unit == 0 {
+        format!("{:.0} {}", value, UNITS[unit])
+    } else {
+        format!("{:.1} {}", value, UNITS[unit])
+    }
+}
+
+#[cfg(target_os = "linux")]
+impl Module for ThroughputModule {
+    fn get_output(&mut self) -> ModuleRes {
+        let now = self
+            .sample()
+            .ok_or_else(|| format!("{} unavailable", self.device))?;
+        // The first sample only sets the baseline.
+        let prev = self.last.replace(now).ok_or(ModuleError::Hidden)?;
+        let (rx, tx) = throughput(prev, now).ok_or(ModuleError::Hidden)?;
+        let glyphs = &self.theme.glyphs;
+        Ok(ModuleOutput::new(format!(
+            "{} {} {} {}",
+            glyphs.get("download"),
+            format_byte_rate(rx),
+            glyphs.get("upload"),
+            format_byte_rate(tx)
+        )))
+    }
+}
+
+#[cfg(target_os = "linux")]
+const BACKLIGHT_ROOT: &str = "/sys/class/backlight";
+
+/// Screen backlight level, adjusted by scrolling over the block.
+#[cfg(target_os = "linux")]
+pub struct BrightnessModule {
+    /// Backlight device name, or `None` for the first one found.
+    device: Option<String>,
+    step: u32,
+    theme: Arc<Theme>,
+}
+
+#[cfg(target_os = "linux")]
+impl BrightnessModule {
+    pub fn new() -> Self {
+        BrightnessModule {
+            device: None,
+            step: 5,
+            theme: Arc::default(),
+        }
+    }
+
+    pub fn with_theme(mut self, theme: Arc<Theme>) -> Self {
+        self.theme = theme;
+        self
+    }
+
+    /// Picks a backlight from `/sys/class/backlight`, e.g. `intel_backlight`.
+    pub fn with_device(mut self, device: &str) -> Self {
+        self.device = Some(device.to_string());
+        self
+    }
+
+    /// Percent of the maximum brightness to change by per scroll step.
+    pub fn with_step(mut self, step: u32) -> Self {
+        self.step = step;
+        self
+    }
+
+    fn device_dir(&self) -> Option<PathBuf> {
+        match &self.device {
+            Some(device) => Some(Path::new(BACKLIGHT_ROOT).join(device)),
+            None => {
+                let mut devices: Vec<PathBuf> = read_dir(BACKLIGHT_ROOT)
+                    .ok()?
+                    .flatten()
+                    .map(|entry| entry.path())
+                    .collect();
+                devices.sort();
+                devices.into_iter().next()
+            }
+        }
+    }
+
+    /// Current and maximum raw brightness.
+    fn read(&self) -> Option<(PathBuf, u64, u64)> {
+        let dir = self.device_dir()?;
+        let read = |file: &str| {
+            read_to_string(dir.join(file))
+                .ok()?
+                .trim()
+                .parse::<u64>()
+                .ok()
+        };
+        let (current, max) = (read("brightness")?, read("max_brightness")?);
+        Some((dir, current, max))
+    }
+}
+
+#[cfg(target_os = "linux")]
+impl Default for BrightnessModule {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Raw brightness after a scroll `button`, moving by `step` percent of `max`
+/// (at least one unit) and clamped to `0..=max`.
+#[cfg(target_os = "linux")]
+fn brightness_target(button: u8, current: u64, max: u64, step: u32) -> Option<u64> {
+    let delta = (max * step as u64 / 100).max(1);
+    match button {
+        BUTTON_SCROLL_UP => Some(current.saturating_add(delta).min(max)),
+        BUTTON_SCROLL_DOWN => Some(current.saturating_sub(delta)),
+        _ => None,
+    }
+}
+
+#[cfg(target_os = "linux")]
+impl Module for BrightnessModule {
+    fn get_output(&mut self) -> ModuleRes {
+        let (_, current, max) = self.read().ok_or(ModuleError::Hidden)?;
+        if max == 0 {
+            return Err(ModuleError::Hidden);
+        }
+        Ok(ModuleOutput::new(format!(
+            "{} {}%",
+            self.theme.glyphs.get("brightness"),
+            (current * 100 + max / 2) / max
+        )))
+    }
+
+    /// Writing `brightness` usually needs a udev rule granting access, so
+    /// `brightnessctl`, which goes through logind, is tried when that fails.
+    fn on_click(&mut self, event: &ClickEvent) {
+        let Some((dir, current, max)) = self.read() else {
+            return;
+        };
+        let Some(target) = brightness_target(event.button, current, max, self.step) else {
+            return;
+        };
+        if let Err(e) = std::fs::write(dir.join("brightness"), target.to_string()) {
+            log::debug!("can't write {}: {}, trying brightnessctl", dir.display(), e);
+            let device = dir.file_name().unwrap_or_default().to_string_lossy();
+            let status = Command::new("brightnessctl")
+                .args([
+                    format!("--device={}", device),
+                    "set".to_string(),
+                    target.to_string(),
+                ])
+                .output();
+            if let Err(e) = status {
+                log::warn!("can't set brightness: {}", e);
+            }
+        }
+    }
+}
+
+/// The process with the largest `usage`, as `(name, usage)` pairs.
+#[cfg(feature = "sysinfo-modules")]
+fn top_process<'a, T: PartialOrd + Copy>(
+    processes: impl IntoIterator<Item = (&'a str, T)>,
+) -> Option<(&'a str, T)> {
+    processes
+        .into_iter()
+        // Skips NaN, which would otherwise compare equal to everything.
+        .filter(|(_, usage)| usage.partial_cmp(usage).is_some())
+        .max_by(|a, b| a.1.partial_cmp(&b.1).unwrap_or(std::cmp::Ordering::Equal))
+}
+
+/// Shows the process using the most memory, e.g. `firefox 2.1 GiB`.
+#[cfg(feature = "sysinfo-modules")]
+pub struct TopMemModule {
+    system: System,
+}
+
+#[cfg(feature = "sysinfo-modules")]
+impl TopMemModule {
+    pub fn new() -> Self {
+        TopMemModule {
+            system: System::new(),
+        }
+    }
+}
+
+#[cfg(feature = "sysinfo-modules")]
+impl Default for TopMemModule {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(feature = "sysinfo-modules")]
+impl Module for TopMemModule {
+    fn get_output(&mut self) -> ModuleRes {
+        // Memory is all that's needed, so skip sampling CPU usage.
+        self.system
+            .refresh_processes_specifics(ProcessRefreshKind::new());
+
+        let processes = self
+            .system
+            .processes()
+            .values()
+            .map(|p| (p.name(), p.memory()));
+        let (name, kib) = top_process(processes).ok_or(ModuleError::Hidden)?;
+        Ok(ModuleOutput::new(format!(
+            "{} {} GiB",
+            name,
+            format_number(kib as f64 / 1024. / 1024., 1, None)
+        )))
+    }
+
+    /// Walking every process is expensive.
+    fn rate(&self) -> usize {
+        10
+    }
+}
+
+/// Shows the process using the most CPU, e.g. `ffmpeg 180%`, where 100% is
+/// one full core.
+#[cfg(feature = "sysinfo-modules")]
+pub struct TopCpuModule {
+    system: System,
+    /// Whether a previous refresh exists to measure usage against, like
+    /// [`ThroughputModule`]'s last sample.
+    primed: bool,
+}
+
+#[cfg(feature = "sysinfo-modules")]
+impl TopCpuModule {
+    pub fn new() -> Self {
+        TopCpuModule {
+            system: System::new(),
+            primed: false,
+        }
+    }
+}
+
+#[cfg(feature = "sysinfo-modules")]
+impl Default for TopCpuModule {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(feature = "sysinfo-modules")]
+impl Module for TopCpuModule {
+    fn get_output(&mut self) -> ModuleRes {
+        // Usage is measured between consecutive refreshes, so the previous
+        // poll serves as the first sample instead of sleeping.
+        self.system
+            .refresh_processes_specifics(ProcessRefreshKind::new().with_cpu());
+        if !std::mem::replace(&mut self.primed, true) {
+            return Err(ModuleError::Hidden);
+        }
+
+        let processes = self
+            .system
+            .processes()
+            .values()
+            .map(|p| (p.name(), p.cpu_usage()));
+        let (name, usage) = top_process(processes).ok_or(ModuleError::Hidden)?;
+        Ok(ModuleOutput::new(format!("{} {:.0}%", name, usage)))
+    }
+
+    /// Walking every process is expensive.
+    fn rate(&self) -> usize {
+        10
+    }
+}
+
+/// The kernel's IPv4 routing table.
+#[cfg(target_os = "linux")]
+const ROUTE_TABLE: &str = "/proc/net/route";
+
+/// Gateway of the default route with the lowest metric in a
+/// `/proc/net/route` table, whose addresses are little-endian hex.
+#[cfg(target_os = "linux")]
+fn default_gateway(table: &str) -> Option<Ipv4Addr> {
+    table
+        .lines()
+        .skip(1)
+        .filter_map(|line| {
+            let fields: Vec<&str> = line.split_whitespace().collect();
+            match fields[..] {
+                [_, "00000000", gateway, _, _, _, metric, ..] => Some((
+                    metric.parse::<u32>().ok()?,
+                    u32::from_str_radix(gateway, 16).ok()?,
+                )),
+                _ => None,
+            }
+        })
+        .filter(|(_, gateway)| *gateway != 0)
+        .min_by_key(|(metric, _)| *metric)
+        .map(|(_, gateway)| Ipv4Addr::from(gateway.swap_bytes()))
+}
+
+/// Shows the default gateway's address, telling apart networks that hand
+/// out similar addresses. Hidden while there's no default route.
+#[cfg(target_os = "linux")]
+pub struct GatewayModule;
+
+#[cfg(target_os = "linux")]
+impl Module for GatewayModule {
+    fn get_output(&mut self) -> ModuleRes {
+        let table = read_to_string(ROUTE_TABLE).map_err(|e| format!("routes: {}", e))?;
+        let gateway = default_gateway(&table).ok_or(ModuleError::Hidden)?;
+        Ok(ModuleOutput::new(gateway.to_string()))
+    }
+
+    fn rate(&self) -> usize {
+        10
+    }
+}
+
+/// Looks up a host name, returning whether it resolved to any address.
+pub type Resolver = fn(&str) -> bool;
+
+/// Resolves through the system resolver, as other programs would.
+fn system_resolve(host: &str) -> bool {
+    use std::net::ToSocketAddrs;
+    (host, 0)
+        .to_socket_addrs()
+        .is_ok_and(|mut addrs| addrs.next().is_some())
+}
+
+/// Runs `resolver` on its own thread, giving up after `timeout`. A lookup
+/// that hangs finishes in the background without holding up the bar.
+fn resolve_with_timeout(resolver: Resolver, host: &str, timeout: Duration) -> bool {
+    let (tx, rx) = std::sync::mpsc::channel();
+    let host = host.to_string();
+    std::thread::spawn(move || tx.send(resolver(&host)));
+    rx.recv_timeout(timeout).unwrap_or(false)
+}
+
+/// Checks that host names resolve, telling a broken DNS setup apart from a
+/// missing connection.
+pub struct DnsModule {
+    host: String,
+    timeout: Duration,
+    resolver: Resolver,
+    theme: Arc<Theme>,
+}
+
+impl DnsModule {
+    pub fn new() -> Self {
+        DnsModule {
+            host: "example.com".to_string(),
+            timeout: Duration::from_secs(2),
+            resolver: system_resolve,
+            theme: Arc::default(),
+        }
+    }
+
+    pub fn with_host(mut self, host: &str) -> Self {
+        self.host = host.to_string();
+        self
+    }
+
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// Replaces the system resolver, e.g. to check a specific server.
+    pub fn with_resolver(mut self, resolver: Resolver) -> Self {
+        self.resolver = resolver;
+        self
+    }
+
+    pub fn with_theme(mut self, theme: Arc<Theme>) -> Self {
+        self.theme = theme;
+        self
+    }
+}
+
+impl Default for DnsModule {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Module for DnsModule {
+    fn get_output(&mut self) -> ModuleRes {
+        Ok(
+            if resolve_with_timeout(self.resolver, &self.host, self.timeout) {
+                ModuleOutput::new("DNS OK".to_string()).with_color_fg(self.theme.ok())
+            } else {
+                ModuleOutput::new("DNS FAIL".to_string()).with_color_fg(self.theme.crit())
+            },
+        )
+    }
+
+    fn rate(&self) -> usize {
+        30
+    }
+}
+
+/// Turns the trimmed contents of a file into the text to show.
+pub type FileTransform = Box<dyn Fn(&str) -> Option<String>>;
+
+/// Transform parsing a number and dividing it by `divisor`, e.g. 1000 for
+/// the millidegrees in `/sys/class/thermal`.
+pub fn scaled(divisor: f64, decimals: usize) -> FileTransform {
+    Box::new(move |raw| {
+        let value: f64 = raw.parse().ok()?;
+        Some(format!("{:.*}", decimals, value / divisor))
+    })
+}
+
+/// Shows a value read from any file, such as a sysfs or procfs entry that
+/// has no module of its own. Hidden while the file doesn't exist.
+pub struct FileModule {
+    path: PathBuf,
+    /// Template where `{}` stands for the transformed value.
+    format: String,
+    transform: FileTransform,
+}
+
+impl FileModule {
+    pub fn new(
+        path: impl AsRef<Path>,
+        format: &str,
+        transform: impl Fn(&str) -> Option<String> + 'static,
+    ) -> Self {
+        FileModule {
+            path: path.as_ref().to_path_buf(),
+            format: format.to_string(),
+            transform: Box::new(transform),
+        }
+    }
+}
+
+impl Module for FileModule {
+    fn get_output(&mut self) -> ModuleRes {
+        let raw = read_to_string(&self.path).map_err(|_| None)?;
+        let value = (self.transform)(raw.trim())
+            .ok_or_else(|| format!("can't read {}", self.path.display()))?;
+        Ok(ModuleOutput::new(render_template(
+            &self.format,
+            &[("", value)],
+        )))
+    }
+}
+
+/// What a finished command printed, and whether it exited successfully.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct CommandOutput {
+    pub success: bool,
+    pub stdout: String,
+    pub stderr: String,
+}
+
+/// Runs a shell command line, giving up after the timeout.
+pub type CommandRunner = fn(&str, Duration) -> Result<CommandOutput, String>;
+
+/// Reads `pipe` to the end on its own thread, so a command writing more than
+/// the pipe's buffer doesn't stall waiting for a reader.
+fn drain_pipe<R: Read + Send + 'static>(pipe: Option<R>) -> std::sync::mpsc::Receiver<String> {
+    let (tx, rx) = std::sync::mpsc::channel();
+    std::thread::spawn(move || {
+        let mut text = String::new();
+        if let Some(mut pipe) = pipe {
+            let _ = pipe.read_to_string(&mut text);
+        }
+        tx.send(text)
+    });
+    rx
+}
+
+/// Runs `command` with `sh -c`, killing it if it's still running after
+/// `timeout`. Its output is read while it runs, and a background process
+/// keeping the pipes open past the deadline also counts as timing out.
+fn run_shell(command: &str, timeout: Duration) -> Result<CommandOutput, String> {
+    let mut child = Command::new("sh")
+        .args(["-c", command])
+        .stdin(std::process::Stdio::null())
+        .stdout(std::process::Stdio::piped())
+        .stderr(std::process::Stdio::piped())
+        .spawn()
+        .map_err(|e| format!("can't run command: {}", e))?;
+    let stdout = drain_pipe(child.stdout.take());
+    let stderr = drain_pipe(child.stderr.take());
+    let deadline = Instant::now() + timeout;
+    let status = loop {
+        if let Some(status) = child.try_wait().map_err(|e| e.to_string())? {
+            break status;
+        }
+        if Instant::now() >= deadline {
+            let _ = child.kill();
+            let _ = child.wait();
+            return Err("command timed out".to_string());
+        }
+        std::thread::sleep(Duration::from_millis(10));
+    };
+    let collect = |pipe: std::sync::mpsc::Receiver<String>| {
+        pipe.recv_timeout(deadline.saturating_duration_since(Instant::now()))
+            .map_err(|_| "command timed out".to_string())
+    };
+    Ok(CommandOutput {
+        success: status.success(),
+        stdout: collect(stdout)?,
+        stderr: collect(stderr)?,
+    })
+}
+
+/// First non-blank line of `text`, trimmed.
+fn first_line(text: &str) -> Option<&str> {
+    text.lines().map(str::trim).find(|line| !line.is_empty())
+}
+
+/// Parses a command's output the i3blocks way: the first line is the
+/// full text, the second the short text and the third a color. Blank or
+/// invalid optional lines are skipped, and an empty first line hides the
+/// block.
+pub fn parse_command_output(stdout: &str) -> Option<ModuleOutput> {
+    let mut lines = stdout.lines().map(str::trim);
+    let full_text = lines.next().filter(|line| !line.is_empty())?;
+    let mut out = ModuleOutput::new(full_text.to_string());
+    if let Some(short_text) = lines.next().filter(|line| !line.is_empty()) {
+        out = out.with_short_text(short_text.to_string());
+    }
+    if let Some(color) = lines.next().filter(|line| !line.is_empty()) {
+        match Color::parse(color) {
+            Ok(color) => out = out.with_color_fg(color.to_string()),
+            Err(e) => log::warn!("ignoring command color: {}", e),
+        }
+    }
+    Some(out)
+}
+
+/// Shows what a command prints, i3blocks style, for anything there's no
+/// module for. See [`parse_command_output`]. A failing command shows its error output in the
+/// theme's `crit` color; no output at all hides the block.
+pub struct CommandModule {
+    command: String,
+    timeout: Duration,
+    runner: CommandRunner,
+    theme: Arc<Theme>,
+}
+
+impl CommandModule {
+    pub fn new(command: &str) -> Self {
+        CommandModule {
+            command: command.to_string(),
+            timeout: Duration::from_secs(2),
+            runner: run_shell,
+            theme: Arc::default(),
+        }
+    }
+
+    /// Kills the command if it runs longer than `timeout`, which holds up
+    /// the whole bar meanwhile.
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    pub fn with_runner(mut self, runner: CommandRunner) -> Self {
+        self.runner = runner;
+        self
+    }
+
+    pub fn with_theme(mut self, theme: Arc<Theme>) -> Self {
+        self.theme = theme;
+        self
+    }
+}
+
+impl Module for CommandModule {
+    fn get_output(&mut self) -> ModuleRes {
+        let out = (self.runner)(&self.command, self.timeout)?;
+        if out.success {
+            return parse_command_output(&out.stdout).ok_or(ModuleError::Hidden);
+        }
+        let line = first_line(&out.stderr).unwrap_or("command failed");
+        Ok(ModuleOutput::new(line.to_string()).with_color_fg(self.theme.crit()))
+    }
+
+    fn rate(&self) -> usize {
+        5
+    }
+}
+
+/// The kernel's TCP socket tables, for IPv4 and IPv6.
+#[cfg(target_os = "linux")]
+const TCP_TABLES: [&str; 2] = ["/proc/net/tcp", "/proc/net/tcp6"];
+
+/// `st` value of established connections in the TCP tables.
+#[cfg(target_os = "linux")]
+const TCP_ESTABLISHED: u8 = 0x01;
+
+/// Counts established connections in a `/proc/net/tcp` style table,
+/// skipping its header line.
+#[cfg(target_os = "linux")]
+fn count_established(table: &str) -> usize {
+    table
+        .lines()
+        .skip(1)
+        .filter(|line| {
+            line.split_whitespace()
+                .nth(3)
+                .and_then(|st| u8::from_str_radix(st, 16).ok())
+                == Some(TCP_ESTABLISHED)
+        })
+        .count()
+}
+
+/// Shows the number of established TCP connections, e.g. `conns 42`, to
+/// spot connection leaks. Hidden when the tables can't be read.
+#[cfg(target_os = "linux")]
+pub struct ConnectionsModule;
+
+#[cfg(target_os = "linux")]
+impl Module for ConnectionsModule {
+    fn get_output(&mut self) -> ModuleRes {
+        let tables: Vec<String> = TCP_TABLES
+            .iter()
+            .filter_map(|path| read_to_string(path).ok())
+            .collect();
+        if tables.is_empty() {
+            return Err(ModuleError::Hidden);
+        }
+        let count: usize = tables.iter().map(|t| count_established(t)).sum();
+        Ok(ModuleOutput::new(format!("conns {}", count)).with_metric("connections", count as f64))
+    }
+
+    /// Busy servers have tables of thousands of lines.
+    fn rate(&self) -> usize {
+        10
+    }
+}
+
+/// Wraps any module and prefixes its content with a label, e.g. `CPU 12%`.
+pub struct LabeledModule<M> {
+    inner: M,
+    prefix: String,
+}
+
+impl<M: Module> LabeledModule<M> {
+    pub fn new(label: &str, inner: M) -> Self {
+        LabeledModule {
+            inner,
+            prefix: format!("{} ", label),
+        }
+    }
+}
+
+impl<M: Module> Module for LabeledModule<M> {
+    fn get_output(&mut self) -> ModuleRes {
+        Ok(self.inner.get_output()?.with_prefix(&self.prefix))
+    }
+
+    fn rate(&self) -> usize {
+        self.inner.rate()
+    }
+
+    fn hidden_limit(&self) -> Option<usize> {
+        self.inner.hidden_limit()
+    }
+
+    fn set_expanded(&mut self, expanded: bool) {
+        self.inner.set_expanded(expanded);
+    }
+
+    fn on_click(&mut self, event: &ClickEvent) {
+        self.inner.on_click(event);
+    }
+
+    fn min_width(&self) -> Option<MinWidth> {
+        match self.inner.min_width()? {
+            MinWidth::Text(text) => Some(MinWidth::Text(format!("{}{}", self.prefix, text))),
+            // The label's width in pixels isn't known.
+            MinWidth::Pixels(_) => None,
+        }
+    }
+}
+
+/// Wraps any module to add a fixed prefix or suffix, or to force its colors,
+/// without touching the module itself.
+pub struct Decorated<M> {
+    inner: M,
+    prefix: Option<String>,
+    suffix: Option<String>,
+    color_fg: Option<String>,
+    color_bg: Option<String>,
+    name: Option<String>,
+}
+
+impl<M: Module> Decorated<M> {
+    pub fn new(inner: M) -> Self {
+        Decorated {
+            inner,
+            prefix: None,
+            suffix: None,
+            color_fg: None,
+            color_bg: None,
+            name: None,
+        }
+    }
+
+    pub fn with_name(mut self, name: &str) -> Self {
+        self.name = Some(name.to_string());
+        self
+    }
+
+    pub fn with_prefix(mut self, prefix: &str) -> Self {
+        self.prefix = Some(prefix.to_string());
+        self
+    }
+
+    pub fn with_suffix(mut self, suffix: &str) -> Self {
+        self.suffix = Some(suffix.to_string());
+        self
+    }
+
+    pub fn with_color_fg(mut self, color: String) -> Self {
+        self.color_fg = Some(color);
+        self
+    }
+
+    pub fn with_color_bg(mut self, color: String) -> Self {
+        self.color_bg = Some(color);
+        self
+    }
+}
+
+impl<M: Module> Module for Decorated<M> {
+    fn get_output(&mut self) -> ModuleRes {
+        let mut out = self.inner.get_output()?;
+        if let Some(prefix) = &self.prefix {
+            out = out.with_prefix(prefix);
+        }
+        if let Some(suffix) = &self.suffix {
+            out = out.with_suffix(suffix);
+        }
+        if let Some(color) = &self.color_fg {
+            out = out.with_color_fg(color.clone());
+        }
+        if let Some(color) = &self.color_bg {
+            out = out.with_color_bg(color.clone());
+        }
+        if let Some(name) = &self.name {
+            out = out.with_name(name.clone());
+        }
+        Ok(out)
+    }
+
+    fn rate(&self) -> usize {
+        self.inner.rate()
+    }
+
+    fn hidden_limit(&self) -> Option<usize> {
+        self.inner.hidden_limit()
+    }
+
+    fn set_expanded(&mut self, expanded: bool) {
+        self.inner.set_expanded(expanded);
+    }
+
+    fn on_click(&mut self, event: &ClickEvent) {
+        self.inner.on_click(event);
+    }
+
+    fn min_width(&self) -> Option<MinWidth> {
+        match self.inner.min_width()? {
+            MinWidth::Text(text) => Some(MinWidth::Text(format!(
+                "{}{}{}",
+                self.prefix.as_deref().unwrap_or_default(),
+                text,
+                self.suffix.as_deref().unwrap_or_default()
+            ))),
+            MinWidth::Pixels(px) if self.prefix.is_none() && self.suffix.is_none() => {
+                Some(MinWidth::Pixels(px))
+            }
+            MinWidth::Pixels(_) => None,
+        }
+    }
+}
+
+/// Merges two modules into a single block, e.g. CPU usage and temperature on
+/// a narrow bar. The block takes its styling from the first module. If one
+/// side is hidden the other is shown alone; any other error wins.
+pub struct Joined<A, B> {
+    first: A,
+    second: B,
+    separator: String,
+}
+
+impl<A: Module, B: Module> Joined<A, B> {
+    pub fn new(first: A, second: B) -> Self {
+        Joined {
+            first,
+            second,
+            separator: " ".to_string(),
+        }
+    }
+
+    pub fn with_separator(mut self, separator: &str) -> Self {
+        self.separator = separator.to_string();
+        self
+    }
+}
+
+impl<A: Module, B: Module> Module for Joined<A, B> {
+    fn get_output(&mut self) -> ModuleRes {
+        match (self.first.get_output(), self.second.get_output()) {
+            (Ok(first), Ok(second)) => Ok(first
+                .with_suffix(&self.separator)
+                .with_suffix(second.content())),
+            (Ok(out), Err(ModuleError::Hidden)) | (Err(ModuleError::Hidden), Ok(out)) => Ok(out),
+            (Err(e), _) | (_, Err(e)) => Err(e),
+        }
+    }
+
+    /// Both sides refresh together, as often as the faster one needs.
+    fn rate(&self) -> usize {
+        self.first.rate().min(self.second.rate())
+    }
+
+    fn set_expanded(&mut self, expanded: bool) {
+        self.first.set_expanded(expanded);
+        self.second.set_expanded(expanded);
+    }
+
+    fn on_click(&mut self, event: &ClickEvent) {
+        self.first.on_click(event);
+        self.second.on_click(event);
+    }
+}
+
+/// Shows the first of several modules that has something to show, e.g. one
+/// of the temperature sensors found on different machines. Modules failing
+/// fatally are skipped from then on; while none succeeds the block is hidden.
+pub struct FirstOf {
+    modules: Vec<Box<dyn Module>>,
+    retired: Vec<bool>,
+    /// Module whose output is shown, which receives clicks.
+    shown: Option<usize>,
+}
+
+impl FirstOf {
+    pub fn new(modules: Vec<Box<dyn Module>>) -> Self {
+        FirstOf {
+            retired: modules.iter().map(|_| false).collect(),
+            modules,
+            shown: None,
+        }
+    }
+}
+
+/// Polls `modules` in order starting at `start`, wrapping around, until one
+/// has output, returning its index. Modules failing fatally are marked in
+/// `retired` and skipped; any other error falls through to the next module.
+fn first_output(
+    modules: &mut [Box<dyn Module>],
+    retired: &mut [bool],
+    start: usize,
+) -> Result<(usize, ModuleOutput), ModuleError> {
+    let len = modules.len();
+    for i in (start..start + len).map(|i| i % len) {
+        if retired[i] {
+            continue;
+        }
+        match poll(modules[i].as_mut()) {
+            Ok(out) => return Ok((i, out)),
+            Err(ModuleError::Fatal(mes)) => {
+                log::info!("skipping inner module {} from now on: {}", i + 1, mes);
+                retired[i] = true;
+            }
+            Err(_) => (),
+        }
+    }
+    if retired.iter().all(|r| *r) {
+        return Err(ModuleError::Fatal("no module left to show".to_string()));
+    }
+    Err(ModuleError::Hidden)
+}
+
+impl Module for FirstOf {
+    fn get_output(&mut self) -> ModuleRes {
+        self.shown = None;
+        let (i, out) = first_output(&mut self.modules, &mut self.retired, 0)?;
+        self.shown = Some(i);
+        Ok(out)
+    }
+
+    fn rate(&self) -> usize {
+        self.modules.iter().map(|m| m.rate()).min().unwrap_or(1)
+    }
+
+    fn set_expanded(&mut self, expanded: bool) {
+        for module in &mut self.modules {
+            module.set_expanded(expanded);
+        }
+    }
+
+    fn on_click(&mut self, event: &ClickEvent) {
+        if let Some(i) = self.shown {
+            self.modules[i].on_click(event);
+        }
+    }
+
+    fn min_width(&self) -> Option<MinWidth> {
+        self.modules[self.shown?].min_width()
+    }
+}
+
+/// Fits several modules into one block by showing them in turn, switching
+/// every `period` ticks. Modules with nothing to show are skipped.
+pub struct Rotating {
+    modules: Vec<Box<dyn Module>>,
+    retired: Vec<bool>,
+    period: usize,
+    /// Module to try first on the next refresh.
+    next: usize,
+    shown: Option<usize>,
+}
+
+impl Rotating {
+    pub fn new(modules: Vec<Box<dyn Module>>) -> Self {
+        Rotating {
+            retired: modules.iter().map(|_| false).collect(),
+            modules,
+            period: 1,
+            next: 0,
+            shown: None,
+        }
+    }
+
+    pub fn with_period(mut self, ticks: usize) -> Self {
+        self.period = ticks.max(1);
+        self
+    }
+}
+
+impl Module for Rotating {
+    fn get_output(&mut self) -> ModuleRes {
+        self.shown = None;
+        let (i, out) = first_output(&mut self.modules, &mut self.retired, self.next)?;
+        self.shown = Some(i);
+        self.next = (i + 1) % self.modules.len();
+        Ok(out)
+    }
+
+    fn rate(&self) -> usize {
+        self.period
+    }
+
+    fn set_expanded(&mut self, expanded: bool) {
+        for module in &mut self.modules {
+            module.set_expanded(expanded);
+        }
+    }
+
+    fn on_click(&mut self, event: &ClickEvent) {
+        if let Some(i) = self.shown {
+            self.modules[i].on_click(event);
+        }
+    }
+}
+
+/// Finds the first number in `content`, giving its byte range, value and
+/// number of decimals.
+fn find_number(content: &str) -> Option<(std::ops::Range<usize>, f64, usize)> {
+    let start = content.find(|c: char| c.is_ascii_digit())?;
+    let whole = content[start..]
+        .find(|c: char| !c.is_ascii_digit())
+        .map_or(content.len(), |len| start + len);
+    let fraction = content[whole..]
+        .strip_prefix('.')
+        .map(|rest| rest.len() - rest.trim_start_matches(|c: char| c.is_ascii_digit()).len())
+        .unwrap_or(0);
+    let end = if fraction > 0 {
+        whole + 1 + fraction
+    } else {
+        whole
+    };
+    let value = content[start..end].parse().ok()?;
+    Some((start..end, value, fraction))
+}
+
+/// Smooths a noisy module by replacing the first number in its content with
+/// the mean of its last few readings, keeping the number of decimals. Colors
+/// still follow the latest reading.
+pub struct Averaged<M> {
+    inner: M,
+    history: History<f64>,
+}
+
+impl<M: Module> Averaged<M> {
+    pub fn new(inner: M, samples: usize) -> Self {
+        Averaged {
+            inner,
+            history: History::new(samples),
+        }
+    }
+}
+
+impl<M: Module> Module for Averaged<M> {
+    fn get_output(&mut self) -> ModuleRes {
+        let mut out = self.inner.get_output()?;
+        let Some((range, value, decimals)) = find_number(&out.content) else {
+            return Ok(out);
+        };
+        self.history.push(value);
+        if !self.history.is_empty() {
+            let mean = self.history.iter().sum::<f64>() / self.history.len() as f64;
+            out.content
+                .replace_range(range, &format!("{:.*}", decimals, mean));
+        }
+        Ok(out)
+    }
+
+    fn rate(&self) -> usize {
+        self.inner.rate()
+    }
+
+    fn hidden_limit(&self) -> Option<usize> {
+        self.inner.hidden_limit()
+    }
+
+    fn set_expanded(&mut self, expanded: bool) {
+        self.inner.set_expanded(expanded);
+    }
+
+    fn on_click(&mut self, event: &ClickEvent) {
+        self.inner.on_click(event);
+    }
+
+    fn min_width(&self) -> Option<MinWidth> {
+        self.inner.min_width()
+    }
+}
+
+/// Which side of a threshold a watched value must cross to.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Bound {
+    Above(f64),
+    Below(f64),
+}
+
+impl Bound {
+    fn beyond(self, value: f64) -> bool {
+        match self {
+            Bound::Above(limit) => value > limit,
+            Bound::Below(limit) => value < limit,
+        }
+    }
+}
+
+/// Calls a callback when a module's value crosses a [`Bound`], e.g. free
+/// disk space dropping below 5 GiB. It fires once per crossing rather than
+/// on every refresh beyond the bound, including when the first reading
+/// already is.
+pub struct NotifyOnThreshold<M> {
+    inner: M,
+    bound: Bound,
+    /// Metric to watch, see [`ModuleOutput::with_metric`]; otherwise the
+    /// first number in the content.
+    metric: Option<String>,
+    callback: Box<dyn FnMut(f64)>,
+    beyond: bool,
+}
+
+impl<M: Module> NotifyOnThreshold<M> {
+    pub fn new(inner: M, bound: Bound, callback: impl FnMut(f64) + 'static) -> Self {
+        NotifyOnThreshold {
+            inner,
+            bound,
+            metric: None,
+            callback: Box::new(callback),
+            beyond: false,
+        }
+    }
+
+    pub fn with_metric(mut self, metric: &str) -> Self {
+        self.metric = Some(metric.to_string());
+        self
+    }
+}
+
+/// Runs `command` with `sh -c` for [`NotifyOnThreshold`], passing the value
+/// that crossed the bound as `$VALUE`.
+pub fn threshold_command(command: String) -> impl FnMut(f64) {
+    move |value| {
+        let mut cmd = Command::new("sh");
+        cmd.args(["-c", &command]).env("VALUE", value.to_string());
+        spawn_reaped(cmd, "threshold");
+    }
+}
+
+impl<M: Module> Module for NotifyOnThreshold<M> {
+    fn get_output(&mut self) -> ModuleRes {
+        let out = self.inner.get_output()?;
+        let value = match &self.metric {
+            Some(metric) => out.metric(metric),
+            None => find_number(&out.content).map(|(_, value, _)| value),
+        };
+        if let Some(value) = value {
+            let beyond = self.bound.beyond(value);
+            if beyond && !self.beyond {
+                (self.callback)(value);
+            }
+            self.beyond = beyond;
+        }
+        Ok(out)
+    }
+
+    fn rate(&self) -> usize {
+        self.inner.rate()
+    }
+
+    fn hidden_limit(&self) -> Option<usize> {
+        self.inner.hidden_limit()
+    }
+
+    fn set_expanded(&mut self, expanded: bool) {
+        self.inner.set_expanded(expanded);
+    }
+
+    fn on_click(&mut self, event: &ClickEvent) {
+        self.inner.on_click(event);
+    }
+
+    fn min_width(&self) -> Option<MinWidth> {
+        self.inner.min_width()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::VecDeque;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Mutex;
+
+    /// A clock that only moves when told to, shared between a test and the
+    /// module under test.
+    #[derive(Clone)]
+    struct FakeClock(Arc<Mutex<(DateTime<Local>, Instant)>>);
+
+    impl FakeClock {
+        fn at(now: DateTime<Local>) -> Self {
+            FakeClock(Arc::new(Mutex::new((now, Instant::now()))))
+        }
+
+        fn advance(&self, by: Duration) {
+            let mut time = self.0.lock().unwrap();
+            time.0 += chrono::Duration::from_std(by).unwrap();
+            time.1 += by;
+        }
+    }
+
+    impl Clock for FakeClock {
+        fn now(&self) -> DateTime<Local> {
+            self.0.lock().unwrap().0
+        }
+
+        fn instant(&self) -> Instant {
+            self.0.lock().unwrap().1
+        }
+    }
+
+    /// An in-memory sink that stays readable after being handed to `Modules`.
+    #[derive(Clone, Default)]
+    struct SharedBuf(Arc<Mutex<Vec<u8>>>);
+
+    impl IoWrite for SharedBuf {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.0.lock().unwrap().write(buf)
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    impl SharedBuf {
+        fn lines(&self) -> Vec<serde_json::Value> {
+            let buf = self.0.lock().unwrap();
+            String::from_utf8_lossy(&buf)
+                .lines()
+                .map(|l| serde_json::from_str(l).unwrap())
+                .collect()
+        }
+    }
+
+    /// Returns a scripted sequence of results, panicking if polled more often.
+    struct MockModule {
+        script: VecDeque<ModuleRes>,
+        rate: usize,
+        hidden_limit: Option<usize>,
+    }
+
+    impl MockModule {
+        fn new(script: impl IntoIterator<Item = ModuleRes>) -> Self {
+            MockModule {
+                script: script.into_iter().collect(),
+                rate: 1,
+                hidden_limit: None,
+            }
+        }
+
+        fn with_rate(mut self, rate: usize) -> Self {
+            self.rate = rate;
+            self
+        }
+
+        fn with_hidden_limit(mut self, limit: usize) -> Self {
+            self.hidden_limit = Some(limit);
+            self
+        }
+    }
+
+    impl Module for MockModule {
+        fn get_output(&mut self) -> ModuleRes {
+            self.script
+                .pop_front()
+                .expect("mock module polled more often than scripted")
+        }
+
+        fn rate(&self) -> usize {
+            self.rate
+        }
+
+        fn hidden_limit(&self) -> Option<usize> {
+            self.hidden_limit
+        }
+    }
+
+    fn ok(content: &str) -> ModuleRes {
+        Ok(ModuleOutput::new(content.to_string()))
+    }
+
+    #[test]
+    fn keeps_cached_block_between_refreshes() {
+        let mut modules = modules![MockModule::new([ok("a"), ok("b")]).with_rate(2)];
+        let expected_a = r#"[{"full_text": "a", "instance": "0"}]"#;
+        assert_eq!(modules.combine_modules(), Some(expected_a));
+        assert_eq!(modules.combine_modules(), Some(expected_a));
+        assert_eq!(
+            modules.combine_modules(),
+            Some(r#"[{"full_text": "b", "instance": "0"}]"#)
+        );
+    }
+
+    #[test]
+    fn hidden_result_drops_the_block() {
+        let mut modules = modules![
+            MockModule::new([ok("a"), Err(None::<String>.into())]),
+            MockModule::new([ok("b"), ok("c")])
+        ];
+        modules.combine_modules();
+        assert_eq!(
+            modules.combine_modules(),
+            Some(r#"[{"full_text": "c", "instance": "1"}]"#)
+        );
+    }
+
+    #[test]
+    fn error_message_renders_red_block() {
+        let mut modules = modules![MockModule::new([Err(Some("boom".to_string()).into())])];
+        assert_eq!(
+            modules.combine_modules(),
+            Some(r##"[{"full_text": "boom", "color": "#ff0000"}]"##)
+        );
+    }
+
+    #[test]
+    fn empty_error_message_renders_generic_error() {
+        let mut modules = modules![MockModule::new([Err(Some(String::new()).into())])];
+        assert_eq!(
+            modules.combine_modules(),
+            Some(r##"[{"full_text": "error", "color": "#ff0000"}]"##)
+        );
+    }
+
+    #[test]
+    fn default_separator_yields_to_module_setting() {
+        let mut modules = modules![
+            MockModule::new([ok("a")]),
+            MockModule::new([Ok(ModuleOutput::new("b".to_string())
+                .with_separator(true)
+                .with_separator_block_width(3))]),
+        ]
+        .with_default_separator(Some(false), Some(9));
+        assert_eq!(
+            modules.combine_modules(),
+            Some(concat!(
+                r#"[{"full_text": "a", "separator": false, "separator_block_width": 9, "instance": "0"}, "#,
+                r#"{"full_text": "b", "separator": true, "separator_block_width": 3, "instance": "1"}]"#
+            ))
+        );
+    }
+
+    #[test]
+    fn header_advertises_default_separator_width() {
+        let modules = modules![MockModule::new([])].with_default_separator(None, Some(9));
+        assert_eq!(
+            modules.header(true).unwrap(),
+            format!(
+                "{{\"version\": 1, \"stop_signal\": {}, \"cont_signal\": {}, \"click_events\": true, \"separator_block_width\": 9}}\n[",
+                STOP_SIGNAL, CONT_SIGNAL
+            )
+        );
+        assert!(!OutputFormat::I3bar
+            .header(false, None)
+            .unwrap()
+            .contains("separator_block_width"));
+        assert_eq!(OutputFormat::Diff.header(true, Some(9)), None);
+    }
+
+    #[test]
+    fn lines_are_separated_not_terminated() {
+        let script = || MockModule::new([ok("a"), ok("b"), ok("c")]);
+        let mut out = Vec::new();
+        let mut modules = modules![script()];
+        modules.write_header(&mut out, false).unwrap();
+        out.clear();
+        for _ in 0..3 {
+            modules.write_line(&mut out).unwrap();
+        }
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            concat!(
+                "[{\"full_text\": \"a\", \"instance\": \"0\"}]\n",
+                ",[{\"full_text\": \"b\", \"instance\": \"0\"}]\n",
+                ",[{\"full_text\": \"c\", \"instance\": \"0\"}]\n",
+            )
+        );
+
+        let mut out = Vec::new();
+        let mut modules = modules![script()].with_header(false);
+        modules.write_header(&mut out, false).unwrap();
+        modules.write_line(&mut out).unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            ",[{\"full_text\": \"a\", \"instance\": \"0\"}]\n"
+        );
+
+        let mut out = Vec::new();
+        let mut modules = modules![script()].with_output_format(OutputFormat::Plain(String::new()));
+        modules.write_header(&mut out, false).unwrap();
+        modules.write_line(&mut out).unwrap();
+        modules.write_line(&mut out).unwrap();
+        assert_eq!(String::from_utf8(out).unwrap(), "a\nb\n");
+    }
+
+    #[test]
+    fn socket_reader_gets_a_fresh_stream_on_reconnect() {
+        use crate::sink::UnixSocketSink;
+        use std::{io::Read, os::unix::net::UnixListener};
+
+        let path = std::env::temp_dir().join(format!("csb-test-{}.sock", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let listener = UnixListener::bind(&path).unwrap();
+        let mut modules = modules![MockModule::new([ok("a"), ok("b"), ok("c"), ok("d")])];
+        let mut sink = UnixSocketSink::new(&path).with_header(modules.header(false));
+        let read_line = |reader: &mut UnixStream| {
+            let mut line = Vec::new();
+            let mut byte = [0];
+            while reader.read(&mut byte).unwrap() == 1 && byte[0] != b'\n' {
+                line.push(byte[0]);
+            }
+            String::from_utf8(line).unwrap()
+        };
+
+        modules.write_line(&mut sink).unwrap();
+        let (mut reader, _) = listener.accept().unwrap();
+        assert!(read_line(&mut reader).starts_with("{\"version\": 1"));
+        assert_eq!(read_line(&mut reader), "[");
+        assert_eq!(
+            read_line(&mut reader),
+            r#"[{"full_text": "a", "instance": "0"}]"#
+        );
+        modules.write_line(&mut sink).unwrap();
+        assert_eq!(
+            read_line(&mut reader),
+            r#",[{"full_text": "b", "instance": "0"}]"#
+        );
+
+        drop(reader);
+        modules.write_line(&mut sink).unwrap();
+        modules.write_line(&mut sink).unwrap();
+        let (mut reader, _) = listener.accept().unwrap();
+        assert!(read_line(&mut reader).starts_with("{\"version\": 1"));
+        assert_eq!(read_line(&mut reader), "[");
+        assert_eq!(
+            read_line(&mut reader),
+            r#"[{"full_text": "d", "instance": "0"}]"#
+        );
+        let _ = std::fs::remove_file(&path);
+    }
+
+    #[test]
+    fn number_grouping_splits_whole_part_into_threes() {
+        assert_eq!(format_number(999., 0, Some(',')), "999");
+        assert_eq!(format_number(1000., 0, Some(',')), "1,000");
+        assert_eq!(format_number(42350., 0, Some(',')), "42,350");
+        assert_eq!(format_number(1234567.891, 2, Some(' ')), "1 234 567.89");
+        assert_eq!(format_number(-1000.5, 1, Some(',')), "-1,000.5");
+        assert_eq!(format_number(0., 1, Some(',')), "0.0");
+        assert_eq!(format_number(42350., 0, None), "42350");
+    }
+
+    #[test]
+    #[cfg(target_os = "linux")]
+    fn battery_current_is_averaged_over_recent_samples() {
+        let mut currents = History::new(3);
+        assert_eq!(smoothed_current(&currents), None);
+        for sample in [1000, 3000, 2000, 4000] {
+            currents.push(sample);
+        }
+        // The first sample has been pushed out of the window.
+        assert_eq!(smoothed_current(&currents), Some(3000.));
+        currents.clear();
+        currents.push(500);
+        assert_eq!(smoothed_current(&currents), Some(500.));
+    }
+
+    #[test]
+    #[cfg(target_os = "linux")]
+    fn throughput_divides_by_elapsed_time() {
+        let at = Instant::now();
+        let prev = TrafficSample {
+            at,
+            rx: 1000,
+            tx: 500,
+        };
+        let now = TrafficSample {
+            at: at + Duration::from_millis(2500),
+            rx: 6000,
+            tx: 500,
+        };
+        assert_eq!(throughput(prev, now), Some((2000., 0.)));
+        assert_eq!(throughput(prev, prev), None);
+        let reset = TrafficSample { rx: 10, ..now };
+        assert_eq!(throughput(prev, reset), Some((0., 0.)));
+        assert_eq!(format_byte_rate(2000.), "2.0 KiB/s");
+        assert_eq!(format_byte_rate(512.), "512 B/s");
+    }
+
+    #[test]
+    fn optional_numbers_serialize_as_json_literals() {
+        let mut buf = String::new();
+        map_optional(&mut buf, "urgent", Some(true));
+        map_optional(&mut buf, "separator", Some(false));
+        map_optional(&mut buf, "width", Some(12usize));
+        map_optional(&mut buf, "offset", Some(-3i32));
+        map_optional(&mut buf, "missing", None::<u32>);
+        assert_eq!(
+            buf,
+            r#", "urgent": true, "separator": false, "width": 12, "offset": -3"#
+        );
+        let float = |v: f64| {
+            let mut buf = String::new();
+            map_optional(&mut buf, "x", Some(v));
+            buf
+        };
+        assert_eq!(float(12.0), r#", "x": 12"#);
+        assert_eq!(float(-0.5), r#", "x": -0.5"#);
+        assert_eq!(float(2.5f32 as f64), r#", "x": 2.5"#);
+        assert_eq!(float(f64::NAN), r#", "x": null"#);
+        assert_eq!(float(f64::INFINITY), r#", "x": null"#);
+    }
+
+    #[test]
+    fn color_parses_names_and_hex() {
+        assert_eq!(Color::parse("red"), Ok(Color::rgb(0xff, 0, 0)));
+        assert_eq!(Color::parse("Orange"), Ok(Color::rgb(0xff, 0xa5, 0)));
+        assert_eq!(
+            Color::parse("#50FA7B80"),
+            Ok(Color::rgb(0x50, 0xfa, 0x7b).with_alpha(0x80))
+        );
+        assert!(Color::parse("chartreuse").is_err());
+        assert!(Color::parse("#12345").is_err());
+        assert_eq!(
+            ModuleOutput::new("a".to_string())
+                .with_color_fg("green".to_string())
+                .color_fg
+                .as_deref(),
+            Some("#00ff00")
+        );
+    }
+
+    #[test]
+    fn spacer_widths_split_the_free_room() {
+        assert_eq!(spacer_widths(80, 30, 1), [50]);
+        assert_eq!(spacer_widths(80, 75, 2), [3, 2]);
+        assert_eq!(spacer_widths(80, 100, 1), [0]);
+        assert!(spacer_widths(80, 30, 0).is_empty());
+    }
+
+    #[test]
+    fn flexible_spacer_pushes_blocks_right() {
+        let mut modules = modules![
+            MockModule::new([ok("ab")]),
+            SpacerModule::flexible(),
+            MockModule::new([ok("cde")]),
+        ]
+        .with_output_format(OutputFormat::Plain(String::new()))
+        .with_bar_width(Some(10));
+        assert_eq!(modules.combine_modules(), Some("ab     cde"));
+    }
+
+    #[test]
+    fn panicking_module_renders_an_error_block() {
+        // An empty script panics on the first poll.
+        let mut modules = modules![MockModule::new([]), MockModule::new([ok("a"), ok("b")])];
+        let line = modules.combine_modules().unwrap().to_string();
+        assert!(line.contains("panicked: "), "{}", line);
+        assert!(line.contains(r##""color": "#ff0000""##), "{}", line);
+        assert!(line.contains(r#""full_text": "a""#), "{}", line);
+        // The failing module backs off while the rest keep updating.
+        assert!(modules
+            .combine_modules()
+            .unwrap()
+            .contains(r#""full_text": "b""#));
+    }
+
+    #[test]
+    fn theme_sets_the_error_color() {
+        let theme: Theme = toml::from_str(r#"error = "orange""#).unwrap();
+        let mut modules = modules![MockModule::new([Err(Some("boom".to_string()).into())])]
+            .with_error_color(theme.error);
+        assert_eq!(
+            modules.combine_modules(),
+            Some(r##"[{"full_text": "boom", "color": "#ffa500"}]"##)
+        );
+    }
+
+    #[test]
+    #[cfg(target_os = "linux")]
+    fn brightness_steps_are_clamped() {
+        assert_eq!(brightness_target(BUTTON_SCROLL_UP, 500, 1000, 5), Some(550));
+        assert_eq!(
+            brightness_target(BUTTON_SCROLL_UP, 980, 1000, 5),
+            Some(1000)
+        );
+        assert_eq!(brightness_target(BUTTON_SCROLL_DOWN, 30, 1000, 5), Some(0));
+        // Small ranges still move by at least one unit.
+        assert_eq!(brightness_target(BUTTON_SCROLL_UP, 3, 7, 5), Some(4));
+        assert_eq!(brightness_target(1, 500, 1000, 5), None);
+    }
+
+    #[test]
+    fn glyph_overrides_replace_defaults() {
+        let glyphs: Glyphs = toml::from_str(r#"battery_full = "B""#).unwrap();
+        assert_eq!(glyphs.get("battery_full"), 'B');
+        assert_eq!(glyphs.get("battery_empty"), '\u{f244}');
+        assert_eq!(*glyphs.battery_levels().last().unwrap(), 'B');
+        assert!(toml::from_str::<Glyphs>(r#"batery_full = "B""#).is_err());
+    }
+
+    #[test]
+    fn unknown_top_level_config_keys_are_rejected() {
+        let parse = crate::config::parse;
+        assert!(parse("interval_ms = 500\n[output]\nformat = \"plain\"").is_ok());
+        for typo in [
+            "interval = 500",
+            "[ouput]",
+            "[[modules]]\ntype = \"spacer\"",
+        ] {
+            let err = parse(typo).err().expect(typo);
+            assert!(err.contains("unknown field"), "{}", err);
+        }
+    }
+
+    #[test]
+    #[cfg(feature = "sysinfo-modules")]
+    fn cpu_severity_takes_the_more_alarming_metric() {
+        let usage = DEFAULT_CPU_THRESHOLDS;
+        let temp = DEFAULT_TEMP_THRESHOLDS;
+        assert_eq!(cpu_severity(50., None, &usage, &temp), 50.);
+        // Temperature's warn and crit points land on usage's.
+        assert_eq!(cpu_severity(10., Some(70.), &usage, &temp), 80.);
+        assert_eq!(cpu_severity(10., Some(85.), &usage, &temp), 95.);
+        assert_eq!(cpu_severity(10., Some(35.), &usage, &temp), 40.);
+        assert_eq!(cpu_severity(90., Some(35.), &usage, &temp), 90.);
+        assert_eq!(cpu_severity(10., Some(90.), &usage, &temp), 100.);
+    }
+
+    #[test]
+    #[cfg(feature = "sysinfo-modules")]
+    fn top_process_picks_the_largest_usage() {
+        let processes = [("bash", 4_000u64), ("firefox", 2_200_000), ("sway", 90_000)];
+        assert_eq!(top_process(processes), Some(("firefox", 2_200_000)));
+        assert_eq!(top_process(Vec::<(&str, u64)>::new()), None);
+        let processes = [("ffmpeg", 180.), ("idle", f32::NAN), ("sway", 3.5)];
+        assert_eq!(top_process(processes), Some(("ffmpeg", 180.)));
+    }
+
+    #[test]
+    #[cfg(feature = "sysinfo-modules")]
+    fn top_process_handles_ties_and_lone_processes() {
+        assert_eq!(top_process([("init", 0u64)]), Some(("init", 0)));
+        // `max_by` keeps the last of equal maxima.
+        let processes = [("cc1", 50.), ("ld", 12.), ("cc1plus", 50.)];
+        assert_eq!(top_process(processes), Some(("cc1plus", 50.)));
+        assert_eq!(top_process([("idle", f32::NAN)]), None);
+    }
+
+    #[test]
+    fn render_template_substitutes_known_fields() {
+        let fields = [("used", "6.0".to_string()), ("pct", "37".to_string())];
+        assert_eq!(
+            render_template("{used} GiB ({pct}%)", &fields),
+            "6.0 GiB (37%)"
+        );
+        assert_eq!(render_template("{used}{used}", &fields), "6.06.0");
+        assert_eq!(render_template("{free} {pct}", &fields), "{free} 37");
+        assert_eq!(render_template("{{pct}} {used", &fields), "{37} {used");
+        assert_eq!(render_template("", &fields), "");
+    }
+
+    #[test]
+    #[cfg(feature = "sysinfo-modules")]
+    fn memory_fields_render_through_a_template() {
+        let fields = memory_fields(6 * 1024 * 1024, 9 * 1024 * 1024, 16 * 1024 * 1024, None);
+        assert_eq!(
+            render_template("{used}/{total} ({pct}%)", &fields),
+            "6.0/16.0 (37%)"
+        );
+    }
+
+    #[test]
+    #[cfg(feature = "sysinfo-modules")]
+    fn inode_usage_is_share_of_used_inodes() {
+        assert_eq!(inode_usage(1000, 880), Some(12));
+        assert_eq!(inode_usage(1000, 0), Some(100));
+        assert_eq!(inode_usage(1000, 1000), Some(0));
+        // btrfs and friends allocate inodes dynamically and report none.
+        assert_eq!(inode_usage(0, 0), None);
+    }
+
+    #[test]
+    fn errors_are_hidden_during_startup_grace() {
+        let grace = Duration::from_secs(60);
+        let mut modules = modules![
+            MockModule::new((0..2).map(|_| Err(Some("offline".to_string()).into()))),
+            MockModule::new(vec![ok("a"), ok("a")]),
+        ]
+        .with_output_format(OutputFormat::Plain(" | ".to_string()))
+        .with_startup_grace(grace);
+        assert_eq!(modules.combine_modules(), Some("a"));
+
+        modules.started = Instant::now().checked_sub(grace).unwrap();
+        modules.trigger_all();
+        assert_eq!(modules.refresh(), Some("offline | a"));
+    }
+
+    #[test]
+    fn find_number_locates_the_first_number() {
+        assert_eq!(find_number("12%"), Some((0..2, 12., 0)));
+        assert_eq!(find_number("↓ 1.25 MiB/s"), Some((4..8, 1.25, 2)));
+        assert_eq!(find_number("3/8 busy"), Some((0..1, 3., 0)));
+        assert_eq!(find_number("v2."), Some((1..2, 2., 0)));
+        assert_eq!(find_number("▁▂▃"), None);
+    }
+
+    #[test]
+    fn averaged_smooths_the_number() {
+        let mut module = Averaged::new(
+            MockModule::new([ok("10%"), ok("20%"), ok("60%"), ok("idle")]),
+            2,
+        );
+        let mut next = || module.get_output().unwrap().content().to_string();
+        assert_eq!(next(), "10%");
+        assert_eq!(next(), "15%");
+        assert_eq!(next(), "40%");
+        assert_eq!(next(), "idle");
+
+        let mut module = Averaged::new(MockModule::new([ok("1.0 GiB"), ok("1.5 GiB")]), 4);
+        module.get_output().unwrap();
+        assert_eq!(module.get_output().unwrap().content(), "1.2 GiB");
+    }
+
+    #[test]
+    #[cfg(feature = "sysinfo-modules")]
+    fn ram_pins_min_width_to_full_usage() {
+        let mut modules =
+            modules![Decorated::new(RamModule::new().with_template("{pct}%")).with_prefix("RAM ")];
+        assert!(modules
+            .combine_modules()
+            .unwrap()
+            .contains(r#""min_width": "RAM 100%""#));
+    }
+
+    #[test]
+    #[cfg(target_os = "linux")]
+    fn battery_min_width_fits_a_full_battery() {
+        let battery = BatteryModule::new(["/nonexistent"]).with_icons(['a', 'b', 'c']);
+        assert_eq!(
+            battery.min_width(),
+            Some(MinWidth::Text("c 100%".to_string()))
+        );
+        let battery = battery.with_display(BatteryDisplay::Cycles);
+        assert_eq!(battery.min_width(), None);
+    }
+
+    #[test]
+    #[cfg(target_os = "linux")]
+    fn percent_trend_projects_the_fitted_line() {
+        // One percent every 36 seconds, so 100 points per hour.
+        let draining: Vec<(f32, f32)> = (0..5).map(|i| (i as f32 * 36., 60. - i as f32)).collect();
+        let hours = percent_trend(&draining, 0.).unwrap();
+        assert!((hours - 0.56).abs() < 1e-3, "{}", hours);
+        // Heading away from the target.
+        assert_eq!(percent_trend(&draining, 100.), None);
+        // A single step isn't enough to go by.
+        assert_eq!(
+            percent_trend(&[(0., 60.), (30., 59.), (60., 59.)], 0.),
+            None
+        );
+        assert_eq!(percent_trend(&[], 0.), None);
+    }
+
+    #[test]
+    #[cfg(all(target_os = "linux", feature = "network"))]
+    fn network_down_behavior() {
+        let content = |module: &NetworkModule| {
+            module
+                .down("wlan0", "down")
+                .map(|out| out.content().to_string())
+        };
+        let module = NetworkModule::new("wlan0");
+        assert_eq!(content(&module), Ok("wlan0: down".to_string()));
+        let module = module.with_down_behavior(DownBehavior::Text("✗".to_string()));
+        assert_eq!(content(&module), Ok("✗".to_string()));
+        let module = module.with_down_behavior(DownBehavior::Hide);
+        assert!(matches!(content(&module), Err(ModuleError::Hidden)));
+    }
+
+    #[test]
+    #[cfg(target_os = "linux")]
+    fn default_gateway_picks_lowest_metric_default_route() {
+        let header =
+            "Iface\tDestination\tGateway \tFlags\tRefCnt\tUse\tMetric\tMask\t\tMTU\tWindow\tIRTT\n";
+        let table = format!(
+            "{}{}{}{}",
+            header,
+            "wlan0\t00000000\t0102A8C0\t0003\t0\t0\t600\t00000000\t0\t0\t0\n",
+            "eth0\t00000000\t0100000A\t0003\t0\t0\t100\t00000000\t0\t0\t0\n",
+            "eth0\t0000000A\t00000000\t0001\t0\t0\t100\t000000FF\t0\t0\t0\n",
+        );
+        assert_eq!(default_gateway(&table), Some(Ipv4Addr::new(10, 0, 0, 1)));
+        // Only a link-local route, e.g. while offline.
+        let offline = format!(
+            "{}{}",
+            header, "eth0\t0000000A\t00000000\t0001\t0\t0\t100\t000000FF\t0\t0\t0\n"
+        );
+        assert_eq!(default_gateway(&offline), None);
+        assert_eq!(default_gateway(""), None);
+    }
+
+    #[test]
+    fn dns_reports_resolver_result() {
+        let mut module = DnsModule::new().with_resolver(|host| host == "example.com");
+        assert_eq!(module.get_output().unwrap().content(), "DNS OK");
+        let mut module = module.with_host("invalid.");
+        assert_eq!(module.get_output().unwrap().content(), "DNS FAIL");
+    }
+
+    #[test]
+    fn dns_lookup_times_out() {
+        let hanging: Resolver = |_| {
+            std::thread::sleep(Duration::from_secs(5));
+            true
+        };
+        let timeout = Duration::from_millis(50);
+        assert!(!resolve_with_timeout(hanging, "example.com", timeout));
+        assert!(resolve_with_timeout(|_| true, "example.com", timeout));
+    }
+
+    #[test]
+    fn first_of_falls_through_errors() {
+        let mut module = FirstOf::new(vec![
+            Box::new(MockModule::new([
+                Err(Some("boom".to_string()).into()),
+                Err(ModuleError::Hidden),
+                ok("a"),
+            ])),
+            Box::new(MockModule::new([ok("b"), ok("b")])),
+        ]);
+        let mut next = || module.get_output().map(|out| out.content().to_string());
+        assert_eq!(next(), Ok("b".to_string()));
+        assert_eq!(next(), Ok("b".to_string()));
+        assert_eq!(next(), Ok("a".to_string()));
+    }
+
+    #[test]
+    fn first_of_stops_polling_fatal_modules() {
+        // The first mock would panic if polled a second time.
+        let mut module = FirstOf::new(vec![
+            Box::new(MockModule::new([Err(ModuleError::Fatal(
+                "gone".to_string(),
+            ))])),
+            Box::new(MockModule::new([ok("b"), Err(ModuleError::Hidden)])),
+        ]);
+        assert_eq!(module.get_output().unwrap().content(), "b");
+        assert!(matches!(module.get_output(), Err(ModuleError::Hidden)));
+        let mut empty = FirstOf::new(vec![]);
+        assert!(matches!(empty.get_output(), Err(ModuleError::Fatal(_))));
+    }
+
+    #[test]
+    fn rotating_cycles_and_skips_errors() {
+        let mut module = Rotating::new(vec![
+            Box::new(MockModule::new([ok("a"), ok("a"), ok("a")])),
+            Box::new(MockModule::new([ok("b"), Err(ModuleError::Hidden)])),
+            Box::new(MockModule::new([
+                ok("c"),
+                Err(ModuleError::Fatal("gone".to_string())),
+            ])),
+        ])
+        .with_period(3);
+        assert_eq!(module.rate(), 3);
+        let mut next = || module.get_output().map(|out| out.content().to_string());
+        assert_eq!(next(), Ok("a".to_string()));
+        assert_eq!(next(), Ok("b".to_string()));
+        assert_eq!(next(), Ok("c".to_string()));
+        assert_eq!(next(), Ok("a".to_string()));
+        // `b` is hidden and `c` gone, so it's back to `a`.
+        assert_eq!(next(), Ok("a".to_string()));
+    }
+
+    #[test]
+    #[cfg(target_os = "linux")]
+    fn eta_clock_adds_remaining_time() {
+        let now = Utc.with_ymd_and_hms(2024, 5, 1, 12, 18, 0).unwrap();
+        assert_eq!(eta_clock(now, 2.25), Some("14:33".to_string()));
+        assert_eq!(eta_clock(now, 12.), Some("00:18".to_string()));
+        assert_eq!(eta_clock(now, 0.), None);
+        assert_eq!(eta_clock(now, f32::INFINITY), None);
+    }
+
+    #[test]
+    fn on_demand_modules_refresh_only_when_triggered() {
+        // The mock panics if polled more than twice.
+        let mut modules = modules![
+            MockModule::new([ok("a"), Err(Some("boom".to_string()).into())]).with_rate(ON_DEMAND),
+            MockModule::new([ok("b"), ok("b"), ok("b"), ok("b")]),
+        ]
+        .with_output_format(OutputFormat::Plain(" ".to_string()));
+        assert_eq!(modules.combine_modules(), Some("a b"));
+        assert_eq!(modules.combine_modules(), Some("a b"));
+        assert_eq!(modules.combine_modules(), Some("a b"));
+
+        modules.trigger(0);
+        assert_eq!(modules.combine_modules(), Some("boom b"));
+        // Failing doesn't schedule a retry either.
+        assert_eq!(modules.refresh(), Some("boom b"));
+    }
+
+    #[test]
+    fn progress_bar_fills_by_eighths() {
+        assert_eq!(progress_bar(0., 10), "░░░░░░░░░░");
+        assert_eq!(progress_bar(50., 10), "█████░░░░░");
+        assert_eq!(progress_bar(100., 10), "██████████");
+        assert_eq!(progress_bar(150., 4), "████");
+        // 55% of 10 cells is 5.5 cells: five full and a half block.
+        assert_eq!(progress_bar(55., 10), "█████▌░░░░");
+        // Just under a full cell rounds up to it, just over adds a sliver.
+        assert_eq!(progress_bar(59.5, 10), "██████░░░░");
+        assert_eq!(progress_bar(61.25, 10), "██████▏░░░");
+        assert_eq!(progress_bar(60., 0), "");
+    }
+
+    #[test]
+    #[cfg(all(target_os = "linux", feature = "network"))]
+    fn ssid_change_starts_a_flash() {
+        // The first network seen isn't a change.
+        assert_eq!(ssid_flash_until(None, "home", 1, 0, 2), 0);
+        assert_eq!(ssid_flash_until(Some("home"), "home", 2, 0, 2), 0);
+        assert_eq!(ssid_flash_until(Some("home"), "cafe", 3, 0, 2), 5);
+        // A running flash carries on, and another change restarts it.
+        assert_eq!(ssid_flash_until(Some("cafe"), "cafe", 4, 5, 2), 5);
+        assert_eq!(ssid_flash_until(Some("cafe"), "home", 4, 5, 2), 6);
+    }
+
+    #[test]
+    fn file_module_transforms_and_formats() {
+        let path = std::env::temp_dir().join(format!("csb-file-test-{}", std::process::id()));
+        std::fs::write(&path, "45123\n").unwrap();
+        let mut module = FileModule::new(&path, "{}°C", scaled(1000., 1));
+        assert_eq!(module.get_output().unwrap().content(), "45.1°C");
+        let mut raw = FileModule::new(&path, "temp {}", |raw| Some(raw.to_string()));
+        assert_eq!(raw.get_output().unwrap().content(), "temp 45123");
+
+        std::fs::write(&path, "n/a").unwrap();
+        assert!(matches!(module.get_output(), Err(ModuleError::Message(_))));
+        std::fs::remove_file(&path).unwrap();
+        assert!(matches!(module.get_output(), Err(ModuleError::Hidden)));
+    }
+
+    #[test]
+    fn command_shows_first_line_or_error() {
+        let mut module = CommandModule::new("status").with_runner(|_, _| {
+            Ok(CommandOutput {
+                success: true,
+                stdout: "  12 mails  \n".to_string(),
+                ..Default::default()
+            })
+        });
+        assert_eq!(module.get_output().unwrap().content(), "12 mails");
+
+        let mut module = module.with_runner(|_, _| {
+            Ok(CommandOutput {
+                success: false,
+                stdout: "partial".to_string(),
+                stderr: "no such mailbox\n".to_string(),
+            })
+        });
+        let out = module.get_output().unwrap();
+        assert_eq!(out.content(), "no such mailbox");
+        assert_eq!(out.color_fg, Some(Theme::default().crit()));
+
+        let mut module = module.with_runner(|_, _| {
+            Ok(CommandOutput {
+                success: true,
+                ..Default::default()
+            })
+        });
+        assert!(matches!(module.get_output(), Err(ModuleError::Hidden)));
+        let mut module = module.with_runner(|_, _| Err("command timed out".to_string()));
+        assert!(matches!(module.get_output(), Err(ModuleError::Message(_))));
+    }
+
+    #[test]
+    fn command_output_follows_i3blocks_lines() {
+        let out = parse_command_output("12 mails\n").unwrap();
+        assert_eq!(
+            (out.content(), &out.short_text, &out.color_fg),
+            ("12 mails", &None, &None)
+        );
+
+        let out = parse_command_output("12 mails\n12\n#FF0000\n").unwrap();
+        assert_eq!(out.short_text.as_deref(), Some("12"));
+        assert_eq!(out.color_fg.as_deref(), Some("#ff0000"));
+
+        let out = parse_command_output("12 mails\n\nred\nextra\n").unwrap();
+        assert_eq!(out.short_text, None);
+        assert_eq!(out.color_fg, Some(Color::parse("red").unwrap().to_string()));
+
+        let out = parse_command_output("12 mails\n12\nnot a color\n").unwrap();
+        assert_eq!(out.color_fg, None);
+        assert!(parse_command_output("\n12\n").is_none());
+        assert!(parse_command_output("").is_none());
+    }
+
+    #[test]
+    fn run_shell_enforces_timeout() {
+        let out = run_shell("echo hi; echo oops >&2; exit 3", Duration::from_secs(5)).unwrap();
+        assert_eq!(
+            (out.success, out.stdout.as_str(), out.stderr.as_str()),
+            (false, "hi\n", "oops\n")
+        );
+        assert!(run_shell("sleep 5", Duration::from_millis(50)).is_err());
+    }
+
+    #[test]
+    fn run_shell_drains_output_beyond_the_pipe_buffer() {
+        let out = run_shell(
+            "head -c 300000 /dev/zero | tr '\\0' x; head -c 300000 /dev/zero >&2",
+            Duration::from_secs(5),
+        )
+        .unwrap();
+        assert!(out.success);
+        assert_eq!(out.stdout.len(), 300_000);
+        assert!(out.stdout.bytes().all(|b| b == b'x'));
+        assert_eq!(out.stderr.len(), 300_000);
+    }
+
+    #[test]
+    #[cfg(target_os = "linux")]
+    fn battery_events_fire_on_transitions() {
+        use BatteryState::*;
+        assert_eq!(battery_events(None, Charging, false, 50), []);
+        assert_eq!(
+            battery_events(None, Discharging, true, 5),
+            [BatteryEvent::Low(5)]
+        );
+        assert_eq!(
+            battery_events(Some((Discharging, false)), Charging, false, 50),
+            [BatteryEvent::ChargingStarted]
+        );
+        assert_eq!(
+            battery_events(Some((Charging, false)), Full, false, 100),
+            [BatteryEvent::ChargingStopped]
+        );
+        assert_eq!(
+            battery_events(Some((Charging, false)), Discharging, true, 8),
+            [BatteryEvent::ChargingStopped, BatteryEvent::Low(8)]
+        );
+        assert_eq!(
+            battery_events(Some((Discharging, true)), Discharging, true, 7),
+            []
+        );
+        assert_eq!(battery_events(Some((Idle, false)), Full, false, 100), []);
+        // Unplugging a full or not charging battery stops charging too.
+        assert_eq!(
+            battery_events(Some((Full, false)), Discharging, false, 99),
+            [BatteryEvent::ChargingStopped]
+        );
+        assert_eq!(
+            battery_events(Some((Idle, false)), Discharging, true, 6),
+            [BatteryEvent::ChargingStopped, BatteryEvent::Low(6)]
+        );
+        assert_eq!(
+            battery_events(Some((Discharging, false)), Full, false, 100),
+            []
+        );
+    }
+
+    #[test]
+    fn threshold_notifies_once_per_crossing() {
+        let fired = Arc::new(Mutex::new(Vec::new()));
+        let record = |fired: &Arc<Mutex<Vec<f64>>>| {
+            let fired = fired.clone();
+            move |value| fired.lock().unwrap().push(value)
+        };
+        let mut module = NotifyOnThreshold::new(
+            MockModule::new([
+                ok("temp 80°C"),
+                ok("temp 91°C"),
+                ok("temp 95°C"),
+                Err(None::<String>.into()),
+                ok("temp 92°C"),
+                ok("temp 85°C"),
+                ok("temp 93.5°C"),
+            ]),
+            Bound::Above(90.),
+            record(&fired),
+        );
+        for _ in 0..7 {
+            let _ = module.get_output();
+        }
+        assert_eq!(*fired.lock().unwrap(), [91., 93.5]);
+
+        let fired = Arc::new(Mutex::new(Vec::new()));
+        let mut module = NotifyOnThreshold::new(
+            MockModule::new([
+                ok("3 GiB").map(|out| out.with_metric("free", 3.)),
+                ok("no metric"),
+                ok("6 GiB").map(|out| out.with_metric("free", 6.)),
+                ok("4 GiB").map(|out| out.with_metric("free", 4.)),
+            ]),
+            Bound::Below(5.),
+            record(&fired),
+        )
+        .with_metric("free");
+        for _ in 0..4 {
+            let _ = module.get_output();
+        }
+        assert_eq!(*fired.lock().unwrap(), [3., 4.]);
+    }
+
+    #[test]
+    fn lerp_color_blends_across_warn_to_crit() {
+        let (ok, crit) = (Color::rgb(0x00, 0xff, 0x00), Color::rgb(0xff, 0x00, 0x00));
+        assert_eq!(
+            lerp_color(80., 70., 90., ok, crit),
+            Color::rgb(0x80, 0x80, 0x00)
+        );
+        assert_eq!(lerp_color(20., 70., 90., ok, crit), ok);
+        assert_eq!(lerp_color(70., 70., 90., ok, crit), ok);
+        assert_eq!(lerp_color(90., 70., 90., ok, crit), crit);
+        assert_eq!(lerp_color(120., 70., 90., ok, crit), crit);
+        assert_eq!(lerp_color(80., 80., 80., ok, crit), crit);
+        // Inverted: ok right up to crit instead of clamping to crit early.
+        assert_eq!(lerp_color(50., 70., 60., ok, crit), ok);
+        assert_eq!(lerp_color(60., 70., 60., ok, crit), crit);
+    }
+
+    #[test]
+    #[cfg(feature = "sysinfo-modules")]
+    fn inverted_thresholds_are_rejected() {
+        let build = |raw: &str| crate::config::parse(raw).and_then(|c| c.build());
+        let err = build("[[module]]\ntype = \"temperature\"\ncrit = 60").err();
+        assert_eq!(
+            err.as_deref(),
+            Some("module #1: warn level 70 must be below the critical level 60")
+        );
+        assert!(build("[[module]]\ntype = \"ram\"\nwarn = 90\ncrit = 90").is_err());
+        assert!(build("[[module]]\ntype = \"cpu\"\ntemp_warn = 95").is_err());
+        assert!(build("[[module]]\ntype = \"temperature\"\ncrit = 90").is_ok());
+    }
+
+    #[test]
+    fn datetime_follows_its_clock() {
+        let clock = FakeClock::at(Local.with_ymd_and_hms(2024, 1, 2, 23, 59, 30).unwrap());
+        let mut module = DateTimeModule::new()
+            .with_format("%Y-%m-%d %H:%M:%S")
+            .with_clock(clock.clone());
+        assert_eq!(
+            module.get_output().unwrap().content(),
+            "2024-01-02 23:59:30"
+        );
+        clock.advance(Duration::from_secs(45));
+        assert_eq!(
+            module.get_output().unwrap().content(),
+            "2024-01-03 00:00:15"
+        );
+
+        let mut module = DateTimeModule::new()
+            .with_date_format(DateFormat::Iso8601)
+            .with_clock(clock.clone());
+        assert_eq!(module.get_output().unwrap().content(), "2024-01-03T00:00");
+        let start = clock.instant();
+        clock.advance(Duration::from_secs(60));
+        assert_eq!(clock.instant() - start, Duration::from_secs(60));
+    }
+
+    #[test]
+    #[cfg(all(target_os = "linux", feature = "sysinfo-modules"))]
+    fn read_only_mounts_are_found_by_their_flags() {
+        let mounts = concat!(
+            "sysfs /sys sysfs rw,nosuid,nodev,noexec,relatime 0 0\n",
+            "/dev/nvme0n1p2 / ext4 ro,relatime,errors=remount-ro 0 0\n",
+            "/dev/sdb1 /media/USB\\040Stick vfat ro,nosuid 0 0\n",
+            "/dev/sda1 /home ext4 rw,relatime,errors=remount-ro 0 0\n",
+            "/dev/sr0 /mnt/iso iso9660 nosuid,ro 0 0\n",
+            "truncated line\n",
+        );
+        assert_eq!(
+            read_only_mounts(mounts),
+            [
+                ("/dev/nvme0n1p2", PathBuf::from("/")),
+                ("/dev/sdb1", PathBuf::from("/media/USB Stick")),
+                ("/dev/sr0", PathBuf::from("/mnt/iso")),
+            ]
+        );
+        assert_eq!(unescape_octal(r"a\134b\04"), r"a\b\04");
+    }
+
+    #[test]
+    #[cfg(feature = "sysinfo-modules")]
+    fn read_only_warning_is_opt_in() {
+        let out = || ModuleOutput::new("12 GiB".to_string());
+        let module = DiskSpaceModule::new("/");
+        assert!(module.warn_read_only(out()) == out());
+        assert!(
+            DiskSpaceModule::new("/")
+                .with_read_only_warning(true)
+                .read_only_warning
+        );
+    }
+
+    #[test]
+    #[cfg(all(target_os = "linux", feature = "sysinfo-modules"))]
+    fn cpu_breakdown_compares_two_snapshots() {
+        let before = parse_cpu_times(concat!(
+            "cpu  1000 50 300 8000 100 10 20 30 0 0\n",
+            "cpu0 500 25 150 4000 50 5 10 15 0 0\n",
+            "intr 12345\n",
+        ))
+        .unwrap();
+        assert_eq!(
+            before,
+            CpuTimes {
+                user: 1000,
+                system: 300,
+                idle: 8000,
+                iowait: 100,
+                steal: 30,
+                other: 80
+            }
+        );
+        let after = parse_cpu_times("cpu  1100 50 350 8200 400 10 20 180 0 0\n").unwrap();
+        // 800 ticks passed: 100 user, 50 system, 200 idle, 300 iowait, 150 steal.
+        assert_eq!(
+            cpu_breakdown(&before, &after),
+            Some([12.5, 6.25, 37.5, 18.75])
+        );
+        assert_eq!(cpu_breakdown(&after, &after), None);
+
+        // Old kernels without steal time, and garbage.
+        let old = parse_cpu_times("cpu  10 0 10 80 0 0 0\n").unwrap();
+        assert_eq!(old.steal, 0);
+        assert_eq!(
+            cpu_breakdown(&CpuTimes::default(), &old),
+            Some([10., 10., 0., 0.])
+        );
+        assert_eq!(parse_cpu_times("cpu  10 x 10 80\n"), None);
+        assert_eq!(parse_cpu_times("cpu0 10 0 10 80\n"), None);
+    }
+
+    #[test]
+    #[cfg(target_os = "linux")]
+    fn established_connections_are_counted_by_state() {
+        let table = concat!(
+            "  sl  local_address rem_address   st tx_queue rx_queue tr tm->when retrnsmt   uid  timeout inode\n",
+            "   0: 0100007F:0277 00000000:0000 0A 00000000:00000000 00:00000000 00000000     0        0 1234 1\n",
+            "   1: 0F02000A:A2B4 22D8B85D:01BB 01 00000000:00000000 02:000A3C4E 00000000  1000        0 5678 1\n",
+            "   2: 0F02000A:D1C2 8C1CD9AC:01BB 01 00000000:00000000 02:00092A15 00000000  1000        0 9012 1\n",
+            "   3: 0F02000A:C350 22D8B85D:01BB 06 00000000:00000000 03:00001234 00000000     0        0 0 3\n",
+        );
+        assert_eq!(count_established(table), 2);
+        // Only the header: no connections.
+        assert_eq!(count_established(table.lines().next().unwrap()), 0);
+        assert_eq!(count_established(""), 0);
+    }
+
+    #[test]
+    #[cfg(feature = "sysinfo-modules")]
+    fn memory_shows_used_or_available() {
+        let gib = 1024 * 1024;
+        let (used, available, total) = (6 * gib, 9 * gib + gib / 2, 16 * gib);
+        assert_eq!(
+            format_memory(RamDisplay::Used, used, available, total, None),
+            "6.0/16.0 GiB"
+        );
+        assert_eq!(
+            format_memory(RamDisplay::Available, used, available, total, None),
+            "9.5 GiB free"
+        );
+    }
+
+    #[test]
+    fn joined_merges_content_into_one_block() {
+        let mut joined = Joined::new(
+            MockModule::new([ok("▃▅"), ok("▁"), Err(None::<String>.into())]).with_rate(3),
+            MockModule::new([ok("62°C"), Err(None::<String>.into()), ok("40°C")]).with_rate(5),
+        );
+        assert_eq!(joined.rate(), 3);
+        assert_eq!(joined.get_output().unwrap().content(), "▃▅ 62°C");
+        assert_eq!(joined.get_output().unwrap().content(), "▁");
+        assert_eq!(joined.get_output().unwrap().content(), "40°C");
+    }
+
+    #[test]
+    fn threshold_levels_start_at_their_value() {
+        let thresholds = Thresholds::new(80., 95.);
+        assert_eq!(thresholds.level(0.), Level::Normal);
+        assert_eq!(thresholds.level(79.9), Level::Normal);
+        assert_eq!(thresholds.level(80.), Level::Warn);
+        assert_eq!(thresholds.level(94.9), Level::Warn);
+        assert_eq!(thresholds.level(95.), Level::Crit);
+        assert_eq!(thresholds.level(100.), Level::Crit);
+    }
+
+    #[test]
+    fn critical_threshold_marks_block_urgent() {
+        let theme = Theme::default();
+        let out = Thresholds::new(50., 90.).apply(95., &theme, ModuleOutput::default());
+        assert_eq!(out.color_fg, Some(theme.crit()));
+        assert_eq!(out.urgent, Some(true));
+        let out = Thresholds::new(50., 90.).apply(60., &theme, ModuleOutput::default());
+        assert_eq!(out.color_fg, Some(theme.warn()));
+        assert_eq!(out.urgent, None);
+    }
+
+    #[test]
+    #[cfg(feature = "sysinfo-modules")]
+    fn busy_cores_counts_saturated_cores() {
+        assert_eq!(busy_cores(&[]), 0);
+        assert_eq!(busy_cores(&[10., 80., 80.1, 100.]), 2);
+    }
+
+    #[test]
+    #[cfg(all(target_os = "linux", feature = "sysinfo-modules"))]
+    fn converts_thermal_zone_millidegrees() {
+        assert_eq!(millidegrees_to_celsius("45000\n"), Some(45.));
+        assert_eq!(millidegrees_to_celsius("61500"), Some(61.5));
+        assert_eq!(millidegrees_to_celsius("-2000"), Some(-2.));
+        assert_eq!(millidegrees_to_celsius(""), None);
+        assert!(is_cpu_zone("x86_pkg_temp\n"));
+        assert!(!is_cpu_zone("acpitz"));
+    }
+
+    #[test]
+    #[cfg(feature = "sysinfo-modules")]
+    fn aggregates_temperatures() {
+        let temps = [50., 62., 44.];
+        assert_eq!(aggregate_temps(&temps, TempAggregate::First), Some(50.));
+        assert_eq!(aggregate_temps(&temps, TempAggregate::Max), Some(62.));
+        assert_eq!(aggregate_temps(&temps, TempAggregate::Mean), Some(52.));
+        assert_eq!(aggregate_temps(&[], TempAggregate::Mean), None);
+    }
+
+    #[test]
+    #[cfg(feature = "sysinfo-modules")]
+    fn temperatures_render_in_whole_degrees() {
+        let mean = aggregate_temps(&[50., 52., 52.], TempAggregate::Mean).unwrap();
+        assert_eq!(temperature_label(mean), "51°C");
+        assert_eq!(temperature_label(61.5), "62°C");
+        assert_eq!(temperature_label(45.), "45°C");
+    }
+
+    #[test]
+    fn unchanged_output_reuses_serialized_block() {
+        let dump = SharedBuf::default();
+        let mut modules = modules![MockModule::new([ok("a"), ok("a"), ok("b")])]
+            .with_dump(Box::new(dump.clone()));
+        let expected_a = r#"[{"full_text": "a", "instance": "0"}]"#;
+        assert_eq!(modules.combine_modules(), Some(expected_a));
+        assert_eq!(modules.combine_modules(), Some(expected_a));
+        assert_eq!(
+            modules.combine_modules(),
+            Some(r#"[{"full_text": "b", "instance": "0"}]"#)
+        );
+        let statuses: Vec<_> = dump
+            .lines()
+            .iter()
+            .map(|l| l["modules"][0]["status"].clone())
+            .collect();
+        assert_eq!(statuses, ["ok", "unchanged", "ok"]);
+    }
+
+    #[test]
+    fn dump_reports_failing_module() {
+        let dump = SharedBuf::default();
+        let mut modules = modules![MockModule::new([Err("no such device".to_string().into())])]
+            .with_names(&["disk".to_string()])
+            .with_dump(Box::new(dump.clone()));
+        modules.combine_modules();
+        assert_eq!(
+            dump.lines()[0]["modules"][0],
+            serde_json::json!({ "name": "disk", "status": "error", "error": "no such device" })
+        );
+    }
+
+    #[test]
+    fn diff_format_sends_only_changed_blocks() {
+        let mut modules = modules![
+            MockModule::new([ok("a"), ok("a"), ok("a"), ok("a")]),
+            MockModule::new([ok("b"), ok("c"), Err(None::<String>.into())]),
+        ]
+        .with_output_format(OutputFormat::Diff);
+        assert_eq!(
+            modules.combine_modules(),
+            Some(concat!(
+                r#"{"changes": [[0, {"full_text": "a", "instance": "0"}], "#,
+                r#"[1, {"full_text": "b", "instance": "1"}]]}"#
+            ))
+        );
+        assert_eq!(
+            modules.combine_modules(),
+            Some(r#"{"changes": [[1, {"full_text": "c", "instance": "1"}]]}"#)
+        );
+        assert_eq!(
+            modules.combine_modules(),
+            Some(r#"{"changes": [[1, null]]}"#)
+        );
+        modules.trigger(0);
+        assert_eq!(modules.refresh(), None);
+    }
+
+    #[test]
+    fn json_format_keys_blocks_by_module() {
+        let mut modules = modules![
+            MockModule::new([
+                ok("12%").map(|out| out.with_metric("usage", 12.5)),
+                ok("12%")
+            ]),
+            MockModule::new([
+                ok("4.2/16 GiB")
+                    .map(|out| out.with_color_fg("#FF0000".to_string()))
+                    .map(|out| out.with_metric("used", 4.5e9)),
+                Err(None::<String>.into()),
+            ]),
+            MockModule::new([Err(Some("no \"battery\"".to_string()).into())]).with_rate(5),
+        ]
+        .with_keys(&["cpu".to_string(), "ram".to_string()])
+        .with_output_format(OutputFormat::Json);
+        assert_eq!(
+            modules.combine_modules(),
+            Some(concat!(
+                r#"{"cpu": {"text": "12%", "usage": 12.5}, "#,
+                r##""ram": {"text": "4.2/16 GiB", "color": "#ff0000", "used": 4500000000}, "##,
+                r#""3": {"error": "no \"battery\""}}"#
+            ))
+        );
+        assert_eq!(
+            modules.combine_modules(),
+            Some(r#"{"cpu": {"text": "12%"}, "ram": null, "3": {"error": "no \"battery\""}}"#)
+        );
+    }
+
+    #[test]
+    fn reload_swaps_in_rebuilt_modules() {
+        let build = |raw: &str| crate::config::parse(raw).and_then(|c| c.build());
+        let mut modules = build(
+            r#"
+            [output]
+            format = "plain"
+            separator = "|"
+            [[module]]
+            type = "spacer"
+            prefix = "a"
+            "#,
+        )
+        .unwrap();
+        modules.write_line(&mut Vec::new()).unwrap();
+
+        let next = build(
+            r#"
+            [output]
+            separator = ","
+            [[module]]
+            type = "spacer"
+            prefix = "b"
+            [[module]]
+            type = "spacer"
+            prefix = "c"
+            "#,
+        )
+        .unwrap();
+        modules.reload(next);
+        assert_eq!(modules.len(), 2);
+        assert!(modules.emitted);
+        assert!(matches!(modules.output_format(), OutputFormat::Plain(_)));
+        assert_eq!(modules.combine_modules(), Some("b|c"));
+    }
+
+    #[test]
+    fn max_len_truncates_every_block() {
+        let mut modules = modules![
+            MockModule::new([ok("日本語のウィンドウタイトル")]),
+            MockModule::new([ok("短い")]),
+            MockModule::new([ok("🎵🎶🎵🎶🎵").map(|out| out.with_max_len(2))]),
+            MockModule::new([Err(Some("ошибка чтения".to_string()).into())]),
+        ]
+        .with_output_format(OutputFormat::Plain("|".to_string()))
+        .with_max_len(Some(4));
+        assert_eq!(modules.combine_modules(), Some("日本語…|短い|🎵…|оши…"));
+    }
+
+    #[test]
+    fn staleness_follows_block_age() {
+        let minute = Some(Duration::from_secs(60));
+        assert!(!is_stale(Duration::from_secs(3600), None));
+        assert!(!is_stale(Duration::from_secs(60), minute));
+        assert!(is_stale(Duration::from_secs(61), minute));
+    }
+
+    #[test]
+    fn stale_blocks_are_recolored_until_refreshed() {
+        let mut modules = modules![MockModule::new([ok("a"), ok("a")]).with_rate(2)]
+            .with_stale_after(
+                Some(Duration::from_millis(20)),
+                Color::rgb(0x11, 0x22, 0x33),
+            );
+        assert_eq!(
+            modules.combine_modules(),
+            Some(r#"[{"full_text": "a", "instance": "0"}]"#)
+        );
+        std::thread::sleep(Duration::from_millis(30));
+        assert_eq!(
+            modules.combine_modules(),
+            Some(r##"[{"full_text": "a", "color": "#112233", "instance": "0"}]"##)
+        );
+        assert_eq!(
+            modules.combine_modules(),
+            Some(r#"[{"full_text": "a", "instance": "0"}]"#)
+        );
+    }
+
+    #[test]
+    fn control_commands_parse() {
+        assert_eq!(
+            Control::parse("highlight net"),
+            Ok(Control::Highlight {
+                module: "net".to_string(),
+                color: None,
+                ticks: 5
+            })
+        );
+        assert_eq!(
+            Control::parse("  highlight net #FF0000 3\n"),
+            Ok(Control::Highlight {
+                module: "net".to_string(),
+                color: Some(Color::rgb(0xff, 0, 0)),
+                ticks: 3,
+            })
+        );
+        assert_eq!(
+            Control::parse("highlight cpu 10 red"),
+            Ok(Control::Highlight {
+                module: "cpu".to_string(),
+                color: Some(Color::rgb(0xff, 0, 0)),
+                ticks: 10,
+            })
+        );
+        assert_eq!(
+            Control::parse("clear net"),
+            Ok(Control::Clear {
+                module: "net".to_string()
+            })
+        );
+        assert!(Control::parse("").is_err());
+        assert!(Control::parse("highlight").is_err());
+        assert!(Control::parse("highlight net 0").is_err());
+        assert!(Control::parse("highlight net 3 4").is_err());
+        assert!(Control::parse("highlight net notacolor").is_err());
+        assert!(Control::parse("clear net now").is_err());
+        assert!(Control::parse("flash net").is_err());
+    }
+
+    #[test]
+    fn highlights_recolor_blocks_for_a_while() {
+        let mut modules = modules![
+            MockModule::new([ok("a"), ok("a"), ok("a"), ok("a")]),
+            MockModule::new([ok("b")]).with_rate(10),
+        ]
+        .with_keys(&["net".to_string(), "cpu".to_string()])
+        .with_highlight_color(Color::rgb(0x12, 0x34, 0x56));
+        let plain = r#"[{"full_text": "a", "instance": "0"}, {"full_text": "b", "instance": "1"}]"#;
+        assert_eq!(modules.combine_modules(), Some(plain));
+
+        let highlight = Control::parse("highlight cpu 2").unwrap();
+        modules.control(&highlight).unwrap();
+        let highlighted = r##"[{"full_text": "a", "instance": "0"}, {"full_text": "b", "color": "#123456", "instance": "1"}]"##;
+        assert_eq!(modules.refresh(), Some(highlighted));
+        assert_eq!(modules.combine_modules(), Some(highlighted));
+        assert_eq!(modules.combine_modules(), Some(highlighted));
+        assert_eq!(modules.combine_modules(), Some(plain));
+
+        modules.control(&highlight).unwrap();
+        modules
+            .control(&Control::parse("clear cpu").unwrap())
+            .unwrap();
+        assert_eq!(modules.refresh(), Some(plain));
+        assert!(modules
+            .control(&Control::parse("clear disk").unwrap())
+            .is_err());
+    }
+
+    #[test]
+    fn joins_blocks_with_commas() {
+        let mut modules = modules![
+            MockModule::new([ok("a")]),
+            MockModule::new([ok("b")]),
+            MockModule::new([ok("c")]),
+        ];
+        assert_eq!(
+            modules.combine_modules(),
+            Some(concat!(
+                r#"[{"full_text": "a", "instance": "0"}, "#,
+                r#"{"full_text": "b", "instance": "1"}, "#,
+                r#"{"full_text": "c", "instance": "2"}]"#
+            ))
+        );
+    }
+
+    #[test]
+    fn plain_format_joins_bare_contents() {
+        let mut modules = modules![
+            MockModule::new([Ok(ModuleOutput::new("cpu 12%".to_string())
+                .with_color_fg("red".to_string())
+                .with_urgent(true))]),
+            MockModule::new([ok("ram 3.2G")]),
+            MockModule::new([ok("12:30")]),
+        ]
+        .with_output_format(OutputFormat::Plain(" | ".to_string()));
+        let line = modules.combine_modules().unwrap();
+        assert_eq!(line, "cpu 12% | ram 3.2G | 12:30");
+        assert!(!line.contains(['{', '}', '"']), "{}", line);
+    }
+
+    /// A block with characters every target has to escape.
+    fn escaped_block() -> ModuleOutput {
+        ModuleOutput::new("a \"<b>\" & c".to_string()).with_color_fg("#ff5555".to_string())
+    }
+
+    #[test]
+    fn i3bar_format_streams_json_arrays() {
+        let format = OutputFormat::I3bar;
+        let block = format.serialize_block(&escaped_block());
+        assert_eq!(
+            block,
+            r##"{"full_text": "a \"<b>\" & c", "color": "#ff5555"}"##
+        );
+        let mut line = String::new();
+        format.write_line(&mut line, [block.as_str(), "{}"]);
+        assert_eq!(line, format!("[{}, {{}}]", block));
+        assert!(format.header(false, None).unwrap().ends_with("\n["));
+        assert_eq!(format.footer(), Some("]"));
+        assert_eq!(format.line_separator(), ",");
+    }
+
+    #[test]
+    fn waybar_format_writes_one_markup_object() {
+        let format = OutputFormat::Waybar(" · ".to_string());
+        let block = format.serialize_block(&escaped_block());
+        assert_eq!(
+            block,
+            "<span foreground='#ff5555'>a &quot;&lt;b&gt;&quot; &amp; c</span>"
+        );
+        assert_eq!(
+            format.serialize_block(&ModuleOutput::new("x".to_string())),
+            "x"
+        );
+        let mut line = String::new();
+        format.write_line(&mut line, ["a", "b"]);
+        assert_eq!(line, r#"{"text": "a · b"}"#);
+        assert_eq!(format.header(true, None), None);
+        assert_eq!(format.footer(), None);
+        assert_eq!(format.line_separator(), "");
+    }
+
+    #[test]
+    fn plain_format_writes_text_only() {
+        let format = OutputFormat::Plain(" | ".to_string());
+        assert_eq!(format.serialize_block(&escaped_block()), "a \"<b>\" & c");
+        let mut line = String::new();
+        format.write_line(&mut line, ["a", "b"]);
+        assert_eq!(line, "a | b");
+        assert_eq!(format.header(true, None), None);
+        assert_eq!(format.footer(), None);
+    }
+
+    #[test]
+    fn diff_format_lists_blocks_by_index() {
+        let format = OutputFormat::Diff;
+        let block = format.serialize_block(&escaped_block());
+        assert_eq!(block, OutputFormat::I3bar.serialize_block(&escaped_block()));
+        let mut line = String::new();
+        format.write_line(&mut line, ["{}", "{}"]);
+        assert_eq!(line, r#"{"changes": [[0, {}], [1, {}]]}"#);
+        assert_eq!(format.header(true, None), None);
+        assert_eq!(format.footer(), None);
+    }
+
+    #[test]
+    fn json_format_writes_text_objects() {
+        let format = OutputFormat::Json;
+        assert_eq!(
+            format.serialize_block(&escaped_block().with_metric("usage", 12.5)),
+            r##"{"text": "a \"<b>\" & c", "color": "#ff5555", "usage": 12.5}"##
+        );
+        let mut line = String::new();
+        format.write_line(&mut line, [r#""cpu": null"#, r#""ram": {}"#]);
+        assert_eq!(line, r#"{"cpu": null, "ram": {}}"#);
+        let mut error = String::new();
+        format.write_error(&mut error, "no \"sensor\"", DEFAULT_ERROR_COLOR);
+        assert_eq!(error, r#"{"error": "no \"sensor\""}"#);
+        assert_eq!(format.header(true, None), None);
+    }
+
+    #[test]
+    fn serialize_block_maps_each_set_field() {
+        type Setter = fn(ModuleOutput) -> ModuleOutput;
+        let fields: [(Setter, &str); 7] = [
+            (
+                |o| o.with_short_text("s".to_string()),
+                r#", "short_text": "s""#,
+            ),
+            (
+                |o| o.with_color_fg("#112233".to_string()),
+                r##", "color": "#112233""##,
+            ),
+            (
+                |o| o.with_color_bg("#445566".to_string()),
+                r##", "background": "#445566""##,
+            ),
+            (|o| o.with_border_top(2), r#", "border_top": 2"#),
+            (|o| o.with_separator(false), r#", "separator": false"#),
+            (|o| o.with_urgent(true), r#", "urgent": true"#),
+            (|o| o.with_name("cpu".to_string()), r#", "name": "cpu""#),
+        ];
+        for set in 0..1u32 << fields.len() {
+            let mut out = ModuleOutput::new("x".to_string());
+            let mut expected = r#"{"full_text": "x""#.to_string();
+            for (i, (setter, json)) in fields.iter().enumerate() {
+                if set & 1 << i != 0 {
+                    out = setter(out);
+                    expected += json;
+                }
+            }
+            expected.push('}');
+            assert_eq!(OutputFormat::I3bar.serialize_block(&out), expected);
+        }
+    }
+
+    #[test]
+    fn empty_module_set_follows_empty_line_setting() {
+        let hidden = || MockModule::new([Err(ModuleError::Hidden)]);
+        assert_eq!(modules![].combine_modules(), Some("[]"));
+        assert_eq!(modules![hidden(), hidden()].combine_modules(), Some("[]"));
+
+        let mut modules = modules![].with_empty_line(EmptyLine::Skip);
+        assert_eq!(modules.combine_modules(), None);
+        let mut out = Vec::new();
+        modules.write_line(&mut out).unwrap();
+        assert!(out.is_empty());
+        let mut modules = modules![hidden()].with_empty_line(EmptyLine::Skip);
+        assert_eq!(modules.combine_modules(), None);
+
+        let mut modules =
+            modules![hidden()].with_empty_line(EmptyLine::Placeholder("-".to_string()));
+        assert_eq!(modules.combine_modules(), Some(r#"[{"full_text": "-"}]"#));
+    }
+
+    #[test]
+    fn public_ip_response_is_parsed() {
+        assert_eq!(
+            parse_ip_response("203.0.113.7\n"),
+            Some(IpAddr::from([203, 0, 113, 7]))
+        );
+        assert_eq!(
+            parse_ip_response("2001:db8::1"),
+            Some("2001:db8::1".parse().unwrap())
+        );
+        assert_eq!(parse_ip_response("<html>rate limited</html>"), None);
+        assert_eq!(parse_ip_response(""), None);
+    }
+
+    #[test]
+    #[cfg(all(target_os = "linux", feature = "network"))]
+    fn vpn_status_lists_live_tunnels() {
+        let tunnel = |name: &str, state| (name.to_string(), state);
+        let glyphs = Glyphs::default();
+        assert_eq!(vpn_status(&[], &glyphs), None);
+        // Deactivating (3) and deactivated (4) tunnels don't count.
+        assert_eq!(
+            vpn_status(&[tunnel("work", 3), tunnel("home", 4)], &glyphs),
+            None
+        );
+        assert_eq!(
+            vpn_status(&[tunnel("work", NM_ACTIVE_ACTIVATED)], &glyphs),
+            Some(("\u{f023} work".to_string(), true))
+        );
+        assert_eq!(
+            vpn_status(
+                &[
+                    tunnel("work", NM_ACTIVE_ACTIVATED),
+                    tunnel("wg0", NM_ACTIVE_ACTIVATING),
+                ],
+                &glyphs
+            ),
+            Some(("\u{f023} work, wg0".to_string(), false))
+        );
+
+        let mut glyphs = Glyphs::default();
+        glyphs.set("vpn", 'V').unwrap();
+        assert_eq!(
+            vpn_status(&[tunnel("work", NM_ACTIVE_ACTIVATED)], &glyphs),
+            Some(("V work".to_string(), true))
+        );
+    }
+
+    #[test]
+    #[cfg(all(target_os = "linux", feature = "dbus-modules"))]
+    fn media_label_is_truncated_to_max_len() {
+        assert_eq!(
+            media_label("Playing", Some("Daft Punk"), Some("Veridis Quo")),
+            "▶ Daft Punk – Veridis Quo"
+        );
+        assert_eq!(media_label("Paused", None, Some("Intro")), "⏸ Intro");
+        assert_eq!(media_label("Stopped", None, None), "■ unknown");
+
+        let label = media_label("Playing", Some("Daft Punk"), Some("Veridis Quo"));
+        let out = ModuleOutput::new(label).with_max_len(12);
+        assert_eq!(out.full_text(), "▶ Daft Punk…");
+        let out = ModuleOutput::new(media_label("Paused", None, Some("Intro"))).with_max_len(12);
+        assert_eq!(out.full_text(), "⏸ Intro");
+    }
+
+    #[test]
+    fn truncation_counts_chars_not_bytes() {
+        // Each of these is several bytes long, so cutting by bytes would
+        // land inside a character.
+        assert_eq!(truncate("äöüß", 4), "äöüß");
+        assert_eq!(truncate("äöüßx", 4), "äöü…");
+        assert_eq!(truncate("日本語のテキスト", 3), "日本…");
+        assert_eq!(truncate("🎵🎶🎵", 2), "🎵…");
+        assert_eq!(truncate("ab", 1), "…");
+        assert_eq!(truncate("", 0), "");
+        let out = ModuleOutput::new("€uro €uro".to_string()).with_max_len(5);
+        assert_eq!(
+            OutputFormat::I3bar.serialize_block(&out),
+            r#"{"full_text": "€uro…"}"#
+        );
+    }
+
+    #[test]
+    fn min_width_serializes_as_number_or_text() {
+        let block = |min_width| {
+            OutputFormat::I3bar.serialize_block(
+                &ModuleOutput::new("a".to_string())
+                    .with_min_width(min_width)
+                    .with_align(Align::Right),
+            )
+        };
+        assert_eq!(
+            block(MinWidth::Pixels(120)),
+            r#"{"full_text": "a", "min_width": 120, "align": "right"}"#
+        );
+        assert_eq!(
+            block(MinWidth::Text("100% \"x\"".to_string())),
+            r#"{"full_text": "a", "min_width": "100% \"x\"", "align": "right"}"#
+        );
+        // Configs may give either form.
+        assert_eq!(
+            toml::from_str::<std::collections::BTreeMap<String, MinWidth>>("w = 80\nt = \"88:88\"")
+                .unwrap(),
+            [
+                ("t".to_string(), MinWidth::Text("88:88".to_string())),
+                ("w".to_string(), MinWidth::Pixels(80)),
+            ]
+            .into()
+        );
+    }
+
+    #[test]
+    #[cfg(target_os = "linux")]
+    fn update_counts_follow_each_backend() {
+        let pacman = "linux 6.9.1.arch1-1 -> 6.9.2.arch1-1\nmesa 1:24.0.7-1 -> 1:24.1.0-1\n\n";
+        assert_eq!(count_updates(UpdatesBackend::Pacman, pacman), 2);
+        assert_eq!(count_updates(UpdatesBackend::Pacman, ""), 0);
+
+        let apt = concat!(
+            "Listing... Done\n",
+            "curl/jammy-updates 7.81.0-1ubuntu1.16 amd64 [upgradable from: 7.81.0-1ubuntu1.15]\n",
+            "libcurl4/jammy-updates 7.81.0-1ubuntu1.16 amd64 [upgradable from: 7.81.0-1ubuntu1.15]\n",
+        );
+        assert_eq!(count_updates(UpdatesBackend::Apt, apt), 2);
+        assert_eq!(count_updates(UpdatesBackend::Apt, "Listing... Done\n"), 0);
+    }
+
+    #[test]
+    fn git_status_gives_branch_and_dirtiness() {
+        assert_eq!(
+            parse_git_status("## main...origin/main [ahead 1]\n"),
+            Some(("main".to_string(), false))
+        );
+        assert_eq!(
+            parse_git_status("## feature/x\n M src/lib.rs\n?? notes.txt\n"),
+            Some(("feature/x".to_string(), true))
+        );
+        assert_eq!(
+            parse_git_status("## No commits yet on trunk\n"),
+            Some(("trunk".to_string(), false))
+        );
+        assert_eq!(parse_git_status(""), None);
+        assert_eq!(parse_git_status(" M src/lib.rs\n"), None);
+    }
+
+    #[test]
+    #[cfg(feature = "sysinfo-modules")]
+    fn process_count_label_shows_the_count() {
+        assert_eq!(process_count_label(0), "procs: 0");
+        assert_eq!(process_count_label(1234), "procs: 1234");
+    }
+
+    #[test]
+    fn uptime_is_formatted_in_largest_units() {
+        assert_eq!(format_duration(0), "0m");
+        assert_eq!(format_duration(59), "0m");
+        assert_eq!(format_duration(60), "1m");
+        assert_eq!(format_duration(3599), "59m");
+        assert_eq!(format_duration(3600), "1h 0m");
+        assert_eq!(format_duration(86399), "23h 59m");
+        assert_eq!(format_duration(86400), "1d 0h 0m");
+        assert_eq!(format_duration(90061), "1d 1h 1m");
+    }
+
+    #[test]
+    #[cfg(target_os = "linux")]
+    fn time_remaining_is_formatted_from_hours() {
+        assert_eq!(format_time_remaining(2.5), Some("2h 30m".to_string()));
+        assert_eq!(format_time_remaining(0.5), Some("30m".to_string()));
+        assert_eq!(format_time_remaining(26.25), Some("1d 2h 15m".to_string()));
+        assert_eq!(format_time_remaining(0.), None);
+        // Under a minute, or no estimate at all.
+        assert_eq!(format_time_remaining(0.01), None);
+        assert_eq!(format_time_remaining(f32::INFINITY), None);
+        assert_eq!(format_time_remaining(f32::NAN), None);
+    }
+
+    #[test]
+    fn sparkline_clamps_percentages() {
+        assert_eq!(sparkline(&[0.], 0., 100.), "▁");
+        assert_eq!(sparkline(&[100.], 0., 100.), "█");
+        assert_eq!(sparkline(&[105.], 0., 100.), "█");
+        assert_eq!(sparkline(&[-5.], 0., 100.), "▁");
+    }
+
+    /// Writes a fake `power_supply` directory holding `files`, named after
+    /// the test so parallel tests don't share one.
+    #[cfg(target_os = "linux")]
+    fn fake_battery(name: &str, files: &[(&str, &str)]) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("csb-{}-{}", name, std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        for (file, contents) in files {
+            std::fs::write(dir.join(file), format!("{}\n", contents)).unwrap();
+        }
+        dir
+    }
+
+    #[test]
+    #[cfg(target_os = "linux")]
+    fn battery_reports_health_and_cycles() {
+        let dir = fake_battery(
+            "health",
+            &[
+                ("charge_full", "4200000"),
+                ("charge_full_design", "5000000"),
+                ("cycle_count", "312"),
+            ],
+        );
+        let content = |display| {
+            BatteryModule::new([&dir])
+                .with_display(display)
+                .get_output()
+                .map(|out| out.content().to_string())
+        };
+        assert_eq!(
+            content(BatteryDisplay::Health),
+            Ok("health 84%".to_string())
+        );
+        assert_eq!(
+            content(BatteryDisplay::Cycles),
+            Ok("312 cycles".to_string())
+        );
+        assert_eq!(battery_health(1, 0), None);
+
+        std::fs::remove_file(dir.join("cycle_count")).unwrap();
+        assert_eq!(content(BatteryDisplay::Cycles), Err(ModuleError::Hidden));
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    #[cfg(target_os = "linux")]
+    fn low_battery_turns_urgent_below_threshold() {
+        assert!(battery_low(9, true, 10));
+        assert!(!battery_low(10, true, 10));
+        assert!(!battery_low(5, false, 10));
+
+        let dir = fake_battery("low", &[("capacity", "8"), ("status", "Discharging")]);
+        let mut module = BatteryModule::new([&dir]).with_blink(true);
+        let out = module.get_output().unwrap();
+        assert_eq!(out.urgent, Some(true));
+        assert_eq!(out.color_fg, Some(Theme::default().fg()));
+        // Blinking alternates with the regular discharging color.
+        let out = module.get_output().unwrap();
+        assert_eq!(out.color_fg, Some(Theme::default().crit()));
+
+        let mut module = BatteryModule::new([&dir]).with_low_threshold(5);
+        assert_eq!(module.get_output().unwrap().urgent, None);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn weather_response_is_parsed() {
+        let body = r#"{
+            "latitude": 55.7, "longitude": 12.6, "elevation": 14.0,
+            "current_weather": {"temperature": 17.4, "windspeed": 11.2,
+                "winddirection": 240, "weathercode": 61, "time": "2024-06-01T12:00"}
+        }"#;
+        assert_eq!(parse_weather(body), Some((17.4, 61)));
+        assert_eq!(weather_glyph(61), '🌧');
+        assert_eq!(
+            parse_weather(r#"{"error": true, "reason": "bad latitude"}"#),
+            None
+        );
+        assert_eq!(parse_weather("not json"), None);
+    }
+
+    #[test]
+    #[cfg(target_os = "linux")]
+    fn mic_glyph_follows_mute_state() {
+        assert_eq!(parse_pactl_mute("Mute: yes\n"), Some(true));
+        assert_eq!(parse_pactl_mute("Mute: no\n"), Some(false));
+        assert_eq!(parse_pactl_mute("No such entity\n"), None);
+        let mut glyphs = Glyphs::default();
+        assert_eq!(mic_glyph(true, &glyphs), "🎤\u{338}");
+        assert_eq!(mic_glyph(false, &glyphs), "🎤");
+
+        glyphs.set("microphone", 'M').unwrap();
+        assert_eq!(mic_glyph(true, &glyphs), "M\u{338}");
+        assert_eq!(mic_glyph(false, &glyphs), "M");
+    }
+
+    #[test]
+    #[cfg(target_os = "linux")]
+    fn lock_key_bits_map_to_labels() {
+        assert_eq!(lock_keys_label(0), "");
+        assert_eq!(lock_keys_label(LOCK_CAPS), "CAPS");
+        assert_eq!(lock_keys_label(LOCK_NUM), "NUM");
+        assert_eq!(lock_keys_label(LOCK_CAPS | LOCK_NUM), "CAPS NUM");
+    }
+
+    #[test]
+    #[cfg(target_os = "linux")]
+    fn window_title_comes_from_the_backend() {
+        struct Titles(VecDeque<Option<String>>);
+
+        impl WindowTitleBackend for Titles {
+            fn title(&mut self) -> Option<String> {
+                self.0.pop_front().flatten()
+            }
+        }
+
+        let titles = [Some("Inbox — Mail".to_string()), None];
+        let mut module = WindowTitleModule::new(Titles(titles.into())).with_max_len(5);
+        let out = module.get_output().unwrap();
+        assert_eq!(out.content(), "Inbox — Mail");
+        assert_eq!(out.full_text(), "Inbo…");
+        // Nothing focused, e.g. an empty workspace.
+        assert!(matches!(module.get_output(), Err(ModuleError::Hidden)));
+
+        assert_eq!(
+            parse_xprop_value("_NET_WM_NAME(UTF8_STRING) = \"say \\\"hi\\\"\"\n"),
+            Some("say \"hi\"".to_string())
+        );
+        assert_eq!(
+            parse_xprop_value("_NET_ACTIVE_WINDOW(WINDOW): window id # 0x1c00007\n"),
+            Some("0x1c00007".to_string())
+        );
+    }
+
+    #[test]
+    #[cfg(target_os = "linux")]
+    fn focused_workspace_is_found_in_reply() {
+        let reply = r#"[
+            {"num": 1, "name": "1: web", "visible": true, "focused": false, "output": "eDP-1"},
+            {"num": 2, "name": "2: code", "visible": true, "focused": true, "output": "HDMI-A-1"},
+            {"num": 3, "name": "3", "visible": false, "focused": false, "output": "eDP-1"}
+        ]"#;
+        assert_eq!(focused_workspace(reply), Some("2: code".to_string()));
+        assert_eq!(
+            focused_workspace(r#"[{"name": "1", "focused": false}]"#),
+            None
+        );
+        assert_eq!(focused_workspace("[]"), None);
+        assert_eq!(focused_workspace(r#"{"success": false}"#), None);
+    }
+
+    #[test]
+    fn timer_counts_down_as_mm_ss() {
+        assert_eq!(format_clock(0), "00:00");
+        assert_eq!(format_clock(59), "00:59");
+        assert_eq!(format_clock(25 * 60), "25:00");
+        assert_eq!(format_clock(3599), "59:59");
+        assert_eq!(format_clock(3600), "01:00:00");
+
+        let mut timer = TimerModule::new(Duration::from_secs(90));
+        let start = timer.started;
+        assert_eq!(timer.remaining(start), Duration::from_secs(90));
+        assert_eq!(
+            timer.remaining(start + Duration::from_secs(30)),
+            Duration::from_secs(60)
+        );
+        assert_eq!(
+            timer.remaining(start + Duration::from_secs(120)),
+            Duration::ZERO
+        );
+        timer.paused_at = Some(start + Duration::from_secs(10));
+        assert_eq!(
+            timer.remaining(start + Duration::from_secs(60)),
+            Duration::from_secs(80)
+        );
+    }
+
+    #[test]
+    fn pomodoro_alternates_work_and_break() {
+        let mins = |m: u64| Duration::from_secs(m * 60);
+        let (work, rest) = (mins(25), mins(5));
+        assert_eq!(
+            pomodoro_phase(Duration::ZERO, work, rest),
+            (PomodoroPhase::Work, mins(25))
+        );
+        assert_eq!(
+            pomodoro_phase(mins(24), work, rest),
+            (PomodoroPhase::Work, mins(1))
+        );
+        assert_eq!(
+            pomodoro_phase(mins(25), work, rest),
+            (PomodoroPhase::Break, mins(5))
+        );
+        assert_eq!(
+            pomodoro_phase(mins(29), work, rest),
+            (PomodoroPhase::Break, mins(1))
+        );
+        // The next cycle starts with work again.
+        assert_eq!(
+            pomodoro_phase(mins(30), work, rest),
+            (PomodoroPhase::Work, mins(25))
+        );
+        assert_eq!(
+            pomodoro_phase(mins(57), work, rest),
+            (PomodoroPhase::Break, mins(3))
+        );
+    }
+
+    #[test]
+    fn users_are_counted_once_each() {
+        let who = concat!(
+            "alice    tty1         2024-06-01 08:12\n",
+            "alice    pts/0        2024-06-01 09:30 (:0)\n",
+            "bob      pts/1        2024-06-01 10:02 (10.0.0.7)\n",
+        );
+        assert_eq!(count_logged_in(who), 2);
+        assert_eq!(count_logged_in(""), 0);
+        assert_eq!(users_label(Some(2)), "users: 2");
+        assert_eq!(users_label(None), "users: ?");
+    }
+
+    #[test]
+    #[cfg(all(target_os = "linux", feature = "dbus-modules"))]
+    fn power_profiles_map_to_glyphs() {
+        let mut theme = Theme::default();
+        assert_eq!(
+            power_profile_glyph("performance", &theme),
+            Some(('\u{f0e7}', theme.crit()))
+        );
+        assert_eq!(
+            power_profile_glyph("balanced", &theme),
+            Some(('\u{f24e}', theme.fg()))
+        );
+        assert_eq!(
+            power_profile_glyph("power-saver", &theme),
+            Some(('\u{f06c}', theme.ok()))
+        );
+        assert_eq!(power_profile_glyph("turbo", &theme), None);
+
+        theme.glyphs.set("power_saver", 'S').unwrap();
+        assert_eq!(
+            power_profile_glyph("power-saver", &theme),
+            Some(('S', theme.ok()))
+        );
+    }
+
+    #[test]
+    #[cfg(target_os = "linux")]
+    fn nvidia_smi_csv_is_parsed() {
+        assert_eq!(parse_nvidia_smi("37, 61\n"), Some((37, 61)));
+        // Only the first GPU is shown.
+        assert_eq!(parse_nvidia_smi("5, 40\n80, 72\n"), Some((5, 40)));
+        assert_eq!(parse_nvidia_smi("[N/A], 45\n"), None);
+        assert_eq!(parse_nvidia_smi("12\n"), None);
+        assert_eq!(parse_nvidia_smi(""), None);
+    }
+
+    #[test]
+    #[cfg(target_os = "linux")]
+    fn psi_some_avg10_is_parsed() {
+        let memory = concat!(
+            "some avg10=12.34 avg60=3.10 avg300=0.88 total=123456789\n",
+            "full avg10=4.00 avg60=1.02 avg300=0.20 total=23456789\n",
+        );
+        assert_eq!(parse_psi_some_avg10(memory), Some(12.34));
+        assert_eq!(
+            parse_psi_some_avg10("full avg10=4.00 avg60=1.02 avg300=0.20 total=1\n"),
+            None
+        );
+        assert_eq!(parse_psi_some_avg10("some avg10=n/a\n"), None);
+        assert_eq!(parse_psi_some_avg10(""), None);
+    }
+
+    #[test]
+    #[cfg(target_os = "linux")]
+    fn custom_theme_colors_the_battery() {
+        let theme: Theme = toml::from_str(
+            r##"
+            ok = "#00ff00"
+            crit = "#0000ff"
+            "##,
+        )
+        .unwrap();
+        assert_eq!(theme.warn(), Theme::default().warn());
+        let theme = Arc::new(theme);
+
+        let dir = fake_battery("theme", &[("capacity", "60"), ("status", "Charging")]);
+        let mut module = BatteryModule::new([&dir]).with_theme(theme.clone());
+        assert_eq!(
+            module.get_output().unwrap().color_fg,
+            Some("#00ff00".to_string())
+        );
+
+        std::fs::write(dir.join("status"), "Discharging\n").unwrap();
+        let mut module = BatteryModule::new([&dir]).with_theme(theme);
+        assert_eq!(
+            module.get_output().unwrap().color_fg,
+            Some("#0000ff".to_string())
+        );
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn hex_colors_are_validated() {
+        assert_eq!(Color::from_hex("#50fa7b"), Ok(Color::rgb(0x50, 0xfa, 0x7b)));
+        assert_eq!(
+            Color::from_hex("#FF5555cc"),
+            Ok(Color::rgb(0xff, 0x55, 0x55).with_alpha(0xcc))
+        );
+        assert_eq!(Color::from_hex("#FF5555").unwrap().to_string(), "#ff5555");
+
+        for bad in [
+            "#gggggg",
+            "50fa7b",
+            "#50fa7",
+            "#50fa7b8",
+            "#50fa7b800",
+            "#",
+            "",
+            "#ééé",
+        ] {
+            assert!(
+                Color::from_hex(bad).is_err(),
+                "{:?} should be rejected",
+                bad
+            );
+        }
+        // Names are only understood by `parse`.
+        assert!(Color::from_hex("red").is_err());
+    }
+
+    #[test]
+    fn mixed_border_widths_are_serialized() {
+        let out = ModuleOutput::new("x".to_string())
+            .with_border("#bd93f9".to_string())
+            .with_border_top(2)
+            .with_border_bottom(0)
+            .with_border_left(1);
+        assert_eq!(
+            OutputFormat::I3bar.serialize_block(&out),
+            r##"{"full_text": "x", "border": "#bd93f9", "border_top": 2, "border_bottom": 0, "border_left": 1}"##
+        );
+    }
+
+    #[test]
+    fn gradient_interpolates_through_midpoints() {
+        let (black, white) = (Color::rgb(0, 0, 0), Color::rgb(0xff, 0xff, 0xff));
+        assert_eq!(black.lerp(white, 0.5), Color::rgb(0x80, 0x80, 0x80));
+        assert_eq!(black.lerp(white, 0.25), Color::rgb(0x40, 0x40, 0x40));
+        assert_eq!(black.lerp(white, -1.), black);
+        assert_eq!(black.lerp(white, 2.), white);
+        assert_eq!(
+            black.with_alpha(0).lerp(white, 0.5),
+            Color::rgb(0x80, 0x80, 0x80).with_alpha(0x80)
+        );
+
+        let theme = Theme {
+            ok: Color::rgb(0x00, 0xff, 0x00),
+            crit: Color::rgb(0xff, 0x00, 0x00),
+            ..Theme::default()
+        };
+        assert_eq!(theme.gradient_color(0.), "#00ff00");
+        assert_eq!(theme.gradient_color(25.), "#40bf00");
+        assert_eq!(theme.gradient_color(50.), "#808000");
+        assert_eq!(theme.gradient_color(75.), "#bf4000");
+        assert_eq!(theme.gradient_color(100.), "#ff0000");
+        assert_eq!(theme.gradient_color(150.), "#ff0000");
+    }
+
+    #[test]
+    fn sparkline_scales_to_its_range() {
+        let values = [1000., 1110., 1220., 1330., 1440., 1550., 1660., 1700.];
+        assert_eq!(sparkline(&values, 1000., 1700.), "▁▂▃▄▅▆▇█");
+        assert_eq!(sparkline(&[900., 1800.], 1000., 1700.), "▁█");
+        assert_eq!(sparkline(&[-3., 0., 3.], -4., 4.), "▁▄▇");
+        // A flat range can't be scaled, so it stays at the bottom.
+        assert_eq!(sparkline(&[5., 5.], 5., 5.), "▁▁");
+        assert_eq!(sparkline(&[], 1000., 1700.), "");
+    }
+
+    #[test]
+    fn history_wraps_around_keeping_the_newest() {
+        let mut history = History::new(3);
+        assert!(history.is_empty());
+        history.push(1);
+        history.push(2);
+        assert_eq!(history.iter().copied().collect::<Vec<_>>(), [1, 2]);
+
+        history.push(3);
+        history.push(4);
+        assert_eq!(history.len(), 3);
+        assert_eq!(history.iter().copied().collect::<Vec<_>>(), [2, 3, 4]);
+
+        for sample in 5..=9 {
+            history.push(sample);
+        }
+        assert_eq!(history.iter().copied().collect::<Vec<_>>(), [7, 8, 9]);
+
+        history.clear();
+        history.push(10);
+        assert_eq!(history.iter().copied().collect::<Vec<_>>(), [10]);
+
+        let mut empty = History::new(0);
+        empty.push(1);
+        assert!(empty.is_empty());
+    }
+
+    #[test]
+    #[cfg(all(target_os = "linux", feature = "network"))]
+    fn network_label_expands_to_show_detail() {
+        let detail = " 72% at home, 5 GHz";
+        assert_eq!(
+            network_label("wlan0", detail, " 192.168.1.20", true),
+            "wlan0: ( 72% at home, 5 GHz) 192.168.1.20"
+        );
+        assert_eq!(
+            network_label("wlan0", detail, " 192.168.1.20", false),
+            "wlan0: 192.168.1.20"
+        );
+
+        let mut module = NetworkModule::new("wlan0").with_expanded(false);
+        module.set_expanded(true);
+        assert!(module.expanded);
+        module.set_expanded(false);
+        assert!(!module.expanded);
+    }
+
+    #[test]
+    #[cfg(target_os = "linux")]
+    fn scroll_builds_clamped_volume_commands() {
+        let target =
+            |button, current| volume_adjust_args(button, current, 5).map(|args| args[2].clone());
+        assert_eq!(
+            volume_adjust_args(BUTTON_SCROLL_UP, 40, 5),
+            Some(vec![
+                "set-sink-volume".to_string(),
+                "@DEFAULT_SINK@".to_string(),
+                "45%".to_string(),
+            ])
+        );
+        assert_eq!(target(BUTTON_SCROLL_DOWN, 40), Some("35%".to_string()));
+        assert_eq!(target(BUTTON_SCROLL_UP, 98), Some("100%".to_string()));
+        assert_eq!(target(BUTTON_SCROLL_UP, 100), Some("100%".to_string()));
+        assert_eq!(target(BUTTON_SCROLL_DOWN, 3), Some("0%".to_string()));
+        assert_eq!(target(BUTTON_SCROLL_DOWN, 0), Some("0%".to_string()));
+        assert_eq!(target(1, 40), None);
+    }
+
+    #[test]
+    fn grouped_blocks_suppress_inner_separators() {
+        let group = |name: &str| Some(name.to_string());
+        let mut modules = modules![
+            MockModule::new([ok("a1")]),
+            MockModule::new([ok("a2")]),
+            MockModule::new([ok("a3")]),
+            MockModule::new([ok("solo")]),
+            MockModule::new([ok("b1")]),
+            MockModule::new([ok("b2")]),
+        ]
+        .with_groups(&[
+            group("a"),
+            group("a"),
+            group("a"),
+            None,
+            group("b"),
+            group("b"),
+        ]);
+        let line: serde_json::Value =
+            serde_json::from_str(modules.combine_modules().unwrap()).unwrap();
+        let separators: Vec<_> = line
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|block| block.get("separator").and_then(|s| s.as_bool()))
+            .collect();
+        assert_eq!(
+            separators,
+            [Some(false), Some(false), None, None, Some(false), None]
+        );
+    }
+
+    #[test]
+    fn fully_set_block_serializes_byte_for_byte() {
+        let out = ModuleOutput::new("cpu 12%".to_string())
+            .with_short_text("12%".to_string())
+            .with_color_fg("#50fa7b".to_string())
+            .with_color_bg("#44475a".to_string())
+            .with_border("#bd93f9".to_string())
+            .with_border_top(1)
+            .with_border_bottom(2)
+            .with_border_left(3)
+            .with_border_right(4)
+            .with_separator(false)
+            .with_separator_block_width(9)
+            .with_min_width(MinWidth::Pixels(80))
+            .with_align(Align::Right)
+            .with_urgent(true)
+            .with_name("cpu".to_string())
+            .with_instance("0".to_string());
+        assert_eq!(
+            OutputFormat::I3bar.serialize_block(&out),
+            concat!(
+                r##"{"full_text": "cpu 12%", "short_text": "12%", "color": "#50fa7b", "##,
+                r##""background": "#44475a", "border": "#bd93f9", "border_top": 1, "##,
+                r##""border_bottom": 2, "border_left": 3, "border_right": 4, "##,
+                r##""separator": false, "separator_block_width": 9, "min_width": 80, "##,
+                r##""align": "right", "urgent": true, "name": "cpu", "instance": "0"}"##,
+            )
+        );
+    }
+
+    /// Records every log line, for tests asserting something was logged.
+    struct CaptureLogger(Mutex<Vec<(log::Level, String)>>);
+
+    impl log::Log for CaptureLogger {
+        fn enabled(&self, _: &log::Metadata) -> bool {
+            true
+        }
+
+        fn log(&self, record: &log::Record) {
+            let line = record.args().to_string();
+            self.0.lock().unwrap().push((record.level(), line));
+        }
+
+        fn flush(&self) {}
+    }
+
+    static LOGGER: CaptureLogger = CaptureLogger(Mutex::new(Vec::new()));
+
+    /// Installs the capturing logger, returning a check for whether a line at
+    /// some level containing some text was logged. Tests run in parallel, so
+    /// the text should be unique to the test.
+    fn captured_logs() -> impl Fn(log::Level, &str) -> bool {
+        static INSTALL: std::sync::Once = std::sync::Once::new();
+        INSTALL.call_once(|| {
+            log::set_logger(&LOGGER).unwrap();
+            log::set_max_level(log::LevelFilter::Trace);
+        });
+        |level, needle| {
+            LOGGER
+                .0
+                .lock()
+                .unwrap()
+                .iter()
+                .any(|(l, line)| *l == level && line.contains(needle))
+        }
+    }
+
+    #[test]
+    fn failing_module_is_logged() {
+        let logged = captured_logs();
+        let mut modules = modules![
+            MockModule::new([ok("a")]),
+            MockModule::new([Err("sensor went away".to_string().into())]),
+        ];
+        modules.combine_modules();
+        assert!(logged(
+            log::Level::Warn,
+            "module #2 failed: sensor went away"
+        ));
+    }
+
+    #[test]
+    fn fatal_module_is_retired() {
+        let logged = captured_logs();
+        // Scripted for a single poll, so polling it again panics.
+        let fatal = MockModule::new([Err(ModuleError::Fatal("no such gpu".to_string()))]);
+        let mut modules = modules![MockModule::new((0..4).map(|_| ok("a"))), fatal];
+        for _ in 0..4 {
+            assert_eq!(
+                modules.combine_modules(),
+                Some(r#"[{"full_text": "a", "instance": "0"}]"#)
+            );
+        }
+        assert!(logged(log::Level::Error, "module #2 disabled: no such gpu"));
+    }
+
+    /// Always fails, counting how often it was polled.
+    struct Failing(Arc<AtomicUsize>);
+
+    impl Module for Failing {
+        fn get_output(&mut self) -> ModuleRes {
+            self.0.fetch_add(1, Ordering::Relaxed);
+            Err("offline".to_string().into())
+        }
+
+        fn rate(&self) -> usize {
+            1
+        }
+    }
+
+    #[test]
+    fn failing_module_backs_off() {
+        let polls = Arc::new(AtomicUsize::new(0));
+        let mut modules = modules![Failing(polls.clone())];
+        for _ in 0..30 {
+            modules.combine_modules();
+        }
+        // Polled on ticks 0, 2, 6 and 14, doubling the wait each time.
+        assert_eq!(polls.load(Ordering::Relaxed), 4);
+        assert_eq!(backoff_ticks(1, 1), 2);
+        assert_eq!(backoff_ticks(5, 3), 40);
+        assert_eq!(backoff_ticks(1, 40), MAX_BACKOFF_TICKS);
+        assert_eq!(backoff_ticks(600, 2), 600);
+    }
+
+    #[test]
+    fn always_hidden_module_stops_being_polled() {
+        let logged = captured_logs();
+        // Scripted for exactly the three polls before the limit is reached.
+        let hidden = MockModule::new((0..3).map(|_| Err(ModuleError::Hidden))).with_hidden_limit(3);
+        let mut modules = modules![MockModule::new((0..10).map(|_| ok("a"))), hidden];
+        for _ in 0..10 {
+            assert_eq!(
+                modules.combine_modules(),
+                Some(r#"[{"full_text": "a", "instance": "0"}]"#)
+            );
+        }
+        assert!(logged(
+            log::Level::Info,
+            "module #2 unavailable, no longer polling it"
+        ));
+    }
+
+    #[test]
+    fn short_text_drops_label_and_unit() {
+        let short = auto_short_text;
+        assert_eq!(short("cpu: 12 %"), Some("12".to_string()));
+        assert_eq!(short("temp: 54.5 °C"), Some("54.5".to_string()));
+        assert_eq!(short("ram: 3.2GiB"), Some("3.2GiB".to_string()));
+        assert_eq!(
+            short("wlan0: 192.168.1.20"),
+            Some("192.168.1.20".to_string())
+        );
+        // The unit is only dropped from a plain `number unit` pair.
+        assert_eq!(short("bat: 80% 2:10"), Some("80% 2:10".to_string()));
+        assert_eq!(short("vol: muted now"), Some("muted now".to_string()));
+
+        assert_eq!(short("12 %"), None);
+        assert_eq!(short(": 12"), None);
+        assert_eq!(short("load avg: 0.5"), None);
+        assert_eq!(short("connections: 3 up"), None);
+        assert_eq!(short("cpu:  "), None);
+    }
+
+    #[test]
+    fn argb_colors_keep_their_alpha() {
+        let color = Color::parse("#11223344").unwrap();
+        assert_eq!(color, Color::rgb(0x11, 0x22, 0x33).with_alpha(0x44));
+        assert_eq!(color.to_string(), "#11223344");
+
+        let out = ModuleOutput::new("x".to_string())
+            .with_color_fg("#11223344".to_string())
+            .with_color_bg("#AABBCCDD".to_string());
+        assert_eq!(
+            OutputFormat::I3bar.serialize_block(&out),
+            r##"{"full_text": "x", "color": "#11223344", "background": "#aabbccdd"}"##
+        );
+
+        let theme: Theme = toml::from_str(r##"ok = "#11223344""##).unwrap();
+        assert_eq!(theme.ok(), "#11223344");
+        // An opaque end counts as alpha `ff`.
+        assert_eq!(
+            color.lerp(Color::rgb(0x11, 0x22, 0x33), 0.5).to_string(),
+            "#112233a2"
+        );
+    }
+
+    #[test]
+    fn date_presets_format_a_fixed_instant() {
+        let now = Utc
+            .with_ymd_and_hms(2024, 3, 9, 7, 5, 42)
+            .unwrap()
+            .with_timezone(&Local);
+        let uptime = 3 * 86400 + 4 * 3600 + 12 * 60 + 59;
+        let format = |format| format_date(&format, now, uptime);
+        assert_eq!(format(DateFormat::Unix), "1709967942");
+        assert_eq!(format(DateFormat::SinceBoot), "3d 4h 12m");
+
+        // The other presets show local time, so pin the instant locally.
+        let local = Local.with_ymd_and_hms(2024, 3, 9, 7, 5, 42).unwrap();
+        let format = |format| format_date(&format, local, 0);
+        assert_eq!(format(DateFormat::Iso8601), "2024-03-09T07:05");
+        assert_eq!(
+            format(DateFormat::Custom("%a %d %b %H:%M:%S".to_string())),
+            "Sat 09 Mar 07:05:42"
+        );
+        assert_eq!(format(DateFormat::SinceBoot), "0m");
+    }
+
+    #[test]
+    fn stopwatch_adds_the_running_segment() {
+        let start = Instant::now();
+        let later = start + Duration::from_secs(90);
+        let before = Duration::from_secs(30);
+        assert_eq!(stopwatch_elapsed(before, None, later), before);
+        assert_eq!(
+            stopwatch_elapsed(Duration::ZERO, Some(start), later),
+            Duration::from_secs(90)
+        );
+        assert_eq!(
+            stopwatch_elapsed(before, Some(start), later),
+            Duration::from_secs(120)
+        );
+        // A clock reading before the segment started counts as no time.
+        assert_eq!(stopwatch_elapsed(before, Some(later), start), before);
+    }
+
+    #[test]
+    fn moon_phase_matches_known_dates() {
+        let phase = |y, m, d, h, min| {
+            MOON_PHASES[moon_phase_index(Utc.with_ymd_and_hms(y, m, d, h, min, 0).unwrap())]
+        };
+        assert_eq!(phase(2000, 1, 6, 18, 14), "🌑");
+        assert_eq!(phase(2024, 1, 11, 11, 57), "🌑");
+        assert_eq!(phase(2024, 1, 18, 3, 53), "🌓");
+        assert_eq!(phase(2024, 1, 25, 17, 54), "🌕");
+        assert_eq!(phase(2024, 2, 2, 23, 18), "🌗");
+        // Dates before the reference lunation wrap around too.
+        assert_eq!(phase(1999, 12, 22, 17, 31), "🌕");
+    }
+
+    #[test]
+    fn sun_times_cover_normal_and_polar_days() {
+        let date = |y, m, d| NaiveDate::from_ymd_opt(y, m, d).unwrap();
+        // London at midsummer: sunrise 03:43 UTC, sunset 21:21 BST (20:21 UTC).
+        let SunTimes::Normal { sunrise, sunset } = sun_times(date(2024, 6, 21), 51.5074, -0.1278)
+        else {
+            panic!("London has a sunrise in June");
+        };
+        let near = |time: DateTime<Utc>, h, m| {
+            let expected = Utc.with_ymd_and_hms(2024, 6, 21, h, m, 0).unwrap();
+            (time - expected).num_minutes().abs() <= 3
+        };
+        assert!(near(sunrise, 3, 43), "sunrise at {}", sunrise);
+        assert!(near(sunset, 20, 21), "sunset at {}", sunset);
+
+        // Tromsø, well inside the Arctic circle.
+        assert_eq!(
+            sun_times(date(2024, 6, 21), 69.65, 18.96),
+            SunTimes::PolarDay
+        );
+        assert_eq!(
+            sun_times(date(2024, 12, 21), 69.65, 18.96),
+            SunTimes::PolarNight
+        );
+        // And the Antarctic the other way round.
+        assert_eq!(
+            sun_times(date(2024, 6, 21), -77.85, 166.67),
+            SunTimes::PolarNight
+        );
+        assert_eq!(
+            sun_times(date(2024, 12, 21), -77.85, 166.67),
+            SunTimes::PolarDay
+        );
+    }
+
+    #[test]
+    fn ticker_price_is_read_for_the_coin_and_currency() {
+        let body = r#"{"bitcoin": {"usd": 42350.5, "eur": 39012}, "ethereum": {"usd": 2290.12}}"#;
+        assert_eq!(parse_ticker_price(body, "bitcoin", "usd"), Some(42350.5));
+        assert_eq!(parse_ticker_price(body, "bitcoin", "eur"), Some(39012.));
+        assert_eq!(parse_ticker_price(body, "ethereum", "usd"), Some(2290.12));
+        assert_eq!(parse_ticker_price(body, "ethereum", "eur"), None);
+        assert_eq!(parse_ticker_price(body, "dogecoin", "usd"), None);
+        assert_eq!(
+            parse_ticker_price(r#"{"bitcoin": {"usd": "n/a"}}"#, "bitcoin", "usd"),
+            None
+        );
+        assert_eq!(parse_ticker_price("rate limited", "bitcoin", "usd"), None);
+    }
+
+    #[test]
+    #[cfg(feature = "sysinfo-modules")]
+    fn hostname_shows_over_ssh_only_when_asked() {
+        let over_ssh =
+            |key: &str| (key == "SSH_CONNECTION").then(|| "10.0.0.2 51234 10.0.0.1 22".to_string());
+        let local = |_: &str| None;
+        let emptied = |key: &str| (key == "SSH_CONNECTION").then(String::new);
+
+        assert!(hostname_visible(false, local));
+        assert!(hostname_visible(false, over_ssh));
+        assert!(hostname_visible(true, over_ssh));
+        assert!(!hostname_visible(true, local));
+        assert!(!hostname_visible(true, emptied));
+    }
+
+    #[test]
+    #[cfg(all(target_os = "linux", feature = "dbus-modules"))]
+    fn dnd_state_picks_the_bell_glyph() {
+        let mut glyphs = Glyphs::default();
+        assert_eq!(dnd_glyph(true, &glyphs), '\u{f1f6}');
+        assert_eq!(dnd_glyph(false, &glyphs), '\u{f0f3}');
+
+        glyphs.set("notifications_off", 'Z').unwrap();
+        assert_eq!(dnd_glyph(true, &glyphs), 'Z');
+        assert_eq!(dnd_glyph(false, &glyphs), '\u{f0f3}');
+
+        assert!(parse_mako_modes("default\ndo-not-disturb\n"));
+        assert!(!parse_mako_modes("default\n"));
+        assert!(!parse_mako_modes(""));
+    }
+
+    #[test]
+    fn container_count_comes_from_the_api_response() {
+        let response = |status: &str, body: &str| {
+            format!(
+                "HTTP/1.0 {}\r\nContent-Type: application/json\r\n\r\n{}",
+                status, body
+            )
+        };
+        assert_eq!(
+            parse_container_count(&response("200 OK", r#"[{"Id": "a1"}, {"Id": "b2"}]"#)),
+            Some(2)
+        );
+        assert_eq!(parse_container_count(&response("200 OK", "[]")), Some(0));
+        assert_eq!(
+            parse_container_count(&response(
+                "500 Internal Server Error",
+                r#"{"message": "boom"}"#
+            )),
+            None
+        );
+        assert_eq!(
+            parse_container_count(&response("200 OK", r#"{"Id": "a1"}"#)),
+            None
+        );
+        assert_eq!(parse_container_count(&response("200 OK", "[{")), None);
+        assert_eq!(parse_container_count("HTTP/1.0 200 OK\r\n"), None);
+    }
+
+    #[test]
+    #[cfg(all(target_os = "linux", feature = "dbus-modules"))]
+    fn failed_units_are_counted_by_line() {
+        let output = concat!(
+            "● nfs-mount.service  loaded failed failed NFS mount\n",
+            "● backup.timer       loaded failed failed Nightly backup\n",
+            "\n",
+        );
+        assert_eq!(count_failed_units(output), 2);
+        assert_eq!(count_failed_units(""), 0);
+        assert_eq!(count_failed_units("  \n"), 0);
+    }
+
+    #[test]
+    fn unread_mail_is_counted_in_new() {
+        let maildir = std::env::temp_dir().join(format!("csb-maildir-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&maildir);
+        assert_eq!(count_maildir_new(&maildir), None);
+
+        for dir in ["new", "cur", "tmp"] {
+            std::fs::create_dir_all(maildir.join(dir)).unwrap();
+        }
+        assert_eq!(count_maildir_new(&maildir), Some(0));
+        for file in [
+            "new/1700000000.1.host",
+            "new/1700000001.2.host",
+            "new/.hidden",
+            "cur/1699999999.3.host:2,S",
+        ] {
+            std::fs::write(maildir.join(file), "").unwrap();
+        }
+        assert_eq!(count_maildir_new(&maildir), Some(2));
+        assert!(matches!(
+            MailModule::new(&maildir).get_output(),
+            Ok(out) if out.content() == "✉ 2"
+        ));
+        std::fs::remove_dir_all(&maildir).unwrap();
+    }
+
+    #[test]
+    fn next_event_is_the_soonest_upcoming_one() {
+        let ics = [
+            "BEGIN:VCALENDAR",
+            "VERSION:2.0",
+            "BEGIN:VEVENT",
+            "SUMMARY:Already over",
+            "DTSTART:20240310T080000",
+            "END:VEVENT",
+            "BEGIN:VEVENT",
+            "SUMMARY:Dentist\\, Dr. Smith",
+            "DTSTART;TZID=Europe/Copenhagen:20240312T143000",
+            "END:VEVENT",
+            "BEGIN:VEVENT",
+            "SUMMARY:Quarterly planning with the whole",
+            "  team",
+            "DTSTART:20240311T090000",
+            "END:VEVENT",
+            "BEGIN:VEVENT",
+            "SUMMARY:Holiday",
+            "DTSTART;VALUE=DATE:20240320",
+            "END:VEVENT",
+            "BEGIN:VEVENT",
+            "SUMMARY:No start time",
+            "END:VEVENT",
+            "END:VCALENDAR",
+        ]
+        .join("\r\n");
+        let at = |d, h, m| Local.with_ymd_and_hms(2024, 3, d, h, m, 0).unwrap();
+
+        assert_eq!(
+            next_event(&ics, at(10, 12, 0)),
+            Some((
+                "Quarterly planning with the whole team".to_string(),
+                at(11, 9, 0)
+            ))
+        );
+        // An event starting right now still counts as upcoming.
+        assert_eq!(
+            next_event(&ics, at(12, 14, 30)),
+            Some(("Dentist, Dr. Smith".to_string(), at(12, 14, 30)))
+        );
+        assert_eq!(
+            next_event(&ics, at(13, 0, 0)),
+            Some(("Holiday".to_string(), at(20, 0, 0)))
+        );
+        assert_eq!(next_event(&ics, at(21, 0, 0)), None);
+
+        let utc = "BEGIN:VEVENT\nSUMMARY:Call\nDTSTART:20240311T090000Z\nEND:VEVENT\n";
+        assert_eq!(
+            next_event(utc, at(1, 0, 0)).map(|(_, start)| start.with_timezone(&Utc)),
+            Some(Utc.with_ymd_and_hms(2024, 3, 11, 9, 0, 0).unwrap())
+        );
+    }
+
+    #[test]
+    fn open_todos_skip_done_and_blank_lines() {
+        let todo = concat!(
+            "(A) Call the landlord +flat\n",
+            "x 2024-03-01 Pay rent\n",
+            "\n",
+            "Buy milk @shop\n",
+            "   \n",
+            "xylophone lessons\n",
+        );
+        assert_eq!(count_open_todos(todo), 3);
+        assert_eq!(count_open_todos("x done\nx also done\n"), 0);
+        assert_eq!(count_open_todos(""), 0);
+    }
+
+    #[test]
+    #[cfg(target_os = "linux")]
+    fn throttling_shows_only_when_counters_rise() {
+        // The first sample has nothing to compare against.
+        assert!(!throttled_since(None, 12));
+        assert!(!throttled_since(Some(12), 12));
+        assert!(throttled_since(Some(12), 13));
+        // Counters reset on reboot or CPU hotplug.
+        assert!(!throttled_since(Some(12), 0));
+    }
+
+    #[test]
+    fn plain_separator_only_goes_between_shown_blocks() {
+        let hidden = || MockModule::new([Err(ModuleError::Hidden)]);
+        let plain = OutputFormat::Plain(" | ".to_string());
+
+        let mut modules = modules![MockModule::new([ok("solo")])].with_output_format(plain.clone());
+        assert_eq!(modules.combine_modules(), Some("solo"));
+
+        let mut modules = modules![
+            hidden(),
+            MockModule::new([ok("cpu")]),
+            hidden(),
+            MockModule::new([ok("ram")]),
+            hidden(),
+        ]
+        .with_output_format(plain.clone());
+        assert_eq!(modules.combine_modules(), Some("cpu | ram"));
+
+        let mut line = String::new();
+        plain.write_line(&mut line, ["a", "b", "c"]);
+        assert_eq!(line, "a | b | c");
+    }
+
+    #[test]
+    fn padding_surrounds_multibyte_content() {
+        let padded = |content: &str, left, right| {
+            let out = ModuleOutput::new(content.to_string()).with_padding(left, right);
+            OutputFormat::I3bar.serialize_block(&out)
+        };
+        assert_eq!(padded("🔋 80%", 1, 1), r#"{"full_text": " 🔋 80% "}"#);
+        assert_eq!(padded("日本語", 2, 0), r#"{"full_text": "  日本語"}"#);
+        assert_eq!(padded("\u{f240}", 0, 3), "{\"full_text\": \"\u{f240}   \"}");
+
+        // Padding is added after truncation, so it always survives.
+        let out = ModuleOutput::new("ñandú ñandú".to_string())
+            .with_max_len(4)
+            .with_padding(1, 1);
+        assert_eq!(
+            OutputFormat::Plain(String::new()).serialize_block(&out),
+            " ñan… "
+        );
+    }
+
+    #[test]
+    fn toggled_off_modules_are_skipped() {
+        // The second module is scripted for exactly the ticks it is enabled on.
+        let mut modules = modules![
+            MockModule::new((0..4).map(|_| ok("a"))),
+            MockModule::new([ok("b1"), ok("b2")]),
+        ]
+        .with_output_format(OutputFormat::Plain(" ".to_string()));
+        assert_eq!(modules.combine_modules(), Some("a b1"));
+
+        modules.toggle(1);
+        assert_eq!(modules.combine_modules(), Some("a"));
+        assert_eq!(modules.combine_modules(), Some("a"));
+
+        modules.toggle(1);
+        // Indices past the last module are ignored.
+        modules.toggle(7);
+        assert_eq!(modules.combine_modules(), Some("a b2"));
+    }
+
+    #[test]
+    #[cfg(target_os = "linux")]
+    fn battery_prefers_the_capacity_file() {
+        let dir = fake_battery(
+            "capacity",
+            &[
+                ("capacity", "73"),
+                ("charge_now", "2500000"),
+                ("charge_full", "5000000"),
+                ("status", "Discharging"),
+            ],
+        );
+        let content = || {
+            BatteryModule::new([&dir])
+                .with_icons(['a', 'b', 'c', 'd', 'e'])
+                .get_output()
+                .map(|out| out.content().to_string())
+        };
+        assert_eq!(content(), Ok("c 73%".to_string()));
+
+        // Without it, the charge ratio is used instead.
+        std::fs::remove_file(dir.join("capacity")).unwrap();
+        assert_eq!(content(), Ok("c 50%".to_string()));
+
+        std::fs::write(dir.join("capacity"), "garbage\n").unwrap();
+        assert_eq!(content(), Ok("c 50%".to_string()));
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    #[cfg(target_os = "linux")]
+    fn conflicting_batteries_combine_into_one_state() {
+        use BatteryState::*;
+        assert_eq!(battery_state(["Full\n", "Discharging\n"]), Discharging);
+        assert_eq!(battery_state(["Charging", "Discharging"]), Discharging);
+        assert_eq!(battery_state(["Full", "Charging"]), Charging);
+        assert_eq!(battery_state(["Unknown", "Charging"]), Charging);
+        assert_eq!(battery_state(["Not charging", "Full"]), Full);
+        assert_eq!(battery_state(["Unknown", "Full"]), Idle);
+        assert_eq!(battery_state::<&str>([]), Idle);
+
+        let bat0 = fake_battery("bat0-conflict", &[("capacity", "100"), ("status", "Full")]);
+        let bat1 = fake_battery(
+            "bat1-conflict",
+            &[("capacity", "40"), ("status", "Discharging")],
+        );
+        let out = BatteryModule::new([&bat0, &bat1]).get_output().unwrap();
+        assert_eq!(out.color_fg, Some(Theme::default().crit()));
+        assert!(out.content().ends_with(" 70%"), "{}", out.content());
+        std::fs::remove_dir_all(&bat0).unwrap();
+        std::fs::remove_dir_all(&bat1).unwrap();
+    }
+
+    #[test]
+    #[cfg(target_os = "linux")]
+    fn battery_watts_convert_micro_units() {
+        // 12.4 W reported directly in µW.
+        assert_eq!(battery_watts(Some(12_400_000), None, None), Some(12.4));
+        // `power_now` wins over the product when both are there.
+        assert_eq!(
+            battery_watts(Some(8_000_000), Some(12_000_000), Some(1_000_000)),
+            Some(8.)
+        );
+        // 11.55 V × 1.2 A.
+        let watts = battery_watts(None, Some(11_550_000), Some(1_200_000)).unwrap();
+        assert!((watts - 13.86).abs() < 1e-9, "{}", watts);
+        assert_eq!(battery_watts(None, None, Some(1_200_000)), None);
+        assert_eq!(battery_watts(None, Some(11_550_000), None), None);
+
+        let dir = fake_battery(
+            "watts",
+            &[("voltage_now", "11550000"), ("current_now", "1200000")],
+        );
+        let power = || {
+            BatteryModule::new([&dir])
+                .with_display(BatteryDisplay::Power)
+                .get_output()
+                .map(|out| out.content().to_string())
+        };
+        assert_eq!(power(), Ok("13.9W".to_string()));
+        std::fs::remove_file(dir.join("voltage_now")).unwrap();
+        assert_eq!(power(), Err(ModuleError::Hidden));
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    #[cfg(target_os = "linux")]
+    fn battery_icon_is_picked_from_a_custom_set() {
+        let icons = ['○', '◔', '◑', '◕', '●'];
+        let picked: Vec<char> = [0, 24, 25, 49, 50, 74, 75, 99, 100, 130]
+            .into_iter()
+            .map(|perc| battery_icon(&icons, perc))
+            .collect();
+        assert_eq!(picked, ['○', '○', '◔', '◔', '◑', '◑', '◕', '◕', '●', '●']);
+
+        // Sets of any size split the range evenly.
+        assert_eq!(battery_icon(&['-', '+'], 99), '-');
+        assert_eq!(battery_icon(&['-', '+'], 100), '+');
+        assert_eq!(battery_icon(&['x'], 0), 'x');
+        assert_eq!(battery_icon(&[], 50), ' ');
+
+        let defaults = Glyphs::default().battery_levels();
+        assert_eq!(battery_icon(&defaults, 100), '\u{f240}');
+        assert_eq!(battery_icon(&defaults, 10), '\u{f244}');
+    }
+
+    #[test]
+    fn labeled_module_prefixes_the_inner_output() {
+        let inner = MockModule::new([
+            Ok(ModuleOutput::new("12%".to_string()).with_color_fg("#ff5555".to_string())),
+            Err(ModuleError::Hidden),
+        ])
+        .with_rate(3);
+        let mut module = LabeledModule::new("CPU", inner);
+        assert_eq!(module.rate(), 3);
+
+        let out = module.get_output().unwrap();
+        assert_eq!(out.content(), "CPU 12%");
+        assert_eq!(out.color_fg.as_deref(), Some("#ff5555"));
+        // Errors pass through without a label.
+        assert!(matches!(module.get_output(), Err(ModuleError::Hidden)));
+    }
+
+    #[test]
+    fn decorated_overrides_colors_and_appends_suffix() {
+        let inner = MockModule::new([
+            Ok(ModuleOutput::new("21°C".to_string())
+                .with_color_fg("#50fa7b".to_string())
+                .with_short_text("21".to_string())),
+            Ok(ModuleOutput::new("22°C".to_string())),
+        ]);
+        let mut module = Decorated::new(inner)
+            .with_prefix("T ")
+            .with_suffix(" in")
+            .with_color_fg("orange".to_string())
+            .with_color_bg("#282a36".to_string());
+
+        let out = module.get_output().unwrap();
+        assert_eq!(out.content(), "T 21°C in");
+        assert_eq!(out.color_fg.as_deref(), Some("#ffa500"));
+        assert_eq!(out.color_bg.as_deref(), Some("#282a36"));
+        assert_eq!(out.short_text.as_deref(), Some("21"));
+
+        // Colors apply even when the module sets none itself.
+        let out = module.get_output().unwrap();
+        assert_eq!(out.content(), "T 22°C in");
+        assert_eq!(out.color_fg.as_deref(), Some("#ffa500"));
+    }
+
+    #[test]
+    fn modules_macro_takes_names_and_a_trailing_comma() {
+        let mut modules = modules![
+            "cpu" => MockModule::new([ok("12%")]),
+            MockModule::new([ok("plain")]),
+            "clock" => MockModule::new([ok("12:30")]),
+        ];
+        assert_eq!(modules.len(), 3);
+        assert_eq!(
+            modules.combine_modules(),
+            Some(concat!(
+                r#"[{"full_text": "12%", "name": "cpu", "instance": "0"}, "#,
+                r#"{"full_text": "plain", "instance": "1"}, "#,
+                r#"{"full_text": "12:30", "name": "clock", "instance": "2"}]"#,
+            ))
+        );
+        assert_eq!(modules![MockModule::new([]),].len(), 1);
+    }
+
+    #[test]
+    #[cfg(all(target_os = "linux", feature = "network"))]
+    fn primary_interface_is_picked_from_active_connections() {
+        let conn = |n: u32, ifaces: &[&str]| {
+            (
+                format!("/org/freedesktop/NetworkManager/ActiveConnection/{}", n),
+                ifaces.iter().map(|i| i.to_string()).collect::<Vec<_>>(),
+            )
+        };
+        let active = [conn(1, &["wlp3s0"]), conn(2, &["enp0s31f6"]), conn(3, &[])];
+        let primary = |n: u32| format!("/org/freedesktop/NetworkManager/ActiveConnection/{}", n);
+
+        assert_eq!(
+            select_primary_interface(&primary(1), &active),
+            Some("wlp3s0".to_string())
+        );
+        // Plugging in a cable moves the primary connection to ethernet.
+        assert_eq!(
+            select_primary_interface(&primary(2), &active),
+            Some("enp0s31f6".to_string())
+        );
+        assert_eq!(select_primary_interface(&primary(3), &active), None);
+        assert_eq!(select_primary_interface("/", &active), None);
+        assert_eq!(select_primary_interface(&primary(1), &[]), None);
+    }
+
+    #[test]
+    #[cfg(all(target_os = "linux", feature = "network"))]
+    fn signal_bars_start_a_bar_per_quarter() {
+        let bars: Vec<&str> = [0, 24, 25, 49, 50, 74, 75, 99, 100, 255]
+            .into_iter()
+            .map(signal_bars)
+            .collect();
+        assert_eq!(
+            bars,
+            [
+                "▂",
+                "▂",
+                "▂▄",
+                "▂▄",
+                "▂▄▆",
+                "▂▄▆",
+                "▂▄▆█",
+                "▂▄▆█",
+                "▂▄▆█",
+                "▂▄▆█"
+            ]
+        );
+    }
+
+    #[test]
+    #[cfg(all(target_os = "linux", feature = "network"))]
+    fn wifi_without_access_point_counts_as_down() {
+        let addr = || Some(vec![vec![u32::from_ne_bytes([192, 168, 1, 20]), 24, 0]]);
+        let ap = || Some(("home".to_string(), 72, 5180));
+        let mut module = NetworkModule::new("wlp3s0").with_name("W");
+
+        let out = module.wifi("W", None, addr(), Some(866_000)).unwrap();
+        assert_eq!(out.content(), "W: down");
+        assert_eq!(out.color_fg, Some(Theme::default().crit()));
+        // Associated, but no address yet.
+        assert_eq!(
+            module.wifi("W", ap(), None, None).unwrap().content(),
+            "W: down"
+        );
+
+        let out = module.wifi("W", ap(), addr(), Some(866_000)).unwrap();
+        assert_eq!(
+            out.content(),
+            "W: ( 72% at home, 5G, 866 Mb/s) 192.168.1.20"
+        );
+        assert_eq!(out.color_fg, Some(Theme::default().ok()));
+
+        let mut module = module.with_down_behavior(DownBehavior::Hide);
+        assert!(matches!(
+            module.wifi("W", None, addr(), None),
+            Err(ModuleError::Hidden)
+        ));
+    }
+
+    #[test]
+    #[cfg(all(target_os = "linux", feature = "network"))]
+    fn wifi_band_edges() {
+        let bands: Vec<_> = [
+            2399, 2400, 2412, 2500, 2501, 5149, 5150, 5895, 5896, 5925, 7125, 7126,
+        ]
+        .into_iter()
+        .map(wifi_band)
+        .collect();
+        assert_eq!(
+            bands,
+            [
+                None,
+                Some("2.4G"),
+                Some("2.4G"),
+                Some("2.4G"),
+                None,
+                None,
+                Some("5G"),
+                Some("5G"),
+                None,
+                Some("6G"),
+                Some("6G"),
+                None,
+            ]
+        );
+    }
+
+    #[test]
+    #[cfg(all(target_os = "linux", feature = "network"))]
+    fn bitrate_is_converted_from_kbps() {
+        assert_eq!(format_bitrate(866_700), "866 Mb/s");
+        assert_eq!(format_bitrate(54_000), "54 Mb/s");
+        assert_eq!(format_bitrate(999), "0 Mb/s");
+        assert_eq!(format_bitrate(2_401_000), "2401 Mb/s");
+
+        // The channel frequency is shown as a band, never as a rate.
+        let mut module = NetworkModule::new("wlp3s0");
+        let ap = Some(("home".to_string(), 50, 2437));
+        let addr = Some(vec![vec![u32::from_ne_bytes([10, 0, 0, 2]), 8, 0]]);
+        let out = module
+            .wifi("W", ap.clone(), addr.clone(), Some(144_400))
+            .unwrap();
+        assert_eq!(out.content(), "W: ( 50% at home, 2.4G, 144 Mb/s) 10.0.0.2");
+        let mut module = module.with_raw_frequency(true);
+        let out = module.wifi("W", ap, addr, None).unwrap();
+        assert_eq!(out.content(), "W: ( 50% at home, 2437 MHz) 10.0.0.2");
+    }
+
+    #[test]
+    #[cfg(all(target_os = "linux", feature = "network"))]
+    fn addresses_are_selected_from_several() {
+        let entry = |octets: [u8; 4]| vec![u32::from_ne_bytes(octets), 24, 0];
+        let entries = [
+            entry([192, 168, 1, 20]),
+            entry([10, 8, 0, 6]),
+            entry([10, 8, 0, 7]),
+            vec![],
+        ];
+        let select = |selection| select_addresses(&entries, selection);
+
+        assert_eq!(
+            select(AddressSelection::First),
+            [Ipv4Addr::new(192, 168, 1, 20)]
+        );
+        assert_eq!(
+            select(AddressSelection::All),
+            [
+                Ipv4Addr::new(192, 168, 1, 20),
+                Ipv4Addr::new(10, 8, 0, 6),
+                Ipv4Addr::new(10, 8, 0, 7),
+            ]
+        );
+        let vpn = AddressSelection::parse("10.0.0.0/8").unwrap();
+        assert_eq!(select(vpn), [Ipv4Addr::new(10, 8, 0, 6)]);
+        let none = AddressSelection::parse("172.16.0.0/12").unwrap();
+        assert!(select(none).is_empty());
+        assert_eq!(
+            select(AddressSelection::parse("0.0.0.0/0").unwrap()),
+            [Ipv4Addr::new(192, 168, 1, 20)]
+        );
+        assert!(select_addresses(&[], AddressSelection::First).is_empty());
+        assert_eq!(AddressSelection::parse("10.0.0.0/33"), None);
+    }
+
+    #[test]
+    #[cfg(feature = "sysinfo-modules")]
+    fn disk_space_sums_matching_disks() {
+        const GIB: u64 = 1024 * 1024 * 1024;
+        let disks = [
+            ("/dev/nvme0n1p2", Path::new("/"), 20 * GIB, 100 * GIB),
+            ("/dev/nvme0n1p3", Path::new("/home"), 150 * GIB, 400 * GIB),
+            ("/dev/sdb1", Path::new("/data"), 900 * GIB, 2000 * GIB),
+            ("tmpfs", Path::new("/tmp"), 8 * GIB, 8 * GIB),
+        ];
+        let devs = |devs: &[&str]| devs.iter().map(|d| d.to_string()).collect::<Vec<_>>();
+
+        // Devices and mount points can be mixed.
+        assert_eq!(
+            disk_totals(disks, &devs(&["/", "/home", "/dev/sdb1"])),
+            Some((1070 * GIB, 2500 * GIB))
+        );
+        // A disk that isn't mounted is skipped rather than failing the block.
+        assert_eq!(
+            disk_totals(disks, &devs(&["/", "/mnt/backup"])),
+            Some((20 * GIB, 100 * GIB))
+        );
+        assert_eq!(disk_totals(disks, &devs(&["/mnt/backup"])), None);
+
+        assert!(disk_matches(
+            &devs(&["/home"]),
+            "/dev/nvme0n1p3",
+            Path::new("/home")
+        ));
+        assert!(disk_matches(
+            &devs(&["/dev/sdb1"]),
+            "/dev/sdb1",
+            Path::new("/data")
+        ));
+        // Mount points match whole paths only.
+        assert!(!disk_matches(
+            &devs(&["/home"]),
+            "/dev/sdc1",
+            Path::new("/home/alice")
+        ));
+    }
+
+    #[test]
+    #[cfg(feature = "sysinfo-modules")]
+    fn lists_are_enumerated_once() {
+        #[derive(Default)]
+        struct Counts {
+            refreshes: usize,
+            listings: usize,
+        }
+        let refresh: fn(&mut Counts) = |c| c.refreshes += 1;
+        let list: fn(&mut Counts) = |c| c.listings += 1;
+
+        let (mut counts, mut listed) = (Counts::default(), false);
+        for _ in 0..5 {
+            refresh_listed(&mut counts, &mut listed, refresh, list);
+        }
+        assert_eq!((counts.listings, counts.refreshes), (1, 4));
+
+        // Modules clear `listed` when their device goes missing.
+        listed = false;
+        refresh_listed(&mut counts, &mut listed, refresh, list);
+        refresh_listed(&mut counts, &mut listed, refresh, list);
+        assert_eq!((counts.listings, counts.refreshes), (2, 5));
+    }
+
+    #[test]
+    #[cfg(target_os = "linux")]
+    fn full_battery_on_ac_can_be_hidden() {
+        assert!(battery_full_on_ac(&["Full"], 100));
+        assert!(battery_full_on_ac(&["Not charging\n"], 99));
+        assert!(!battery_full_on_ac(&["Not charging"], 98));
+        assert!(!battery_full_on_ac(&["Charging"], 100));
+        assert!(!battery_full_on_ac(&["Full", "Discharging"], 100));
+
+        let dir = fake_battery("ac", &[("capacity", "100"), ("status", "Full")]);
+        let output = |hide| {
+            BatteryModule::new([&dir])
+                .with_hide_when_full(hide)
+                .get_output()
+        };
+        assert!(matches!(output(true), Err(ModuleError::Hidden)));
+        // Off by default.
+        assert!(BatteryModule::new([&dir]).get_output().is_ok());
+
+        std::fs::write(dir.join("status"), "Discharging\n").unwrap();
+        assert!(output(true).is_ok());
+        std::fs::write(dir.join("status"), "Not charging\n").unwrap();
+        std::fs::write(dir.join("capacity"), "80\n").unwrap();
+        assert!(output(true).is_ok());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    #[cfg(target_os = "linux")]
+    fn battery_states_map_to_glyph_and_color() {
+        let theme = Theme::default();
+        let dir = fake_battery("states", &[("capacity", "100")]);
+        let output = |status: &str| {
+            std::fs::write(dir.join("status"), format!("{}\n", status)).unwrap();
+            let out = BatteryModule::new([&dir]).get_output().unwrap();
+            (out.content().chars().next().unwrap(), out.color_fg)
+        };
+        let full = theme.glyphs.get("battery_full");
+        assert_eq!(output("Charging"), (full, Some(theme.ok())));
+        assert_eq!(output("Discharging"), (full, Some(theme.crit())));
+        assert_eq!(output("Unknown"), (full, None));
+        // Done charging gets the plug and a neutral color.
+        let plugged = theme.glyphs.get("battery_plugged");
+        assert_eq!(output("Full"), (plugged, Some(theme.fg())));
+        assert_eq!(output("Not charging"), (plugged, Some(theme.fg())));
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn dump_follows_a_failing_module_through_backoff_and_retirement() {
+        let dump = SharedBuf::default();
+        let mut modules = modules![
+            MockModule::new([ok("12%"), ok("12%"), ok("12%")]),
+            MockModule::new([
+                Err("timed out".to_string().into()),
+                Err(ModuleError::Fatal("no such device".to_string())),
+            ]),
+        ]
+        .with_names(&["cpu".to_string(), "disk".to_string()])
+        .with_dump(Box::new(dump.clone()));
+        for _ in 0..3 {
+            modules.combine_modules();
+        }
+        let statuses: Vec<_> = dump
+            .lines()
+            .iter()
+            .map(|line| line["modules"][1].clone())
+            .collect();
+        assert_eq!(
+            statuses,
+            [
+                serde_json::json!({ "name": "disk", "status": "error", "error": "timed out" }),
+                // Backing off, so the error block stays up.
+                serde_json::json!({ "name": "disk", "status": "cached" }),
+                serde_json::json!({ "name": "disk", "status": "fatal", "error": "no such device" }),
+            ]
+        );
+        let line = dump.lines().pop().unwrap();
+        assert_eq!(line["modules"][0]["status"], "unchanged");
+    }
+
+    #[test]
+    fn in_memory_sink_gets_the_whole_stream() {
+        let sink = SharedBuf::default();
+        // Boxed the way `main` holds stdout or the socket sink.
+        let mut out: Box<dyn IoWrite> = Box::new(sink.clone());
+        let mut modules = modules![MockModule::new([ok("a"), ok("b")])];
+        modules.write_header(&mut out, true).unwrap();
+        modules.write_line(&mut out).unwrap();
+        modules.write_line(&mut out).unwrap();
+        let written = String::from_utf8(sink.0.lock().unwrap().clone()).unwrap();
+        let (header, lines) = written.split_once("\n[\n").unwrap();
+        assert!(header.starts_with("{\"version\": 1"), "{}", header);
+        assert!(header.ends_with("\"click_events\": true}"), "{}", header);
+        assert_eq!(
+            lines,
+            concat!(
+                "[{\"full_text\": \"a\", \"instance\": \"0\"}]\n",
+                ",[{\"full_text\": \"b\", \"instance\": \"0\"}]\n",
+            )
+        );
+    }
+
+    #[test]
+    fn write_line_streams_full_i3bar_lines() {
+        let mut modules = modules![
+            MockModule::new([ok("cpu 12%"), ok("cpu 40%")]),
+            MockModule::new([
+                Ok(ModuleOutput::new("12:30".to_string()).with_color_fg("#f8f8f2".to_string())),
+                Ok(ModuleOutput::new("12:31".to_string()).with_urgent(true)),
+            ]),
+        ];
+        let mut out: Vec<u8> = Vec::new();
+        modules.write_line(&mut out).unwrap();
+        modules.write_line(&mut out).unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            concat!(
+                r##"[{"full_text": "cpu 12%", "instance": "0"}, "##,
+                r##"{"full_text": "12:30", "color": "#f8f8f2", "instance": "1"}]"##,
+                "\n",
+                r##",[{"full_text": "cpu 40%", "instance": "0"}, "##,
+                r##"{"full_text": "12:31", "urgent": true, "instance": "1"}]"##,
+                "\n",
+            )
+        );
+        assert_eq!(
+            modules![MockModule::new([ok("x")])].combine_modules(),
+            Some(r#"[{"full_text": "x", "instance": "0"}]"#)
+        );
     }
 }
@@ -0,0 +1,80 @@
+use std::{
+    io::{self, Write},
+    os::unix::net::UnixStream,
+    path::{Path, PathBuf},
+};
+
+/// Writes the status line to whoever listens on a Unix socket, reconnecting
+/// after the reader goes away. Lines written while nobody listens are
+/// dropped, so a missing reader never stalls the bar.
+pub struct UnixSocketSink {
+    path: PathBuf,
+    stream: Option<UnixStream>,
+    /// Sent first on every new connection, e.g. the i3bar protocol header.
+    header: Option<String>,
+    /// Whether nothing but the header went out on the current connection.
+    fresh: bool,
+}
+
+impl UnixSocketSink {
+    pub fn new(path: &Path) -> Self {
+        UnixSocketSink {
+            path: path.to_path_buf(),
+            stream: None,
+            header: None,
+            fresh: false,
+        }
+    }
+
+    pub fn with_header(mut self, header: Option<String>) -> Self {
+        self.header = header;
+        self
+    }
+
+    fn connect(&mut self) -> Option<&mut UnixStream> {
+        if self.stream.is_none() {
+            let mut stream = UnixStream::connect(&self.path).ok()?;
+            if let Some(header) = &self.header {
+                writeln!(stream, "{}", header).ok()?;
+            }
+            log::info!("connected to {}", self.path.display());
+            self.stream = Some(stream);
+            self.fresh = true;
+        }
+        self.stream.as_mut()
+    }
+}
+
+impl Write for UnixSocketSink {
+    /// A new reader starts a new stream after the header, so the first line
+    /// it gets mustn't carry the comma separating it from a previous one.
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if self.connect().is_none() {
+            return Ok(buf.len());
+        }
+        let data = match (
+            std::mem::take(&mut self.fresh) && self.header.is_some(),
+            buf,
+        ) {
+            (true, [b',', rest @ ..]) => rest,
+            _ => buf,
+        };
+        let Some(stream) = &mut self.stream else {
+            return Ok(buf.len());
+        };
+        if let Err(e) = stream.write_all(data) {
+            log::warn!("lost connection to {}: {}", self.path.display(), e);
+            self.stream = None;
+        }
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        if let Some(stream) = &mut self.stream {
+            if stream.flush().is_err() {
+                self.stream = None;
+            }
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,6 @@
+#[test]
+fn modules_macro() {
+    let t = trybuild::TestCases::new();
+    t.pass("tests/ui/modules_trailing_comma.rs");
+    t.compile_fail("tests/ui/modules_malformed_entry.rs");
+}
@@ -0,0 +1,14 @@
+use custom_status_bar::modules;
+use custom_status_bar::modules::{Module, ModuleOutput, ModuleRes};
+
+struct Fixed;
+
+impl Module for Fixed {
+    fn get_output(&mut self) -> ModuleRes {
+        Ok(ModuleOutput::new("a".to_string()))
+    }
+}
+
+fn main() {
+    let _ = modules!["first" => , Fixed];
+}
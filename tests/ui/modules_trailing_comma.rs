@@ -0,0 +1,17 @@
+use custom_status_bar::modules;
+use custom_status_bar::modules::{Module, ModuleOutput, ModuleRes};
+
+struct Fixed;
+
+impl Module for Fixed {
+    fn get_output(&mut self) -> ModuleRes {
+        Ok(ModuleOutput::new("a".to_string()))
+    }
+}
+
+fn main() {
+    let plain = modules![Fixed, Fixed,];
+    let named = modules!["first" => Fixed, Fixed, "last" => Fixed,];
+    let empty = modules![];
+    assert_eq!((plain.len(), named.len(), empty.len()), (2, 3, 0));
+}